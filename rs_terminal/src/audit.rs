@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::AuditConfig;
+use crate::protocol::message::Participant;
+use crate::redaction::Redactor;
+use crate::session::repository::now_millis;
+use crate::session::share::ShareRole;
+
+// 单行输入的最大长度，超出部分丢弃
+const MAX_INPUT_LINE_LENGTH: usize = 4096;
+
+// 客户端使用的传输协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Http,
+    WebSocket,
+    WebTransport,
+    // 服务器内部操作，如会话过期和服务器关闭
+    System,
+}
+
+impl TransportKind {
+    // 名称，与审计日志和指标标签一致
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransportKind::Http => "http",
+            TransportKind::WebSocket => "websocket",
+            TransportKind::WebTransport => "webtransport",
+            TransportKind::System => "system",
+        }
+    }
+}
+
+// 发起操作的客户端
+#[derive(Debug, Clone)]
+pub struct ClientContext {
+    // 每个连接唯一，用于在线状态和控制权
+    pub client_id: String,
+    pub user_id: Option<String>,
+    pub remote_addr: Option<String>,
+    pub transport: TransportKind,
+    // 连接时出示的共享令牌
+    pub share_token: Option<String>,
+    // 客户端在会话中的角色，由共享令牌或会话所有者确定；None表示未授权，只能查看
+    pub role: Option<ShareRole>,
+}
+
+impl ClientContext {
+    pub fn new(transport: TransportKind, remote_addr: Option<String>, user_id: Option<String>) -> Self {
+        Self {
+            client_id: Uuid::new_v4().to_string(),
+            user_id,
+            remote_addr,
+            transport,
+            share_token: None,
+            role: None,
+        }
+    }
+    
+    // 在线状态中展示的客户端信息
+    pub fn participant(&self) -> Participant {
+        Participant {
+            client_id: self.client_id.clone(),
+            user_id: self.user_id.clone(),
+            transport: self.transport,
+            role: self.role,
+        }
+    }
+    
+    // 是否允许向终端输入，只读观看者和未授权客户端的输入被丢弃
+    pub fn can_write(&self) -> bool {
+        self.role.is_some_and(|role| role.can_write())
+    }
+
+    // 是否为会话所有者，共享、触发器的管理和关闭会话只允许所有者
+    pub fn is_owner(&self) -> bool {
+        self.role == Some(ShareRole::Owner)
+    }
+
+    // 服务器内部操作，拥有所有者权限
+    pub fn system() -> Self {
+        Self {
+            role: Some(ShareRole::Owner),
+            ..Self::new(TransportKind::System, None, None)
+        }
+    }
+
+    // 从HTTP请求（含WebSocket握手）中识别客户端：用户取自 ?userId= 或 X-User-Id 头，共享令牌取自 ?token= 或 X-Share-Token 头；
+    // 通过Unix域套接字接入时没有对端地址，使用反向代理设置的 X-Forwarded-For
+    pub fn from_request(transport: TransportKind, peer: Option<SocketAddr>, headers: &http::HeaderMap, query: Option<&str>) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let user_id = query.and_then(|query| query_value(query, "userId")).or_else(|| header("x-user-id"));
+        let remote_addr = match peer {
+            Some(peer) => Some(peer.ip().to_string()),
+            None => header("x-forwarded-for").and_then(|value| value.split(',').next().map(|addr| addr.trim().to_string())),
+        };
+        Self {
+            share_token: query.and_then(|query| query_value(query, "token")).or_else(|| header("x-share-token")),
+            ..Self::new(transport, remote_addr, user_id)
+        }
+    }
+}
+
+// 读取查询字符串中的参数
+pub fn query_value(query: &str, key: &str) -> Option<String> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value.to_string())
+}
+
+// 审计操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Create,
+    Attach,
+    Resize,
+    Interrupt,
+    Execute,
+    Input,
+    Terminate,
+    ShareCreate,
+    ShareRevoke,
+    TriggerAdd,
+    TriggerRemove,
+    Signal,
+}
+
+// 审计日志的一行
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditEntry<'a> {
+    timestamp: u64,
+    action: AuditAction,
+    session_id: &'a str,
+    user_id: Option<&'a str>,
+    remote_addr: Option<&'a str>,
+    transport: TransportKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a str>,
+}
+
+// 正在输入的命令行
+#[derive(Default)]
+struct InputLine {
+    text: String,
+    // 输入过程中终端关闭了回显（如输入密码）
+    hidden: bool,
+    // 正在跳过转义序列（方向键等）
+    escape: bool,
+}
+
+// 审计日志 - 只追加的JSON Lines文件，与log4rs调试日志分开
+pub struct AuditLog {
+    // 未启用时为None
+    file: Option<Mutex<File>>,
+    log_input: bool,
+    redactor: Redactor,
+    // 各客户端正在输入的命令行，键为(会话ID, 客户端ID)，多个客户端同时输入时互不混杂
+    input_lines: Mutex<HashMap<(String, String), InputLine>>,
+}
+
+impl AuditLog {
+    // 按配置打开审计日志文件
+    pub fn open(config: &AuditConfig, redactor: Redactor) -> anyhow::Result<Self> {
+        let file = if config.enabled {
+            let path = Path::new(&config.path);
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            log::info!("Writing audit log to {:?}", path);
+            Some(Mutex::new(file))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            file,
+            log_input: config.enabled && config.log_input,
+            redactor,
+            input_lines: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // 记录一次操作，detail经过敏感信息过滤
+    pub fn record(&self, action: AuditAction, session_id: &str, client: &ClientContext, detail: Option<&str>) {
+        let Some(file) = &self.file else { return };
+        let detail = detail.map(|detail| self.redactor.redact(detail));
+
+        let entry = AuditEntry {
+            timestamp: now_millis(),
+            action,
+            session_id,
+            user_id: client.user_id.as_deref(),
+            remote_addr: client.remote_addr.as_deref(),
+            transport: client.transport,
+            detail: detail.as_deref(),
+        };
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to encode audit entry: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // 每条记录立即落盘
+        let mut file = file.lock().unwrap();
+        if let Err(e) = file.write_all(&line).and_then(|_| file.flush()) {
+            log::error!("Failed to write audit entry: {}", e);
+        }
+    }
+
+    // 处理写入会话的输入：Ctrl+C记为中断，开启输入记录时按回车拆分出命令行；
+    // hidden表示终端正在读取不回显的输入（如密码），该行内容不记录
+    pub fn record_input(&self, session_id: &str, client: &ClientContext, data: &str, hidden: bool) {
+        if self.file.is_none() {
+            return;
+        }
+
+        // 先在锁内拆分出完整的行，再写日志
+        let mut events = Vec::new();
+        {
+            let mut input_lines = self.input_lines.lock().unwrap();
+            let key = (session_id.to_string(), client.client_id.clone());
+            let line = input_lines.entry(key.clone()).or_default();
+            // 新的一行按本次写入时的终端模式判断，行内任一部分为隐藏输入则整行不记录
+            if line.text.is_empty() {
+                line.hidden = hidden;
+            } else {
+                line.hidden |= hidden;
+            }
+
+            for c in data.chars() {
+                if line.escape {
+                    // 转义序列以字母或 ~ 结束
+                    line.escape = !(c.is_ascii_alphabetic() || c == '~');
+                    continue;
+                }
+                match c {
+                    '\r' | '\n' => {
+                        let finished = std::mem::take(line);
+                        if !finished.text.trim().is_empty() {
+                            events.push((AuditAction::Input, Some(finished)));
+                        }
+                        // 同一次写入中的后续内容同样不记录
+                        line.hidden = hidden;
+                    },
+                    '\x03' => {
+                        *line = InputLine::default();
+                        events.push((AuditAction::Interrupt, None));
+                    },
+                    // 退格
+                    '\x7f' | '\x08' => {
+                        line.text.pop();
+                    },
+                    // Ctrl+U 清空当前行
+                    '\x15' => line.text.clear(),
+                    '\x1b' => line.escape = true,
+                    '\t' => line.text.push(c),
+                    c if c.is_control() => {},
+                    c => {
+                        if line.text.len() < MAX_INPUT_LINE_LENGTH {
+                            line.text.push(c);
+                        }
+                    },
+                }
+            }
+            // 没有未完成的内容时不保留，REST请求每次都是新的客户端
+            if line.text.is_empty() && !line.escape {
+                input_lines.remove(&key);
+            }
+        }
+
+        for (action, line) in events {
+            match line {
+                Some(_) if !self.log_input => {},
+                Some(line) if line.hidden => self.record(action, session_id, client, Some("[hidden]")),
+                Some(line) => self.record(action, session_id, client, Some(&line.text)),
+                None => self.record(action, session_id, client, None),
+            }
+        }
+    }
+
+    // 客户端断开后丢弃其未完成的输入
+    pub fn forget_client(&self, session_id: &str, client_id: &str) {
+        self.input_lines.lock().unwrap().remove(&(session_id.to_string(), client_id.to_string()));
+    }
+
+    // 会话结束后丢弃所有客户端未完成的输入
+    pub fn forget_session(&self, session_id: &str) {
+        self.input_lines.lock().unwrap().retain(|(session, _), _| session != session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_lines_are_kept_per_client() {
+        let path = std::env::temp_dir().join(format!("rs_terminal-audit-{}.log", Uuid::new_v4()));
+        let config = AuditConfig { enabled: true, path: path.to_string_lossy().to_string(), log_input: true };
+        let audit = AuditLog::open(&config, Redactor::new(&[]).unwrap()).unwrap();
+        let alice = ClientContext::new(TransportKind::WebSocket, None, Some("alice".to_string()));
+        let bob = ClientContext::new(TransportKind::WebSocket, None, Some("bob".to_string()));
+
+        // 两个客户端交替输入同一会话
+        audit.record_input("s1", &alice, "ec", false);
+        audit.record_input("s1", &bob, "pw", false);
+        audit.record_input("s1", &alice, "ho hi\r", false);
+        audit.record_input("s1", &bob, "d\r", false);
+
+        // 断开的客户端未完成的输入被丢弃
+        audit.record_input("s1", &alice, "unfinished", false);
+        audit.forget_client("s1", &alice.client_id);
+        audit.record_input("s1", &alice, "ls\r", false);
+        assert!(audit.input_lines.lock().unwrap().is_empty());
+
+        let entries = std::fs::read_to_string(&path).unwrap().lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|entry| (entry["userId"].as_str().unwrap().to_string(), entry["detail"].as_str().unwrap().to_string()))
+            .collect::<Vec<_>>();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(entries, [
+            ("alice".to_string(), "echo hi".to_string()),
+            ("bob".to_string(), "pwd".to_string()),
+            ("alice".to_string(), "ls".to_string()),
+        ]);
+    }
+}
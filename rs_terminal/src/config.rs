@@ -2,13 +2,26 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
 
+use crate::session::sizing::SizePolicy;
+
 // 终端尺寸配置
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct TerminalSize {
     pub columns: u32,
     pub rows: u32,
 }
 
+// 终端大小上限，防止客户端请求过大的屏幕缓冲区；不超过PTY支持的u16范围
+pub const MAX_TERMINAL_COLUMNS: u32 = 1000;
+pub const MAX_TERMINAL_ROWS: u32 = 1000;
+
+impl TerminalSize {
+    // 行列数是否都在 1..=上限 范围内
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_TERMINAL_COLUMNS).contains(&self.columns) && (1..=MAX_TERMINAL_ROWS).contains(&self.rows)
+    }
+}
+
 // Shell配置
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ShellConfig {
@@ -26,12 +39,26 @@ pub struct TerminalConfig {
     pub default_working_directory: String,
     pub session_timeout: u64,
     pub shells: HashMap<String, ShellConfig>,
+    // 服务端屏幕模型保留的滚动历史行数
+    #[serde(default = "default_scrollback_lines")]
+    pub scrollback_lines: usize,
+}
+
+fn default_scrollback_lines() -> usize {
+    1000
 }
 
 // HTTP服务器配置
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct HttpConfig {
     pub port: u16,
+    // 监听地址，支持IPv4和IPv6
+    #[serde(default = "default_local_bind_address")]
+    pub bind_address: String,
+    // Unix域套接字路径，配置后改为监听该套接字而不是TCP端口
+    pub unix_socket_path: Option<String>,
+    // Unix域套接字文件权限（八进制，如 "660"）
+    pub unix_socket_mode: Option<String>,
     pub use_https: bool,
     pub cert_path: Option<String>,
     pub key_path: Option<String>,
@@ -40,13 +67,490 @@ pub struct HttpConfig {
 // WebSocket服务器配置
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct WebSocketConfig {
+    // 是否启动独立的WebSocket监听器（HTTP服务器的 /ws/{sessionId} 路由始终可用）
+    #[serde(default = "default_true")]
+    pub enabled: bool,
     pub port: u16,
+    // 监听地址，支持IPv4和IPv6
+    #[serde(default = "default_local_bind_address")]
+    pub bind_address: String,
+    // Unix域套接字路径，配置后改为监听该套接字而不是TCP端口
+    pub unix_socket_path: Option<String>,
+    // Unix域套接字文件权限（八进制，如 "660"）
+    pub unix_socket_mode: Option<String>,
+    // 是否使用WSS（TLS）
+    #[serde(default)]
+    pub use_tls: bool,
+    // TLS证书路径（PEM格式，仅当use_tls为true时需要）
+    pub cert_path: Option<String>,
+    // TLS私钥路径（PEM格式，仅当use_tls为true时需要）
+    pub key_path: Option<String>,
 }
 
 // WebTransport服务器配置
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct WebTransportConfig {
     pub port: u16,
+    // 监听地址，支持IPv4和IPv6
+    #[serde(default = "default_any_bind_address")]
+    pub bind_address: String,
+    // 是否使用不可靠数据报传输延迟敏感的控制消息（心跳）
+    #[serde(default)]
+    pub enable_datagrams: bool,
+    // TLS证书路径（PEM格式），未配置时使用自签名证书
+    pub cert_path: Option<String>,
+    // TLS私钥路径（PEM格式），未配置时使用自签名证书
+    pub key_path: Option<String>,
+}
+
+// 关闭配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ShutdownConfig {
+    // 通知客户端后等待其断开的宽限期（毫秒）
+    #[serde(default = "default_grace_period_ms")]
+    pub grace_period_ms: u64,
+    // 终止shell时每一级信号（SIGHUP → SIGTERM → SIGKILL）的等待时间（毫秒）
+    #[serde(default = "default_signal_timeout_ms")]
+    pub signal_timeout_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace_period_ms: default_grace_period_ms(),
+            signal_timeout_ms: default_signal_timeout_ms(),
+        }
+    }
+}
+
+// 健康检查配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HealthConfig {
+    // 必需的适配器（http、websocket、webtransport），全部开始监听后 /readyz 才返回就绪
+    #[serde(default = "default_required_adapters")]
+    pub required_adapters: Vec<String>,
+    // 必需的适配器启动失败（如端口被占用）时以非零状态码退出
+    #[serde(default)]
+    pub exit_on_failure: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            required_adapters: default_required_adapters(),
+            exit_on_failure: false,
+        }
+    }
+}
+
+fn default_required_adapters() -> Vec<String> {
+    vec!["http".to_string(), "websocket".to_string(), "webtransport".to_string()]
+}
+
+// 会话持久化配置（仅Unix）
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PersistenceConfig {
+    // 是否由独立的会话保持进程持有PTY，服务器升级或崩溃重启后重新接管shell
+    #[serde(default)]
+    pub enabled: bool,
+    // 状态目录，存放会话元数据和保持进程的Unix套接字
+    #[serde(default = "default_state_directory")]
+    pub state_directory: String,
+    // 保持进程缓存的最近输出字节数，服务器重新接管后回放给客户端
+    #[serde(default = "default_replay_buffer_size")]
+    pub replay_buffer_size: usize,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            state_directory: default_state_directory(),
+            replay_buffer_size: default_replay_buffer_size(),
+        }
+    }
+}
+
+// 会话录制配置（asciinema v2格式）
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RecordingConfig {
+    // 是否录制所有会话（创建会话时可通过record参数单独指定）
+    #[serde(default)]
+    pub enabled: bool,
+    // 需要录制的shell类型，如 ["bash"]
+    #[serde(default)]
+    pub shells: Vec<String>,
+    // 录制文件目录
+    #[serde(default = "default_recording_directory")]
+    pub directory: String,
+    // 是否同时录制输入（可能包含密码等敏感内容）
+    #[serde(default)]
+    pub record_input: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shells: Vec::new(),
+            directory: default_recording_directory(),
+            record_input: false,
+        }
+    }
+}
+
+fn default_recording_directory() -> String {
+    "recordings".to_string()
+}
+
+// 多人协作配置
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct CollaborationConfig {
+    // 默认开启单人操控模式，创建会话时可通过 singleDriver 参数单独指定
+    #[serde(default)]
+    pub single_driver: bool,
+    // 多个客户端窗口大小不同时的终端大小策略：smallest、largest、latest-active、owner-wins
+    #[serde(default)]
+    pub size_policy: SizePolicy,
+}
+
+// 触发器配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TriggersConfig {
+    // 按shell类型生效的触发器，在创建会话时加入
+    #[serde(default)]
+    pub rules: Vec<TriggerRule>,
+    // 通过REST接口添加的webhook触发器，URL的协议、主机和端口必须与其中之一完全一致，路径以其路径开头；为空时不允许通过接口添加webhook
+    #[serde(default)]
+    pub webhook_url_prefixes: Vec<String>,
+    // webhook请求超时（毫秒）
+    #[serde(default = "default_webhook_timeout_ms")]
+    pub webhook_timeout_ms: u64,
+}
+
+impl Default for TriggersConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            webhook_url_prefixes: Vec::new(),
+            webhook_timeout_ms: default_webhook_timeout_ms(),
+        }
+    }
+}
+
+fn default_webhook_timeout_ms() -> u64 {
+    5000
+}
+
+// 触发条件
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum TriggerEvent {
+    // 去掉转义序列后的输出行匹配正则表达式
+    Output { pattern: String },
+    // shell进程退出
+    Exit,
+    // 指定秒数内没有输入和输出
+    Idle { seconds: u64 },
+}
+
+// 触发后执行的动作
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum TriggerAction {
+    // 向URL发送POST请求，请求体为JSON
+    Webhook { url: String },
+    // 向会话的所有客户端发送控制事件
+    Control,
+    // 自动向终端输入
+    Input { data: String },
+}
+
+// 触发器
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TriggerRule {
+    // 名称，在会话内唯一
+    pub name: String,
+    // 生效的shell类型，为空时对所有shell生效；通过REST接口添加时忽略
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shells: Vec<String>,
+    pub on: TriggerEvent,
+    pub action: TriggerAction,
+    // 两次触发的最小间隔（秒），避免输出触发器重复触发；自动输入的触发器必须大于0
+    #[serde(default)]
+    pub cooldown: u64,
+}
+
+// shell集成配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ShellIntegrationConfig {
+    // 是否为所有会话注入shell集成脚本（创建会话时可通过shellIntegration参数单独指定）
+    #[serde(default)]
+    pub enabled: bool,
+    // 需要注入的shell类型，如 ["bash"]
+    #[serde(default)]
+    pub shells: Vec<String>,
+    // 集成脚本目录，启动shell前写入
+    #[serde(default = "default_shell_integration_directory")]
+    pub directory: String,
+}
+
+impl Default for ShellIntegrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shells: Vec::new(),
+            directory: default_shell_integration_directory(),
+        }
+    }
+}
+
+fn default_shell_integration_directory() -> String {
+    "state/shell-integration".to_string()
+}
+
+// 命令历史配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HistoryConfig {
+    // 是否按用户持久化shell集成识别的命令，可跨会话搜索，会话终止或服务器重启后仍可查询
+    #[serde(default)]
+    pub persist: bool,
+    // 命令历史文件（JSON Lines）
+    #[serde(default = "default_history_path")]
+    pub path: String,
+    // 每个用户保留的命令数量，超出时丢弃最早的命令
+    #[serde(default = "default_history_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            persist: false,
+            path: default_history_path(),
+            max_entries: default_history_max_entries(),
+        }
+    }
+}
+
+fn default_history_path() -> String {
+    "state/history.jsonl".to_string()
+}
+
+fn default_history_max_entries() -> usize {
+    10000
+}
+
+// 会话共享配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SharingConfig {
+    // 未指定有效期时的默认有效期（秒）
+    #[serde(default = "default_share_expires_in")]
+    pub default_expires_in: u64,
+    // 允许的最长有效期（秒）
+    #[serde(default = "default_share_max_expires_in")]
+    pub max_expires_in: u64,
+}
+
+impl Default for SharingConfig {
+    fn default() -> Self {
+        Self {
+            default_expires_in: default_share_expires_in(),
+            max_expires_in: default_share_max_expires_in(),
+        }
+    }
+}
+
+fn default_share_expires_in() -> u64 {
+    3600
+}
+
+fn default_share_max_expires_in() -> u64 {
+    7 * 24 * 3600
+}
+
+// 敏感信息过滤规则
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RedactionRule {
+    // 正则表达式
+    pub pattern: String,
+    // 替换文本，支持 $1 等分组引用
+    #[serde(default = "default_redaction_replacement")]
+    pub replacement: String,
+}
+
+fn default_redaction_replacement() -> String {
+    "***".to_string()
+}
+
+// 敏感信息过滤配置
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RedactionConfig {
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+}
+
+// 终端内容（输入和输出）的日志策略
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentLogging {
+    // 不记录终端内容
+    Off,
+    // 只记录长度
+    #[default]
+    MetadataOnly,
+    // 记录经过敏感信息过滤的完整内容，需同时开启诊断模式
+    Full,
+}
+
+// 日志配置
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub content: ContentLogging,
+    // 诊断模式，仅用于排查问题
+    #[serde(default)]
+    pub diagnostic: bool,
+}
+
+// 链路追踪导出方式
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TracingExporter {
+    // 通过OTLP/HTTP导出到采集器
+    #[default]
+    Otlp,
+    // 写入本地JSON Lines文件，用于离线测试
+    File,
+}
+
+// 链路追踪配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TracingConfig {
+    // 是否开启链路追踪
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub exporter: TracingExporter,
+    // OTLP/HTTP采集地址
+    #[serde(default = "default_otlp_endpoint")]
+    pub otlp_endpoint: String,
+    // 文件导出路径
+    #[serde(default = "default_tracing_file_path")]
+    pub file_path: String,
+    #[serde(default = "default_tracing_service_name")]
+    pub service_name: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            exporter: TracingExporter::default(),
+            otlp_endpoint: default_otlp_endpoint(),
+            file_path: default_tracing_file_path(),
+            service_name: default_tracing_service_name(),
+        }
+    }
+}
+
+fn default_otlp_endpoint() -> String {
+    "http://localhost:4318/v1/traces".to_string()
+}
+
+fn default_tracing_file_path() -> String {
+    "logs/traces.jsonl".to_string()
+}
+
+fn default_tracing_service_name() -> String {
+    "rs_terminal".to_string()
+}
+
+// 审计日志配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuditConfig {
+    // 是否写入审计日志
+    #[serde(default)]
+    pub enabled: bool,
+    // 审计日志文件（JSON Lines，只追加）
+    #[serde(default = "default_audit_path")]
+    pub path: String,
+    // 是否记录输入的命令行（经过敏感信息过滤，关闭回显时输入的内容不记录）
+    #[serde(default)]
+    pub log_input: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_audit_path(),
+            log_input: false,
+        }
+    }
+}
+
+fn default_audit_path() -> String {
+    "logs/audit.jsonl".to_string()
+}
+
+// 会话记录存储配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RegistryConfig {
+    // 存储后端：memory（仅内存）或 file（JSON Lines文件，重启后保留）
+    #[serde(default = "default_registry_backend")]
+    pub backend: String,
+    // file后端的存储文件路径
+    #[serde(default = "default_registry_path")]
+    pub path: String,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            backend: default_registry_backend(),
+            path: default_registry_path(),
+        }
+    }
+}
+
+fn default_registry_backend() -> String {
+    "memory".to_string()
+}
+
+fn default_registry_path() -> String {
+    "state/sessions.jsonl".to_string()
+}
+
+fn default_state_directory() -> String {
+    "state/sessions".to_string()
+}
+
+fn default_replay_buffer_size() -> usize {
+    64 * 1024
+}
+
+fn default_grace_period_ms() -> u64 {
+    5000
+}
+
+fn default_signal_timeout_ms() -> u64 {
+    2000
+}
+
+// 默认启用
+fn default_true() -> bool {
+    true
+}
+
+// 默认监听地址 - 仅本机访问
+fn default_local_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+// 默认监听地址 - 所有网络接口
+fn default_any_bind_address() -> String {
+    "0.0.0.0".to_string()
 }
 
 // 主配置结构
@@ -56,6 +560,34 @@ pub struct Config {
     pub http: HttpConfig,
     pub websocket: WebSocketConfig,
     pub webtransport: WebTransportConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub persistence: PersistenceConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub recording: RecordingConfig,
+    #[serde(default)]
+    pub sharing: SharingConfig,
+    #[serde(default)]
+    pub collaboration: CollaborationConfig,
+    #[serde(default)]
+    pub triggers: TriggersConfig,
+    #[serde(default)]
+    pub shell_integration: ShellIntegrationConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub tracing: TracingConfig,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
 }
 
 impl Config {
@@ -121,3 +653,18 @@ impl Config {
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_size_bounds() {
+        assert!(TerminalSize { columns: 1, rows: 1 }.is_valid());
+        assert!(TerminalSize { columns: MAX_TERMINAL_COLUMNS, rows: MAX_TERMINAL_ROWS }.is_valid());
+        assert!(!TerminalSize { columns: 0, rows: 24 }.is_valid());
+        assert!(!TerminalSize { columns: 80, rows: 0 }.is_valid());
+        assert!(!TerminalSize { columns: MAX_TERMINAL_COLUMNS + 1, rows: 24 }.is_valid());
+        assert!(!TerminalSize { columns: 80, rows: u32::MAX }.is_valid());
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::config::{ContentLogging, LoggingConfig};
+use crate::protocol::message::{ClientMessage, ServerMessage};
+use crate::redaction::Redactor;
+
+// 终端内容日志策略，启动时初始化一次；未初始化时只记录长度
+static CONTENT_LOG: OnceLock<ContentLog> = OnceLock::new();
+
+struct ContentLog {
+    policy: ContentLogging,
+    redactor: Redactor,
+}
+
+// 按配置初始化终端内容日志策略，完整内容只在诊断模式下记录
+pub fn init(config: &LoggingConfig, redactor: Redactor) {
+    let policy = match config.content {
+        ContentLogging::Full if !config.diagnostic => {
+            log::warn!("Full content logging requires diagnostic mode, logging metadata only");
+            ContentLogging::MetadataOnly
+        },
+        ContentLogging::Full => {
+            log::warn!("Diagnostic mode enabled, terminal content will be written to the log");
+            ContentLogging::Full
+        },
+        policy => policy,
+    };
+    let _ = CONTENT_LOG.set(ContentLog { policy, redactor });
+}
+
+// 日志中的终端内容
+pub enum Payload {
+    Length(usize),
+    Content(String),
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Length(length) => write!(f, "<{} bytes>", length),
+            Payload::Content(content) => f.write_str(content),
+        }
+    }
+}
+
+// 按策略描述终端内容，策略为off时返回None，调用方不应输出该日志
+pub fn payload(data: &str) -> Option<Payload> {
+    let (policy, redactor) = match CONTENT_LOG.get() {
+        Some(content_log) => (content_log.policy, Some(&content_log.redactor)),
+        None => (ContentLogging::MetadataOnly, None),
+    };
+    match (policy, redactor) {
+        (ContentLogging::Off, _) => None,
+        (ContentLogging::Full, Some(redactor)) => Some(Payload::Content(format!("{:?}", redactor.redact(data)))),
+        _ => Some(Payload::Length(data.len())),
+    }
+}
+
+// 描述服务端消息，只有终端输出受内容策略限制
+pub fn server_message(message: &ServerMessage) -> Option<Payload> {
+    match message {
+        ServerMessage::Output { data } => payload(data),
+        other => Some(Payload::Content(format!("{:?}", other))),
+    }
+}
+
+// 描述客户端消息，只有终端输入受内容策略限制
+pub fn client_message(message: &ClientMessage) -> Option<Payload> {
+    match message {
+        ClientMessage::Input { data } => payload(data),
+        other => Some(Payload::Content(format!("{:?}", other))),
+    }
+}
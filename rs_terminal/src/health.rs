@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+// 适配器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdapterState {
+    // 正在启动，尚未完成监听
+    Starting,
+    // 已开始监听
+    Ready,
+    // 启动失败或运行中出错退出
+    Failed,
+    // 已正常停止
+    Stopped,
+}
+
+// 单个适配器的健康状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdapterHealth {
+    pub state: AdapterState,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// 服务器整体健康状态
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthState {
+    pub adapters: BTreeMap<String, AdapterHealth>,
+    // 正在关闭，不再接受新连接
+    pub draining: bool,
+}
+
+impl HealthState {
+    // 存活：没有必需的适配器失败
+    pub fn is_healthy(&self) -> bool {
+        self.failed_required_adapter().is_none()
+    }
+
+    // 就绪：所有必需的适配器都已开始监听，且未在关闭
+    pub fn is_ready(&self) -> bool {
+        !self.draining && self.adapters.values()
+            .filter(|adapter| adapter.required)
+            .all(|adapter| adapter.state == AdapterState::Ready)
+    }
+
+    // 第一个失败的必需适配器
+    pub fn failed_required_adapter(&self) -> Option<(&str, &AdapterHealth)> {
+        self.adapters.iter()
+            .find(|(_, adapter)| adapter.required && adapter.state == AdapterState::Failed)
+            .map(|(name, adapter)| (name.as_str(), adapter))
+    }
+}
+
+// 健康状态登记 - 各适配器上报监听状态，供 /healthz、/readyz 查询
+#[derive(Clone)]
+pub struct HealthRegistry {
+    sender: watch::Sender<HealthState>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(HealthState::default());
+        Self { sender }
+    }
+
+    // 登记适配器，初始状态为启动中
+    pub fn register(&self, name: &str, required: bool) {
+        self.sender.send_modify(|state| {
+            state.adapters.insert(name.to_string(), AdapterHealth {
+                state: AdapterState::Starting,
+                required,
+                error: None,
+            });
+        });
+    }
+
+    // 适配器已开始监听
+    pub fn set_ready(&self, name: &str) {
+        self.set_state(name, AdapterState::Ready, None);
+    }
+
+    // 适配器启动失败或出错退出
+    pub fn set_failed(&self, name: &str, error: &anyhow::Error) {
+        self.set_state(name, AdapterState::Failed, Some(error.to_string()));
+    }
+
+    // 适配器已正常停止
+    pub fn set_stopped(&self, name: &str) {
+        self.set_state(name, AdapterState::Stopped, None);
+    }
+
+    // 开始关闭，之后不再就绪
+    pub fn set_draining(&self) {
+        self.sender.send_modify(|state| state.draining = true);
+    }
+
+    fn set_state(&self, name: &str, adapter_state: AdapterState, error: Option<String>) {
+        self.sender.send_modify(|state| {
+            if let Some(adapter) = state.adapters.get_mut(name) {
+                adapter.state = adapter_state;
+                adapter.error = error;
+            }
+        });
+    }
+
+    // 当前状态快照
+    pub fn snapshot(&self) -> HealthState {
+        self.sender.borrow().clone()
+    }
+
+    // 等待任一必需的适配器失败，返回其名称和错误
+    pub async fn wait_for_required_failure(&self) -> (String, String) {
+        let mut receiver = self.sender.subscribe();
+        // 发送者由self持有，wait_for不会因通道关闭而返回错误
+        if let Ok(state) = receiver.wait_for(|state| !state.is_healthy()).await
+            && let Some((name, adapter)) = state.failed_required_adapter() {
+            return (name.to_string(), adapter.error.clone().unwrap_or_default());
+        }
+        std::future::pending().await
+    }
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,19 +2,50 @@
 use std::sync::Arc;
 use std::fs;
 
+mod audit;
 mod config;
+mod content_log;
+mod health;
+mod metrics;
 mod pty;
 mod protocol;
+mod redaction;
 mod session;
+mod shutdown;
+mod telemetry;
 mod transport;
 
+use crate::audit::AuditLog;
 use crate::config::Config;
+use crate::health::HealthRegistry;
+use crate::metrics::Metrics;
 use crate::protocol::adapter::ProtocolAdapterFactory;
+use crate::protocol::message::{ControlEvent, ServerMessage};
+use crate::session::history::HistoryStore;
 use crate::session::session::SessionManager;
+use crate::session::trigger;
 use crate::pty::terminal_service::TerminalService;
+use crate::redaction::Redactor;
+use crate::shutdown::ShutdownSignal;
+use crate::transport::tls::CertificateDigest;
+use crate::transport::webtransport::webtransport::WebTransportAdapter;
+use crate::transport::websocket::websocket::WebSocketAdapter;
+use crate::transport::http::http_server;
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
+    // 会话保持进程模式，由服务器以 `--session-holder <状态目录> <会话ID>` 启动
+    #[cfg(unix)]
+    if let Some((state_directory, session_id)) = pty::holder::holder_arguments() {
+        return pty::holder::run(state_directory, session_id);
+    }
+    
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run_server())
+}
+
+async fn run_server() -> anyhow::Result<()> {
     // 确保日志目录存在
     fs::create_dir_all("logs")?;
     
@@ -26,51 +57,154 @@ async fn main() -> anyhow::Result<()> {
     log::info!("Configuration loaded successfully");
     log::debug!("Default shell type: {}", config.terminal.default_shell_type);
     log::debug!("Available shells: {:?}", config.terminal.shells.keys());
-    log::debug!("HTTP port: {}", config.http.port);
-    log::debug!("WebSocket port: {}", config.websocket.port);
-    log::debug!("WebTransport port: {}", config.webtransport.port);
+    log::debug!("HTTP bind: {}:{} (unix socket: {:?})", config.http.bind_address, config.http.port, config.http.unix_socket_path);
+    log::debug!("WebSocket bind: {}:{} (unix socket: {:?})", config.websocket.bind_address, config.websocket.port, config.websocket.unix_socket_path);
+    log::debug!("WebTransport bind: {}:{}", config.webtransport.bind_address, config.webtransport.port);
+    
+    // 打开会话记录存储
+    let session_repository = crate::session::repository::open(&config.registry)?;
+    
+    // 敏感信息过滤规则无效时拒绝启动
+    let redactor = Redactor::new(&config.redaction.rules)?;
+    content_log::init(&config.logging, redactor.clone());
+    
+    // 触发器配置无效时拒绝启动
+    trigger::validate(&config.triggers.rules)?;
+    
+    // 链路追踪导出器无法创建时拒绝启动
+    let telemetry = telemetry::init(&config.tracing)?;
+    
+    // 打开审计日志
+    let audit_log = Arc::new(AuditLog::open(&config.audit, redactor.clone())?);
+    
+    // 打开命令历史
+    let history = Arc::new(HistoryStore::open(&config.history, redactor)?);
     
     // 创建会话管理器
-    let session_manager = Arc::new(SessionManager::new(config.clone()));
+    let metrics = Arc::new(Metrics::new()?);
+    let session_manager = Arc::new(SessionManager::new(config.clone(), session_repository, audit_log, metrics, history));
     
     // 创建终端服务
     let terminal_service = Arc::new(TerminalService::new(session_manager.clone()));
     
+    // 关闭信号，触发后各服务器停止接受新连接
+    let shutdown = ShutdownSignal::new();
+    let mut server_tasks = Vec::new();
+    
+    // WebTransport证书摘要，由WebTransport适配器更新，HTTP服务器对外提供
+    let certificate_digest = CertificateDigest::default();
+    
+    // 各适配器的监听状态，HTTP服务器通过 /healthz、/readyz 对外提供
+    let health = HealthRegistry::new();
+    let is_required = |name: &str| config.health.required_adapters.iter().any(|required| required == name);
+    
     // 启动HTTP服务器
+    health.register(http_server::ADAPTER_NAME, is_required(http_server::ADAPTER_NAME));
     let http_session_manager = session_manager.clone();
+    let http_terminal_service = terminal_service.clone();
     let http_config = config.clone();
-    tokio::spawn(async move {
-        if let Err(e) = crate::transport::http::http_server::start_server(http_session_manager, http_config).await {
-            log::error!("HTTP server error: {}", e);
+    let http_certificate_digest = certificate_digest.clone();
+    let http_health = health.clone();
+    let http_shutdown = shutdown.clone();
+    server_tasks.push(tokio::spawn(async move {
+        match http_server::start_server(http_session_manager, http_terminal_service, http_config, http_certificate_digest, http_health.clone(), http_shutdown).await {
+            Ok(()) => http_health.set_stopped(http_server::ADAPTER_NAME),
+            Err(e) => {
+                log::error!("HTTP server error: {}", e);
+                http_health.set_failed(http_server::ADAPTER_NAME, &e);
+            }
         }
-    });
+    }));
     
-    // 创建并启动WebSocket适配器
-    let websocket_adapter = ProtocolAdapterFactory::create_websocket_adapter(terminal_service.clone(), config.clone());
-    tokio::spawn(async move {
-        if let Err(e) = websocket_adapter.start().await {
-            log::error!("WebSocket server error: {}", e);
-        }
-    });
+    // 创建并启动独立的WebSocket适配器（可选，HTTP服务器的 /ws/{sessionId} 路由始终可用）
+    if config.websocket.enabled {
+        health.register(WebSocketAdapter::NAME, is_required(WebSocketAdapter::NAME));
+        let websocket_adapter = ProtocolAdapterFactory::create_websocket_adapter(terminal_service.clone(), config.clone(), health.clone(), shutdown.clone());
+        let websocket_health = health.clone();
+        server_tasks.push(tokio::spawn(async move {
+            match websocket_adapter.start().await {
+                Ok(()) => websocket_health.set_stopped(WebSocketAdapter::NAME),
+                Err(e) => {
+                    log::error!("WebSocket server error: {}", e);
+                    websocket_health.set_failed(WebSocketAdapter::NAME, &e);
+                }
+            }
+        }));
+    } else {
+        log::info!("Standalone WebSocket server disabled, WebSocket is served on the HTTP port");
+    }
     
     // 创建并启动WebTransport适配器
-    let webtransport_adapter = ProtocolAdapterFactory::create_webtransport_adapter(terminal_service.clone(), config.clone());
-    tokio::spawn(async move {
-        if let Err(e) = webtransport_adapter.start().await {
-            log::error!("WebTransport server error: {}", e);
+    health.register(WebTransportAdapter::NAME, is_required(WebTransportAdapter::NAME));
+    let webtransport_adapter = ProtocolAdapterFactory::create_webtransport_adapter(terminal_service.clone(), config.clone(), certificate_digest.clone(), health.clone(), shutdown.clone());
+    let webtransport_health = health.clone();
+    server_tasks.push(tokio::spawn(async move {
+        match webtransport_adapter.start().await {
+            Ok(()) => webtransport_health.set_stopped(WebTransportAdapter::NAME),
+            Err(e) => {
+                log::error!("WebTransport server error: {}", e);
+                webtransport_health.set_failed(WebTransportAdapter::NAME, &e);
+            }
         }
-    });
+    }));
     
     log::info!(
-        "Servers starting: HTTP on http://localhost:{}, WebSocket on ws://localhost:{}, WebTransport on https://localhost:{}",
+        "Servers starting: HTTP port {}, WebSocket port {}, WebTransport port {}",
         config.http.port,
         config.websocket.port,
         config.webtransport.port
     );
     
-    // 保持主线程运行
-    tokio::signal::ctrl_c().await?;
+    // 等待SIGINT/SIGTERM；配置了exit_on_failure时，必需的适配器启动失败也会触发关闭
+    let mut adapter_failure = None;
+    tokio::select! {
+        signal_name = shutdown::wait_for_termination_signal() => {
+            log::info!("Received {}, shutting down gracefully", signal_name?);
+        },
+        (name, error) = health.wait_for_required_failure(), if config.health.exit_on_failure => {
+            log::error!("Required adapter {} failed: {}, shutting down", name, error);
+            adapter_failure = Some((name, error));
+        },
+    }
+    
+    // 1. 停止接受新连接
+    health.set_draining();
+    shutdown.trigger();
+    
+    // 2. 通知客户端服务器即将关闭，并给予宽限期
+    let grace_period_ms = config.shutdown.grace_period_ms;
+    if session_manager.session_count() > 0 {
+        session_manager.broadcast_to_all(ServerMessage::Control(ControlEvent::ServerShutdown { grace_period_ms }));
+        log::info!("Waiting {}ms for {} sessions to drain", grace_period_ms, session_manager.session_count());
+        tokio::time::sleep(std::time::Duration::from_millis(grace_period_ms)).await;
+    }
+    
+    // 3. 依次以SIGHUP、SIGTERM、SIGKILL终止所有shell，客户端连接随会话关闭；
+    //    启用持久化时shell由保持进程继续持有，下次启动时重新接管
+    if session_manager.is_persistent() {
+        log::info!("Leaving {} sessions to their holder processes", session_manager.session_count());
+    } else {
+        session_manager.close_all_sessions().await;
+    }
+    
+    // 留出少量时间让连接任务发送关闭帧
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    
+    // 4. 等待服务器完成收尾，避免无限等待
+    let _ = tokio::time::timeout(
+        std::time::Duration::from_secs(2),
+        futures_util::future::join_all(server_tasks),
+    ).await;
+    
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
+    log::info!("Shutdown complete");
     
+    // 必需的适配器失败时以非零状态码退出，交由进程管理器处理
+    if let Some((name, error)) = adapter_failure {
+        anyhow::bail!("Required adapter {} failed: {}", name, error);
+    }
     Ok(())
 }
 
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+
+use crate::audit::TransportKind;
+
+// 指标名称前缀
+const NAMESPACE: &str = "rs_terminal";
+
+// Prometheus指标 - 由SessionManager、TerminalService和各协议适配器更新，/metrics 端点导出
+pub struct Metrics {
+    registry: Registry,
+    sessions_active: IntGaugeVec,
+    sessions_terminated: IntCounterVec,
+    session_lifetime: HistogramVec,
+    spawn_failures: IntCounterVec,
+    sessions_expired: IntCounter,
+    clients_attached: IntGaugeVec,
+    bytes_received: IntCounterVec,
+    bytes_sent: IntCounterVec,
+    pty_read_latency: Histogram,
+    output_chunks_dropped: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> anyhow::Result<Self> {
+        let registry = Registry::new_custom(Some(NAMESPACE.to_string()), None)?;
+
+        let sessions_active = IntGaugeVec::new(
+            Opts::new("sessions_active", "Active sessions by shell type"),
+            &["shell_type"],
+        )?;
+        let sessions_terminated = IntCounterVec::new(
+            Opts::new("sessions_terminated_total", "Terminated sessions by shell type and reason"),
+            &["shell_type", "reason"],
+        )?;
+        let session_lifetime = HistogramVec::new(
+            HistogramOpts::new("session_lifetime_seconds", "Session lifetime from creation to termination")
+                .buckets(vec![1.0, 10.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 4.0 * 3600.0, 12.0 * 3600.0, 24.0 * 3600.0]),
+            &["shell_type"],
+        )?;
+        let spawn_failures = IntCounterVec::new(
+            Opts::new("spawn_failures_total", "Failed attempts to spawn a shell by shell type"),
+            &["shell_type"],
+        )?;
+        let sessions_expired = IntCounter::new("sessions_expired_total", "Sessions closed because they expired")?;
+        let clients_attached = IntGaugeVec::new(
+            Opts::new("clients_attached", "Attached clients by transport"),
+            &["transport"],
+        )?;
+        let bytes_received = IntCounterVec::new(
+            Opts::new("bytes_received_total", "Terminal input bytes received from clients by transport"),
+            &["transport"],
+        )?;
+        let bytes_sent = IntCounterVec::new(
+            Opts::new("bytes_sent_total", "Terminal output bytes sent to clients by transport"),
+            &["transport"],
+        )?;
+        let pty_read_latency = Histogram::with_opts(
+            HistogramOpts::new("pty_read_latency_seconds", "Time from reading PTY output to dispatching it to clients")
+                .buckets(vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]),
+        )?;
+        let output_chunks_dropped = IntCounter::new(
+            "output_chunks_dropped_total",
+            "Output chunks dropped because a client channel was full; the client is disconnected",
+        )?;
+
+        registry.register(Box::new(sessions_active.clone()))?;
+        registry.register(Box::new(sessions_terminated.clone()))?;
+        registry.register(Box::new(session_lifetime.clone()))?;
+        registry.register(Box::new(spawn_failures.clone()))?;
+        registry.register(Box::new(sessions_expired.clone()))?;
+        registry.register(Box::new(clients_attached.clone()))?;
+        registry.register(Box::new(bytes_received.clone()))?;
+        registry.register(Box::new(bytes_sent.clone()))?;
+        registry.register(Box::new(pty_read_latency.clone()))?;
+        registry.register(Box::new(output_chunks_dropped.clone()))?;
+
+        Ok(Self {
+            registry,
+            sessions_active,
+            sessions_terminated,
+            session_lifetime,
+            spawn_failures,
+            sessions_expired,
+            clients_attached,
+            bytes_received,
+            bytes_sent,
+            pty_read_latency,
+            output_chunks_dropped,
+        })
+    }
+
+    // 以当前活动会话重新设置活动会话数，导出前调用
+    pub fn set_active_sessions<'a>(&self, shell_types: impl Iterator<Item = &'a str>) {
+        self.sessions_active.reset();
+        for shell_type in shell_types {
+            self.sessions_active.with_label_values(&[shell_type]).inc();
+        }
+    }
+
+    // 会话终止，lifetime为会话从创建到终止的时长
+    pub fn session_terminated(&self, shell_type: &str, reason: &str, lifetime: Duration) {
+        self.sessions_terminated.with_label_values(&[shell_type, reason]).inc();
+        self.session_lifetime.with_label_values(&[shell_type]).observe(lifetime.as_secs_f64());
+    }
+
+    pub fn spawn_failed(&self, shell_type: &str) {
+        self.spawn_failures.with_label_values(&[shell_type]).inc();
+    }
+
+    pub fn session_expired(&self) {
+        self.sessions_expired.inc();
+    }
+
+    // 客户端连接，返回的守卫释放时计为断开
+    pub fn client_attached(&self, transport: TransportKind) -> AttachedClient {
+        let gauge = self.clients_attached.with_label_values(&[transport.as_str()]);
+        gauge.inc();
+        AttachedClient(gauge)
+    }
+
+    pub fn bytes_received(&self, transport: TransportKind, bytes: usize) {
+        self.bytes_received.with_label_values(&[transport.as_str()]).inc_by(bytes as u64);
+    }
+
+    pub fn bytes_sent(&self, transport: TransportKind, bytes: usize) {
+        self.bytes_sent.with_label_values(&[transport.as_str()]).inc_by(bytes as u64);
+    }
+
+    pub fn pty_read(&self, latency: Duration) {
+        self.pty_read_latency.observe(latency.as_secs_f64());
+    }
+
+    pub fn output_chunk_dropped(&self) {
+        self.output_chunks_dropped.inc();
+    }
+
+    // 以Prometheus文本格式导出所有指标
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+// 已连接客户端的计数守卫
+pub struct AttachedClient(IntGauge);
+
+impl Drop for AttachedClient {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
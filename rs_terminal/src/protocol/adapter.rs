@@ -3,7 +3,10 @@ use std::future::Future;
 use std::pin::Pin;
 
 use crate::config::Config;
+use crate::health::HealthRegistry;
 use crate::pty::terminal_service::TerminalService;
+use crate::shutdown::ShutdownSignal;
+use crate::transport::tls::CertificateDigest;
 
 // 协议适配器接口 - 定义通讯协议的通用接口
 pub trait ProtocolAdapter {
@@ -18,20 +21,25 @@ impl ProtocolAdapterFactory {
     // 创建WebSocket适配器
     pub fn create_websocket_adapter(
         terminal_service: Arc<TerminalService>,
-        config: Arc<Config>
+        config: Arc<Config>,
+        health: HealthRegistry,
+        shutdown: ShutdownSignal
     ) -> Arc<dyn ProtocolAdapter + Send + Sync> {
         Arc::new(
-            crate::transport::websocket::websocket::WebSocketAdapter::new(terminal_service, config)
+            crate::transport::websocket::websocket::WebSocketAdapter::new(terminal_service, config, health, shutdown)
         )
     }
     
     // 创建WebTransport适配器
     pub fn create_webtransport_adapter(
         terminal_service: Arc<TerminalService>,
-        config: Arc<Config>
+        config: Arc<Config>,
+        certificate_digest: CertificateDigest,
+        health: HealthRegistry,
+        shutdown: ShutdownSignal
     ) -> Arc<dyn ProtocolAdapter + Send + Sync> {
         Arc::new(
-            crate::transport::webtransport::webtransport::WebTransportAdapter::new(terminal_service, config)
+            crate::transport::webtransport::webtransport::WebTransportAdapter::new(terminal_service, config, certificate_digest, health, shutdown)
         )
     }
 }
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{query_value, TransportKind};
+use crate::session::share::ShareRole;
+use crate::session::shell_integration::CommandInfo;
+use crate::session::trigger::TriggerCause;
+
+// 单个帧的最大长度（字节），防止恶意客户端声明超大帧耗尽内存
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+// 单条输出消息的最大长度（字节），JSON转义后最多膨胀6倍，编码后仍小于MAX_FRAME_SIZE
+pub const MAX_OUTPUT_CHUNK: usize = MAX_FRAME_SIZE / 8;
+
+// 客户端 → 服务端消息
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ClientMessage {
+    // 终端输入
+    Input { data: String },
+    // 调整终端大小
+    Resize { columns: u32, rows: u32 },
+    // 心跳检测
+    Ping { timestamp: Option<u64> },
+    // 回放：暂停
+    Pause,
+    // 回放：继续
+    Resume,
+    // 回放：跳转到指定位置（毫秒）
+    Seek { position_ms: u64 },
+    // 回放：设置播放倍速
+    Speed { speed: f64 },
+    // 单人操控模式：请求控制权，无人操控时立即获得
+    RequestControl,
+    // 单人操控模式：直接取得控制权
+    TakeControl,
+    // 单人操控模式：交出控制权，优先交给最早请求的客户端
+    ReleaseControl,
+    // 单人操控模式：将控制权交给指定客户端
+    GrantControl { client_id: String },
+}
+
+// 服务端 → 客户端消息
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerMessage {
+    // 终端输出
+    Output { data: String },
+    // 心跳响应
+    Pong { timestamp: Option<u64> },
+    // 错误信息
+    Error { message: String },
+    // 控制事件
+    Control(ControlEvent),
+}
+
+// 服务端控制事件
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ControlEvent {
+    // 服务器即将关闭，客户端应在宽限期内保存状态并断开
+    ServerShutdown { grace_period_ms: u64 },
+    // 终端大小变化，客户端应调整本地终端
+    Resize { columns: u32, rows: u32 },
+    // 回放状态，在开始、暂停、继续、跳转和变速后发送
+    ReplayStatus { position_ms: u64, duration_ms: u64, paused: bool, speed: f64 },
+    // 回放到达录制末尾，仍可跳转重新播放
+    ReplayFinished,
+    // 连接后发送给该客户端：自己的客户端ID、会话中的所有客户端和当前操控者
+    Participants { client_id: String, participants: Vec<Participant>, driver: Option<String>, single_driver: bool },
+    // 有客户端加入会话
+    ParticipantJoined { participant: Participant },
+    // 有客户端离开会话
+    ParticipantLeft { participant: Participant },
+    // 有客户端请求控制权
+    ControlRequested { participant: Participant },
+    // 操控者变化，None表示无人操控
+    ControlChanged { driver: Option<Participant> },
+    // 会话的触发器被触发
+    Trigger { name: String, cause: TriggerCause },
+    // shell上报的当前目录变化（OSC 7）
+    DirectoryChanged { directory: String },
+    // shell开始执行命令（OSC 133）
+    CommandStarted { command: CommandInfo },
+    // 命令执行结束，包含结束时间和退出码
+    CommandFinished { command: CommandInfo },
+}
+
+// 连接到会话的客户端
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Participant {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    pub transport: TransportKind,
+    // 共享令牌的角色，会话所有者为owner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<ShareRole>,
+}
+
+impl Participant {
+    // 是否可以输入，只读观看者和未确定角色的客户端不能取得控制权
+    pub fn can_write(&self) -> bool {
+        self.role.is_some_and(|role| role.can_write())
+    }
+}
+
+impl ClientMessage {
+    // 解析JSON字节（WebTransport帧和数据报）
+    pub fn from_json_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    // 是否为延迟敏感的控制消息，适合通过不可靠数据报发送；调整大小依赖先后顺序，只能走流
+    pub fn is_latency_sensitive(&self) -> bool {
+        matches!(self, ClientMessage::Ping { .. })
+    }
+}
+
+impl ServerMessage {
+    // 编码为JSON字节
+    pub fn to_json_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    // 是否为延迟敏感的控制消息，适合通过不可靠数据报发送
+    pub fn is_latency_sensitive(&self) -> bool {
+        matches!(self, ServerMessage::Pong { .. })
+    }
+
+    // 将大段输出（重绘、回放跳转）拆分为多条输出消息，每条不超过MAX_OUTPUT_CHUNK字节，在字符边界处拆分
+    pub fn output_chunks(data: &str) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let mut end = rest.len().min(MAX_OUTPUT_CHUNK);
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (chunk, tail) = rest.split_at(end);
+            messages.push(ServerMessage::Output { data: chunk.to_string() });
+            rest = tail;
+        }
+        messages
+    }
+}
+
+// WebSocket文本协议 - 默认双向均为类型化JSON消息；旧客户端通过 ?protocol=raw 显式协商原始模式，
+// 此时文本消息即终端输入，只发送原始终端输出
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextProtocol {
+    Typed,
+    Raw,
+}
+
+impl TextProtocol {
+    // 从连接URL的查询参数中确定协议
+    pub fn from_query(query: Option<&str>) -> Self {
+        match query.and_then(|query| query_value(query, "protocol")).as_deref() {
+            Some("raw") => TextProtocol::Raw,
+            _ => TextProtocol::Typed,
+        }
+    }
+
+    // 解析客户端文本消息
+    pub fn parse(&self, text: &str) -> anyhow::Result<ClientMessage> {
+        match self {
+            TextProtocol::Typed => Ok(serde_json::from_str(text)?),
+            TextProtocol::Raw => Ok(ClientMessage::Input { data: text.to_string() }),
+        }
+    }
+
+    // 编码服务端消息，原始模式下不发送终端输出以外的消息
+    pub fn encode(&self, message: &ServerMessage) -> Option<String> {
+        match (self, message) {
+            (TextProtocol::Typed, message) => serde_json::to_string(message).ok(),
+            (TextProtocol::Raw, ServerMessage::Output { data }) => Some(data.clone()),
+            (TextProtocol::Raw, _) => None,
+        }
+    }
+}
+
+// 编码长度前缀帧：4字节大端长度 + JSON消息体
+pub fn encode_frame(message: &ServerMessage) -> anyhow::Result<Vec<u8>> {
+    let payload = message.to_json_bytes()?;
+    if payload.len() > MAX_FRAME_SIZE {
+        anyhow::bail!("Frame too large: {} bytes", payload.len());
+    }
+
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+// 解析长度前缀帧头，返回消息体长度
+pub fn decode_frame_length(header: [u8; 4]) -> anyhow::Result<usize> {
+    let length = u32::from_be_bytes(header) as usize;
+    if length > MAX_FRAME_SIZE {
+        anyhow::bail!("Frame too large: {} bytes", length);
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_data(messages: &[ServerMessage]) -> Vec<&str> {
+        messages.iter().map(|message| match message {
+            ServerMessage::Output { data } => data.as_str(),
+            other => panic!("expected output, got {:?}", other),
+        }).collect()
+    }
+
+    #[test]
+    fn output_chunks_split_at_char_boundaries() {
+        assert!(ServerMessage::output_chunks("").is_empty());
+        assert_eq!(chunk_data(&ServerMessage::output_chunks("short")), ["short"]);
+
+        // 两字节字符从奇数偏移开始，拆分点落在字符中间
+        let data = format!("a{}", "é".repeat(MAX_OUTPUT_CHUNK));
+        let messages = ServerMessage::output_chunks(&data);
+        let chunks = chunk_data(&messages);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty() && chunk.len() <= MAX_OUTPUT_CHUNK));
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn output_chunks_fit_in_a_frame() {
+        // 控制字符JSON转义后膨胀最多
+        let data = "\u{1}".repeat(MAX_OUTPUT_CHUNK * 3);
+        for message in ServerMessage::output_chunks(&data) {
+            assert!(encode_frame(&message).is_ok());
+        }
+        assert!(encode_frame(&ServerMessage::Output { data }).is_err());
+    }
+
+    #[test]
+    fn frame_length() {
+        let frame = encode_frame(&ServerMessage::Output { data: "hi".to_string() }).unwrap();
+        let length = decode_frame_length(frame[..4].try_into().unwrap()).unwrap();
+        assert_eq!(length, frame.len() - 4);
+        assert!(decode_frame_length((MAX_FRAME_SIZE as u32 + 1).to_be_bytes()).is_err());
+    }
+
+    #[test]
+    fn datagram_messages() {
+        assert!(ClientMessage::Ping { timestamp: Some(1) }.is_latency_sensitive());
+        // 数据报可能乱序到达，旧的大小会覆盖新的大小
+        assert!(!ClientMessage::Resize { columns: 80, rows: 24 }.is_latency_sensitive());
+        assert!(!ClientMessage::Input { data: "ls\n".to_string() }.is_latency_sensitive());
+    }
+
+    #[test]
+    fn text_protocols() {
+        assert_eq!(TextProtocol::from_query(None), TextProtocol::Typed);
+        assert_eq!(TextProtocol::from_query(Some("userId=alice")), TextProtocol::Typed);
+        assert_eq!(TextProtocol::from_query(Some("userId=alice&protocol=raw")), TextProtocol::Raw);
+
+        let typed = TextProtocol::Typed;
+        assert!(matches!(
+            typed.parse(r#"{"type":"resize","columns":100,"rows":30}"#).unwrap(),
+            ClientMessage::Resize { columns: 100, rows: 30 }
+        ));
+        // 类型化模式不再把无法解析的文本当作输入
+        assert!(typed.parse("ls\n").is_err());
+        assert!(typed.parse("{not json").is_err());
+        let output = ServerMessage::Output { data: "hi".to_string() };
+        assert_eq!(typed.encode(&output).unwrap(), r#"{"type":"output","data":"hi"}"#);
+
+        let raw = TextProtocol::Raw;
+        assert!(matches!(raw.parse(r#"{"type":"ping"}"#).unwrap(), ClientMessage::Input { data } if data == r#"{"type":"ping"}"#));
+        assert_eq!(raw.encode(&output).unwrap(), "hi");
+        assert!(raw.encode(&ServerMessage::Pong { timestamp: None }).is_none());
+    }
+}
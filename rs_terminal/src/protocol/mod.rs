@@ -1,2 +1,3 @@
 // Protocol模块 - 处理协议适配器和协议相关逻辑
 pub mod adapter;
+pub mod message;
@@ -0,0 +1,617 @@
+// 会话保持进程 - 类似dtach/abduco，每个会话一个独立进程持有PTY主端和shell，
+// 服务器通过状态目录中的Unix套接字与其通信，服务器升级或崩溃重启后可重新接管shell
+use std::collections::VecDeque;
+use std::io::{BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use portable_pty::{MasterPty, PtyPair, PtySize};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::config::ShellConfig;
+use crate::protocol::message::MAX_FRAME_SIZE;
+use crate::pty::terminal::{build_command, open_pty, take_utf8, OutputChunk};
+
+// 启动保持进程的命令行参数：`rs_terminal --session-holder <状态目录> <会话ID>`
+pub const HOLDER_ARGUMENT: &str = "--session-holder";
+
+// 等待保持进程创建套接字的最长时间
+const HOLDER_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 服务器与保持进程之间写入的超时时间，对端卡住时报错而不是一直阻塞
+const HOLDER_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// shell退出后等待剩余输出读完的最长时间
+const HOLDER_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+// 帧类型
+const FRAME_INPUT: u8 = 1;
+const FRAME_RESIZE: u8 = 2;
+const FRAME_TERMINATE: u8 = 3;
+const FRAME_OUTPUT: u8 = 16;
+const FRAME_EXITED: u8 = 17;
+
+// 服务器与保持进程之间的帧：1字节类型 + 4字节大端长度 + 消息体
+#[derive(Debug, Clone)]
+pub enum HolderFrame {
+    // 服务器 → 保持进程：终端输入
+    Input(Vec<u8>),
+    // 服务器 → 保持进程：调整终端大小
+    Resize { columns: u16, rows: u16 },
+    // 服务器 → 保持进程：依次以SIGHUP、SIGTERM、SIGKILL终止shell
+    Terminate { signal_timeout_ms: u64 },
+    // 保持进程 → 服务器：终端输出
+    Output(Vec<u8>),
+    // 保持进程 → 服务器：shell已退出
+    Exited { exit_code: u32 },
+}
+
+// 写入一帧
+fn write_frame(writer: &mut impl Write, frame: &HolderFrame) -> std::io::Result<()> {
+    let (kind, payload) = match frame {
+        HolderFrame::Input(data) => (FRAME_INPUT, data.clone()),
+        HolderFrame::Resize { columns, rows } => {
+            let mut payload = columns.to_be_bytes().to_vec();
+            payload.extend_from_slice(&rows.to_be_bytes());
+            (FRAME_RESIZE, payload)
+        },
+        HolderFrame::Terminate { signal_timeout_ms } => (FRAME_TERMINATE, signal_timeout_ms.to_be_bytes().to_vec()),
+        HolderFrame::Output(data) => (FRAME_OUTPUT, data.clone()),
+        HolderFrame::Exited { exit_code } => (FRAME_EXITED, exit_code.to_be_bytes().to_vec()),
+    };
+
+    let mut buffer = Vec::with_capacity(5 + payload.len());
+    buffer.push(kind);
+    buffer.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(&payload);
+    writer.write_all(&buffer)
+}
+
+// 写入终端输入，超过帧长度上限的输入拆分为多帧，保持进程按顺序写入终端
+fn write_input(writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    for chunk in data.chunks(MAX_FRAME_SIZE) {
+        write_frame(writer, &HolderFrame::Input(chunk.to_vec()))?;
+    }
+    Ok(())
+}
+
+// 读取一帧，对端关闭时返回None
+fn read_frame(reader: &mut impl Read) -> std::io::Result<Option<HolderFrame>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Holder frame too large: {} bytes", length)));
+    }
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload)?;
+
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid holder frame of type {}", header[0]));
+    let frame = match header[0] {
+        FRAME_INPUT => HolderFrame::Input(payload),
+        FRAME_RESIZE => {
+            let bytes: [u8; 4] = payload.try_into().map_err(|_| invalid())?;
+            HolderFrame::Resize {
+                columns: u16::from_be_bytes([bytes[0], bytes[1]]),
+                rows: u16::from_be_bytes([bytes[2], bytes[3]]),
+            }
+        },
+        FRAME_TERMINATE => {
+            let bytes: [u8; 8] = payload.try_into().map_err(|_| invalid())?;
+            HolderFrame::Terminate { signal_timeout_ms: u64::from_be_bytes(bytes) }
+        },
+        FRAME_OUTPUT => HolderFrame::Output(payload),
+        FRAME_EXITED => {
+            let bytes: [u8; 4] = payload.try_into().map_err(|_| invalid())?;
+            HolderFrame::Exited { exit_code: u32::from_be_bytes(bytes) }
+        },
+        _ => return Err(invalid()),
+    };
+    Ok(Some(frame))
+}
+
+// 保持进程启动参数，由服务器写入状态目录，保持进程读取后删除
+#[derive(Debug, Serialize, Deserialize)]
+struct HolderSpec {
+    shell_config: ShellConfig,
+    replay_buffer_size: usize,
+}
+
+// 会话元数据，由保持进程写入状态目录，服务器启动时据此重新发现会话
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HolderMetadata {
+    pub session_id: String,
+    pub command: Vec<String>,
+    pub holder_pid: u32,
+    pub shell_pid: Option<u32>,
+    pub created_at: u64,
+}
+
+// 会话在状态目录中的文件
+struct HolderPaths {
+    spec: PathBuf,
+    metadata: PathBuf,
+    socket: PathBuf,
+}
+
+impl HolderPaths {
+    fn new(state_directory: &Path, session_id: &str) -> Self {
+        Self {
+            spec: state_directory.join(format!("{}.spec.json", session_id)),
+            metadata: state_directory.join(format!("{}.json", session_id)),
+            socket: state_directory.join(format!("{}.sock", session_id)),
+        }
+    }
+
+    // 删除会话的所有文件
+    fn remove_all(&self) {
+        for path in [&self.spec, &self.metadata, &self.socket] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// 解析保持进程模式的命令行参数
+pub fn holder_arguments() -> Option<(PathBuf, String)> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() != Some(HOLDER_ARGUMENT) {
+        return None;
+    }
+    Some((PathBuf::from(args.next()?), args.next()?))
+}
+
+// 保持进程的输出状态 - 当前服务器连接和断开期间缓存的输出
+struct HolderOutput {
+    connection: Option<Arc<UnixStream>>,
+    backlog: VecDeque<u8>,
+    backlog_limit: usize,
+}
+
+impl HolderOutput {
+    // 缓存输出，返回需要转发的当前连接；转发在锁外进行，服务器读取缓慢时不阻塞接管新连接
+    fn buffer(&mut self, data: &[u8]) -> Option<Arc<UnixStream>> {
+        self.backlog.extend(data);
+        let excess = self.backlog.len().saturating_sub(self.backlog_limit);
+        self.backlog.drain(..excess);
+        self.connection.clone()
+    }
+
+    // 转发失败的连接仍是当前连接时断开
+    fn detach(&mut self, connection: &Arc<UnixStream>) {
+        if self.connection.as_ref().is_some_and(|current| Arc::ptr_eq(current, connection)) {
+            self.connection = None;
+        }
+    }
+
+    // 接管新的服务器连接，先回放缓存的输出；持锁写入，保证缓存的输出先于之后的输出到达
+    fn attach(&mut self, mut connection: UnixStream) {
+        if let Some(previous) = self.connection.take() {
+            let _ = previous.shutdown(std::net::Shutdown::Both);
+        }
+
+        let backlog: Vec<u8> = self.backlog.iter().copied().collect();
+        if !backlog.is_empty() && write_frame(&mut connection, &HolderFrame::Output(backlog)).is_err() {
+            return;
+        }
+        self.connection = Some(Arc::new(connection));
+    }
+}
+
+// 保持进程入口 - 启动shell并持有PTY，直到shell退出
+pub fn run(state_directory: PathBuf, session_id: String) -> anyhow::Result<()> {
+    let paths = HolderPaths::new(&state_directory, &session_id);
+    let spec: HolderSpec = serde_json::from_slice(&std::fs::read(&paths.spec)?)?;
+    let _ = std::fs::remove_file(&paths.spec);
+
+    // 启动shell后关闭从端，shell退出时主端读取才能结束
+    let PtyPair { master, slave } = open_pty(&spec.shell_config)?;
+    let mut child = slave.spawn_command(build_command(&spec.shell_config))?;
+    drop(slave);
+    let shell_pid = child.process_id();
+
+    let mut reader = master.try_clone_reader()?;
+    let writer: Arc<Mutex<Box<dyn Write + Send>>> = Arc::new(Mutex::new(master.take_writer()?));
+    let master: Arc<Mutex<Box<dyn MasterPty + Send>>> = Arc::new(Mutex::new(master));
+
+    if std::fs::symlink_metadata(&paths.socket).is_ok() {
+        std::fs::remove_file(&paths.socket)?;
+    }
+    let listener = UnixListener::bind(&paths.socket)?;
+    std::fs::set_permissions(&paths.socket, std::fs::Permissions::from_mode(0o600))?;
+
+    let metadata = HolderMetadata {
+        session_id: session_id.clone(),
+        command: spec.shell_config.command.clone(),
+        holder_pid: std::process::id(),
+        shell_pid,
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64,
+    };
+    let temporary_path = paths.metadata.with_extension("json.tmp");
+    std::fs::write(&temporary_path, serde_json::to_vec_pretty(&metadata)?)?;
+    std::fs::rename(&temporary_path, &paths.metadata)?;
+
+    let output = Arc::new(Mutex::new(HolderOutput {
+        connection: None,
+        backlog: VecDeque::new(),
+        backlog_limit: spec.replay_buffer_size.min(MAX_FRAME_SIZE),
+    }));
+    let exited = Arc::new(AtomicBool::new(false));
+
+    // PTY读取线程 - 输出写入缓存并转发给服务器
+    let (drained_tx, drained_rx) = std::sync::mpsc::channel::<()>();
+    let reader_output = output.clone();
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let connection = reader_output.lock().unwrap().buffer(&buffer[..n]);
+                    if let Some(connection) = connection
+                        && write_frame(&mut connection.as_ref(), &HolderFrame::Output(buffer[..n].to_vec())).is_err() {
+                        reader_output.lock().unwrap().detach(&connection);
+                    }
+                },
+            }
+        }
+        let _ = drained_tx.send(());
+    });
+
+    // 接受服务器连接 - 同一时间只保留最新的连接
+    let accept_output = output.clone();
+    let accept_exited = exited.clone();
+    std::thread::spawn(move || {
+        for connection in listener.incoming() {
+            let Ok(connection) = connection else { continue };
+            let Ok(command_stream) = connection.try_clone() else { continue };
+            let _ = connection.set_write_timeout(Some(HOLDER_WRITE_TIMEOUT));
+            accept_output.lock().unwrap().attach(connection);
+
+            let (writer, master, exited) = (writer.clone(), master.clone(), accept_exited.clone());
+            std::thread::spawn(move || handle_commands(command_stream, writer, master, exited, shell_pid));
+        }
+    });
+
+    // 等待shell退出
+    let exit_code = child.wait().map(|status| status.exit_code()).unwrap_or(1);
+    exited.store(true, Ordering::SeqCst);
+    let _ = drained_rx.recv_timeout(HOLDER_DRAIN_TIMEOUT);
+
+    // 通知服务器后清理状态目录
+    let connection = output.lock().unwrap().connection.clone();
+    if let Some(connection) = connection {
+        let _ = write_frame(&mut connection.as_ref(), &HolderFrame::Exited { exit_code });
+    }
+    paths.remove_all();
+    Ok(())
+}
+
+// 处理服务器发来的命令，连接断开时返回（shell继续运行）
+fn handle_commands(
+    stream: UnixStream,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
+    exited: Arc<AtomicBool>,
+    shell_pid: Option<u32>,
+) {
+    let mut reader = BufReader::new(stream);
+    while let Ok(Some(frame)) = read_frame(&mut reader) {
+        match frame {
+            HolderFrame::Input(data) => {
+                let mut writer = writer.lock().unwrap();
+                if writer.write_all(&data).and_then(|_| writer.flush()).is_err() {
+                    break;
+                }
+            },
+            HolderFrame::Resize { columns, rows } => {
+                let _ = master.lock().unwrap().resize(PtySize {
+                    rows,
+                    cols: columns,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                });
+            },
+            HolderFrame::Terminate { signal_timeout_ms } => {
+                let Some(pid) = shell_pid else { continue };
+                let exited = exited.clone();
+                std::thread::spawn(move || terminate_shell(pid, Duration::from_millis(signal_timeout_ms), &exited));
+            },
+            HolderFrame::Output(_) | HolderFrame::Exited { .. } => {},
+        }
+    }
+}
+
+// 依次发送SIGHUP、SIGTERM、SIGKILL，每一级等待signal_timeout
+fn terminate_shell(pid: u32, signal_timeout: Duration, exited: &AtomicBool) {
+    for signal in [libc::SIGHUP, libc::SIGTERM, libc::SIGKILL] {
+        if exited.load(Ordering::SeqCst) {
+            return;
+        }
+        // SAFETY: kill只向指定pid发送信号，不涉及内存访问
+        unsafe { libc::kill(pid as libc::pid_t, signal) };
+
+        let deadline = std::time::Instant::now() + signal_timeout;
+        while std::time::Instant::now() < deadline && !exited.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+// 服务器侧的保持进程连接
+pub struct HolderConnection {
+    // 写入在阻塞线程池中进行，不占用异步运行时的工作线程
+    stream: Arc<Mutex<UnixStream>>,
+    shell_pid: Option<u32>,
+    exited: Arc<AtomicBool>,
+    exit_code: Arc<Mutex<Option<u32>>>,
+}
+
+impl HolderConnection {
+    // 连接保持进程，返回连接和终端输出接收端
+    fn connect(socket_path: &Path, shell_pid: Option<u32>) -> anyhow::Result<(Self, mpsc::Receiver<OutputChunk>)> {
+        let stream = UnixStream::connect(socket_path)?;
+        // 保持进程卡住时写入超时失败，避免阻塞线程池被长期占用
+        stream.set_write_timeout(Some(HOLDER_WRITE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let exited = Arc::new(AtomicBool::new(false));
+        let exit_code = Arc::new(Mutex::new(None));
+        let (output_tx, output_rx) = mpsc::channel::<OutputChunk>(64);
+
+        let (reader_exited, reader_exit_code) = (exited.clone(), exit_code.clone());
+        std::thread::Builder::new()
+            .name("holder-reader".to_string())
+            .spawn(move || {
+                // 上一帧末尾不完整的UTF-8字节
+                let mut pending: Vec<u8> = Vec::new();
+                loop {
+                    match read_frame(&mut reader) {
+                        Ok(Some(HolderFrame::Output(data))) => {
+                            pending.extend_from_slice(&data);
+                            let output = take_utf8(&mut pending);
+                            if !output.is_empty() && output_tx.blocking_send(OutputChunk::new(output)).is_err() {
+                                break;
+                            }
+                        },
+                        Ok(Some(HolderFrame::Exited { exit_code })) => {
+                            log::debug!("Held shell exited with code {}", exit_code);
+                            *reader_exit_code.lock().unwrap() = Some(exit_code);
+                            break;
+                        },
+                        Ok(Some(_)) => {},
+                        Ok(None) => break,
+                        Err(e) => {
+                            log::debug!("Holder connection stopped: {}", e);
+                            break;
+                        }
+                    }
+                }
+                // 保持进程退出或连接断开都视为终端已结束
+                reader_exited.store(true, Ordering::SeqCst);
+            })?;
+
+        Ok((Self { stream: Arc::new(Mutex::new(stream)), shell_pid, exited, exit_code }, output_rx))
+    }
+
+    // 向保持进程发送一帧
+    pub async fn send(&self, frame: HolderFrame) -> anyhow::Result<()> {
+        let stream = self.stream.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut stream = stream.lock().unwrap();
+            match &frame {
+                HolderFrame::Input(data) => write_input(&mut *stream, data),
+                frame => write_frame(&mut *stream, frame),
+            }
+        }).await??;
+        Ok(())
+    }
+
+    // 保持进程启动的shell进程ID
+    pub fn shell_pid(&self) -> Option<u32> {
+        self.shell_pid
+    }
+
+    // shell是否已退出
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::SeqCst)
+    }
+
+    // shell的退出码，保持进程异常退出时为None
+    pub fn exit_code(&self) -> Option<u32> {
+        *self.exit_code.lock().unwrap()
+    }
+}
+
+// 启动保持进程并连接
+pub async fn spawn(
+    state_directory: &Path,
+    session_id: &str,
+    shell_config: &ShellConfig,
+    replay_buffer_size: usize,
+) -> anyhow::Result<(HolderConnection, mpsc::Receiver<OutputChunk>)> {
+    let spec = HolderSpec {
+        shell_config: shell_config.clone(),
+        replay_buffer_size,
+    };
+    // 创建状态目录、写入启动参数和启动进程都是阻塞操作，在阻塞线程池中进行
+    let (state_directory, session_id) = (state_directory.to_path_buf(), session_id.to_string());
+    let (paths, holder_pid) = tokio::task::spawn_blocking(move || start_holder(&state_directory, &session_id, &spec)).await??;
+
+    // 等待元数据写入（套接字已就绪）后连接
+    let deadline = tokio::time::Instant::now() + HOLDER_START_TIMEOUT;
+    loop {
+        if tokio::fs::try_exists(&paths.metadata).await.unwrap_or(false) {
+            let metadata: HolderMetadata = serde_json::from_slice(&tokio::fs::read(&paths.metadata).await?)?;
+            return tokio::task::spawn_blocking(move || HolderConnection::connect(&paths.socket, metadata.shell_pid)).await?;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            tokio::task::spawn_blocking(move || paths.remove_all()).await?;
+            anyhow::bail!("Session holder {} did not start within {:?}", holder_pid, HOLDER_START_TIMEOUT);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+// 写入启动参数并启动保持进程，返回会话文件路径和保持进程ID
+fn start_holder(state_directory: &Path, session_id: &str, spec: &HolderSpec) -> anyhow::Result<(HolderPaths, u32)> {
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(state_directory)?;
+    let state_directory = state_directory.canonicalize()?;
+    let paths = HolderPaths::new(&state_directory, session_id);
+
+    // 启动参数包含shell的环境变量，只允许服务器用户读取；删除残留文件后新建，确保权限生效
+    let _ = std::fs::remove_file(&paths.spec);
+    let mut spec_file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&paths.spec)?;
+    spec_file.write_all(&serde_json::to_vec(spec)?)?;
+    drop(spec_file);
+
+    // 保持进程脱离服务器的会话和进程组，服务器退出或收到信号时不受影响
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg(HOLDER_ARGUMENT)
+        .arg(&state_directory)
+        .arg(session_id)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // SAFETY: setsid是异步信号安全的，可以在fork之后、exec之前调用
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut holder = command.spawn()?;
+    let holder_pid = holder.id();
+
+    // 回收保持进程，避免服务器运行期间留下僵尸进程
+    std::thread::spawn(move || {
+        let _ = holder.wait();
+    });
+    Ok((paths, holder_pid))
+}
+
+// 扫描状态目录，重新连接仍在运行的保持进程，清理失效的会话文件
+pub fn discover(state_directory: &Path) -> Vec<(HolderMetadata, HolderConnection, mpsc::Receiver<OutputChunk>)> {
+    let entries = match std::fs::read_dir(state_directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut sessions = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+        let Some(session_id) = file_name.strip_suffix(".json") else { continue };
+        if session_id.ends_with(".spec") {
+            continue;
+        }
+
+        let paths = HolderPaths::new(state_directory, session_id);
+        let metadata = std::fs::read(&paths.metadata)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<HolderMetadata>(&bytes)?));
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log::warn!("Removing unreadable session metadata {:?}: {}", paths.metadata, e);
+                paths.remove_all();
+                continue;
+            }
+        };
+
+        match HolderConnection::connect(&paths.socket, metadata.shell_pid) {
+            Ok((connection, output_rx)) => sessions.push((metadata, connection, output_rx)),
+            Err(e) => {
+                log::warn!("Session holder for {} is gone ({}), removing its state", session_id, e);
+                paths.remove_all();
+            }
+        }
+    }
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(frame: HolderFrame) -> HolderFrame {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &frame).unwrap();
+        let mut reader = buffer.as_slice();
+        let decoded = read_frame(&mut reader).unwrap().unwrap();
+        assert!(reader.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        assert!(matches!(round_trip(HolderFrame::Input(b"ls\n".to_vec())), HolderFrame::Input(data) if data == b"ls\n"));
+        assert!(matches!(round_trip(HolderFrame::Output(Vec::new())), HolderFrame::Output(data) if data.is_empty()));
+        assert!(matches!(
+            round_trip(HolderFrame::Resize { columns: 300, rows: 70 }),
+            HolderFrame::Resize { columns: 300, rows: 70 }
+        ));
+        assert!(matches!(
+            round_trip(HolderFrame::Terminate { signal_timeout_ms: 5000 }),
+            HolderFrame::Terminate { signal_timeout_ms: 5000 }
+        ));
+        assert!(matches!(round_trip(HolderFrame::Exited { exit_code: 130 }), HolderFrame::Exited { exit_code: 130 }));
+    }
+
+    #[test]
+    fn frame_layout() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &HolderFrame::Resize { columns: 80, rows: 24 }).unwrap();
+        assert_eq!(buffer, [FRAME_RESIZE, 0, 0, 0, 4, 0, 80, 0, 24]);
+    }
+
+    #[test]
+    fn large_input_is_split() {
+        let data = (0..MAX_FRAME_SIZE * 2 + 10).map(|i| i as u8).collect::<Vec<_>>();
+        let mut buffer = Vec::new();
+        write_input(&mut buffer, &data).unwrap();
+
+        let mut reader = buffer.as_slice();
+        let mut received = Vec::new();
+        let mut frames = 0;
+        while let Some(frame) = read_frame(&mut reader).unwrap() {
+            let HolderFrame::Input(chunk) = frame else { panic!("expected input frame") };
+            received.extend_from_slice(&chunk);
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+        assert_eq!(received, data);
+    }
+
+    #[test]
+    fn end_of_stream() {
+        assert!(read_frame(&mut [].as_slice()).unwrap().is_none());
+        // 消息体不完整
+        assert!(read_frame(&mut [FRAME_INPUT, 0, 0, 0, 4, b'a'].as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        let too_large = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let header = [FRAME_OUTPUT, too_large[0], too_large[1], too_large[2], too_large[3]];
+        assert_eq!(read_frame(&mut header.as_slice()).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(read_frame(&mut [99, 0, 0, 0, 0].as_slice()).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(read_frame(&mut [FRAME_RESIZE, 0, 0, 0, 2, 0, 80].as_slice()).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
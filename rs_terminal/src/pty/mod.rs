@@ -1,3 +1,6 @@
 // PTY模块 - 处理终端进程和PTY交互
+pub mod process;
 pub mod terminal;
 pub mod terminal_service;
+#[cfg(unix)]
+pub mod holder;
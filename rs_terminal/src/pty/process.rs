@@ -0,0 +1,163 @@
+// 终端进程的信号和前台进程 - 信号直接发送给shell或PTY的前台进程组，
+// 进程信息读取自 /proc，其他平台上只返回进程ID
+use serde::Serialize;
+
+// 可以发送给会话的信号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+    Kill,
+    Hangup,
+    Stop,
+    Continue,
+    WindowChange,
+}
+
+impl Signal {
+    // 解析信号名，忽略大小写，可省略SIG前缀
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "INT" => Some(Signal::Interrupt),
+            "TERM" => Some(Signal::Terminate),
+            "KILL" => Some(Signal::Kill),
+            "HUP" => Some(Signal::Hangup),
+            "TSTP" => Some(Signal::Stop),
+            "CONT" => Some(Signal::Continue),
+            "WINCH" => Some(Signal::WindowChange),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Interrupt => "SIGINT",
+            Signal::Terminate => "SIGTERM",
+            Signal::Kill => "SIGKILL",
+            Signal::Hangup => "SIGHUP",
+            Signal::Stop => "SIGTSTP",
+            Signal::Continue => "SIGCONT",
+            Signal::WindowChange => "SIGWINCH",
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn number(&self) -> libc::c_int {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Hangup => libc::SIGHUP,
+            Signal::Stop => libc::SIGTSTP,
+            Signal::Continue => libc::SIGCONT,
+            Signal::WindowChange => libc::SIGWINCH,
+        }
+    }
+}
+
+// 信号的接收者
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalTarget {
+    // PTY的前台进程组（正在运行的命令，没有命令时为shell）
+    Foreground,
+    // shell进程
+    Shell,
+}
+
+impl SignalTarget {
+    pub fn parse(target: &str) -> Option<Self> {
+        match target.to_ascii_lowercase().as_str() {
+            "foreground" => Some(SignalTarget::Foreground),
+            "shell" => Some(SignalTarget::Shell),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignalTarget::Foreground => "foreground",
+            SignalTarget::Shell => "shell",
+        }
+    }
+}
+
+// 向进程（pid为正）或进程组（pid为负）发送信号
+#[cfg(unix)]
+pub fn kill(pid: i32, signal: Signal) -> anyhow::Result<()> {
+    // SAFETY: kill只向指定进程发送信号，不涉及内存访问
+    if unsafe { libc::kill(pid as libc::pid_t, signal.number()) } != 0 {
+        anyhow::bail!("Failed to send {} to {}: {}", signal.name(), pid, std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// 前台进程
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForegroundProcess {
+    // 进程组组长，组长已退出时为组内进程ID最小的进程
+    pub pid: u32,
+    pub process_group: u32,
+    // 进程名（/proc/<pid>/comm），无法读取时为None
+    pub name: Option<String>,
+    // 命令行参数，无法读取时为空
+    pub cmdline: Vec<String>,
+    // 前台是否为shell本身，即没有正在运行的命令
+    pub is_shell: bool,
+}
+
+impl ForegroundProcess {
+    pub fn describe(process_group: u32, shell_pid: Option<u32>) -> Self {
+        let pid = if std::path::Path::new(&format!("/proc/{}", process_group)).exists() {
+            process_group
+        } else {
+            group_members(process_group).into_iter().min().unwrap_or(process_group)
+        };
+        let name = std::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .ok()
+            .map(|comm| comm.trim_end().to_string());
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))
+            .map(|bytes| {
+                bytes.split(|byte| *byte == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            pid,
+            process_group,
+            name,
+            cmdline,
+            is_shell: shell_pid == Some(process_group),
+        }
+    }
+}
+
+// /proc/<pid>/stat 中进程名之后的字段：state ppid pgrp session tty_nr tpgid ...
+fn stat_fields(pid: u32) -> Option<Vec<String>> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // 进程名可能包含空格和括号，从最后一个右括号之后开始解析
+    let (_, rest) = stat.rsplit_once(')')?;
+    Some(rest.split_whitespace().map(str::to_string).collect())
+}
+
+// 进程所在终端的前台进程组，用于无法直接访问PTY主端的会话保持进程
+pub fn terminal_foreground_group(pid: u32) -> Option<u32> {
+    let tpgid: i64 = stat_fields(pid)?.get(5)?.parse().ok()?;
+    u32::try_from(tpgid).ok().filter(|tpgid| *tpgid > 0)
+}
+
+// 进程组内的所有进程
+fn group_members(process_group: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else { return Vec::new() };
+    entries.flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            stat_fields(*pid)
+                .and_then(|fields| fields.get(2)?.parse::<u32>().ok())
+                .is_some_and(|pgrp| pgrp == process_group)
+        })
+        .collect()
+}
@@ -1,8 +1,14 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
 use std::io::{Read, Write};
 
 use crate::config::ShellConfig;
+use crate::pty::process::{Signal, SignalTarget};
+#[cfg(unix)]
+use crate::pty::process;
+#[cfg(unix)]
+use crate::pty::holder::{self, HolderConnection, HolderFrame};
 
 // 使用portable-pty的统一API
 use portable_pty::{CommandBuilder, PtySize, PtyPair, native_pty_system};
@@ -10,148 +16,434 @@ use portable_pty::{CommandBuilder, PtySize, PtyPair, native_pty_system};
 // 终端进程 - 使用portable-pty的统一API
 #[derive(Clone)]
 pub struct TerminalProcess {
-    // 异步读取器
-    reader: Arc<Mutex<Box<dyn Read + Send>>>,
-    // 异步写入器
-    writer: Arc<Mutex<Box<dyn Write + Send>>>,
-    // 子进程，用于检查是否运行
-    child: Arc<Mutex<Box<dyn portable_pty::Child + Send + Sync>>>,
-    // PTY对，用于调整大小
-    pty_pair: Arc<Mutex<PtyPair>>,
+    // 终端输出接收端 - 由独立的读取线程填充，避免阻塞读取占用异步运行时
+    output_rx: Arc<Mutex<mpsc::Receiver<OutputChunk>>>,
+    // 终端后端
+    backend: Arc<TerminalBackend>,
 }
 
-impl TerminalProcess {
-    // 根据配置创建终端进程
-    pub async fn new_with_config(shell_config: &ShellConfig) -> anyhow::Result<Self> {
-        // 创建命令构建器
-        let mut command_builder = CommandBuilder::new(&shell_config.command[0]);
-        
-        // 添加命令参数
-        if shell_config.command.len() > 1 {
-            command_builder.args(&shell_config.command[1..]);
+// 终端输出块
+#[derive(Debug, Clone)]
+pub struct OutputChunk {
+    pub data: String,
+    // 从PTY读取的时刻，用于统计输出延迟
+    pub read_at: Instant,
+}
+
+impl OutputChunk {
+    pub fn new(data: String) -> Self {
+        Self {
+            data,
+            read_at: Instant::now(),
         }
-        
-        // 设置工作目录，解析环境变量
-        if let Some(working_dir) = &shell_config.working_directory {
-            // 解析环境变量
-            let resolved_dir = if working_dir.is_empty() {
-                // 使用当前目录
-                ".".to_string()
+    }
+}
+
+// 终端后端 - 由本进程直接持有PTY，或由独立的会话保持进程持有
+enum TerminalBackend {
+    Local {
+        // 异步写入器
+        writer: Mutex<Box<dyn Write + Send>>,
+        // 子进程，用于检查是否运行
+        child: Mutex<Box<dyn portable_pty::Child + Send + Sync>>,
+        // PTY对，用于调整大小
+        pty_pair: Mutex<PtyPair>,
+    },
+    #[cfg(unix)]
+    Holder(HolderConnection),
+}
+
+// 根据配置构建shell命令
+pub(crate) fn build_command(shell_config: &ShellConfig) -> CommandBuilder {
+    // 创建命令构建器
+    let mut command_builder = CommandBuilder::new(&shell_config.command[0]);
+    
+    // 添加命令参数
+    if shell_config.command.len() > 1 {
+        command_builder.args(&shell_config.command[1..]);
+    }
+    
+    // 设置工作目录，解析环境变量
+    if let Some(working_dir) = &shell_config.working_directory {
+        // 解析环境变量
+        let resolved_dir = if working_dir.is_empty() {
+            // 使用当前目录
+            ".".to_string()
+        } else {
+            // 替换环境变量
+            if working_dir == "${USERPROFILE}" {
+                std::env::var("USERPROFILE").unwrap_or(".".to_string())
             } else {
-                // 替换环境变量
-                let resolved = if working_dir == "${USERPROFILE}" {
-                    std::env::var("USERPROFILE").unwrap_or(".".to_string())
-                } else {
-                    working_dir.clone()
-                };
-                resolved
-            };
-            
-            log::debug!("Resolved working directory: {:?} -> {:?}", working_dir, resolved_dir);
-            command_builder.cwd(resolved_dir);
-        }
-        
-        // 设置环境变量
-        for (key, value) in &shell_config.environment {
-            command_builder.env(key, value);
-        }
-        
-        // 获取默认的PTY系统
-        let pty_system = native_pty_system();
-        
-        // 获取终端大小配置
-        let (rows, cols) = match &shell_config.terminal_size {
-            Some(size) => (size.rows, size.columns),
-            None => (24, 80), // 默认值
+                working_dir.clone()
+            }
         };
         
+        log::debug!("Resolved working directory: {:?} -> {:?}", working_dir, resolved_dir);
+        command_builder.cwd(resolved_dir);
+    }
+    
+    // 设置环境变量
+    for (key, value) in &shell_config.environment {
+        command_builder.env(key, value);
+    }
+    
+    command_builder
+}
+
+// 按配置的终端大小创建PTY对
+pub(crate) fn open_pty(shell_config: &ShellConfig) -> anyhow::Result<PtyPair> {
+    // 获取默认的PTY系统
+    let pty_system = native_pty_system();
+    
+    // 获取终端大小配置
+    let (rows, cols) = match &shell_config.terminal_size {
+        Some(size) => (size.rows, size.columns),
+        None => (24, 80), // 默认值
+    };
+    
+    // 创建PTY对
+    pty_system.openpty(PtySize {
+        rows: rows as u16,
+        cols: cols as u16,
+        pixel_width: 0,
+        pixel_height: 0,
+    })
+}
+
+impl TerminalProcess {
+    // 根据配置创建终端进程
+    pub async fn new_with_config(shell_config: &ShellConfig) -> anyhow::Result<Self> {
         // 创建PTY对
-        let pty_pair = pty_system.openpty(PtySize {
-            rows: rows as u16,
-            cols: cols as u16,
-            pixel_width: 0,
-            pixel_height: 0,
-        })?;
+        let pty_pair = open_pty(shell_config)?;
         
         // 生成子进程
-        let child = pty_pair.slave.spawn_command(command_builder)?;
+        let child = pty_pair.slave.spawn_command(build_command(shell_config))?;
         
-        // 创建异步读取器和写入器
-        let async_reader = Box::new(pty_pair.master.try_clone_reader()?) as Box<dyn Read + Send>;
+        // 创建读取线程和写入器
+        let output_rx = spawn_reader_thread(pty_pair.master.try_clone_reader()?)?;
         let async_writer = Box::new(pty_pair.master.take_writer()?) as Box<dyn Write + Send>;
         
         log::info!("Created new PTY terminal process using command: {:?}", shell_config.command);
         
         Ok(Self {
-            reader: Arc::new(Mutex::new(async_reader)),
-            writer: Arc::new(Mutex::new(async_writer)),
-            child: Arc::new(Mutex::new(child)),
-            pty_pair: Arc::new(Mutex::new(pty_pair)),
+            output_rx: Arc::new(Mutex::new(output_rx)),
+            backend: Arc::new(TerminalBackend::Local {
+                writer: Mutex::new(async_writer),
+                child: Mutex::new(child),
+                pty_pair: Mutex::new(pty_pair),
+            }),
         })
     }
     
+    // 通过会话保持进程创建终端进程，服务器重启后shell继续运行
+    #[cfg(unix)]
+    pub async fn new_held(
+        shell_config: &ShellConfig,
+        state_directory: &std::path::Path,
+        session_id: &str,
+        replay_buffer_size: usize,
+    ) -> anyhow::Result<Self> {
+        let (connection, output_rx) = holder::spawn(state_directory, session_id, shell_config, replay_buffer_size).await?;
+        log::info!("Created held terminal process using command: {:?}", shell_config.command);
+        Ok(Self::from_holder(connection, output_rx))
+    }
+    
+    // 接管已连接的会话保持进程
+    #[cfg(unix)]
+    pub fn from_holder(connection: HolderConnection, output_rx: mpsc::Receiver<OutputChunk>) -> Self {
+        Self {
+            output_rx: Arc::new(Mutex::new(output_rx)),
+            backend: Arc::new(TerminalBackend::Holder(connection)),
+        }
+    }
+    
     // 写入输入到终端 - 使用独立的锁，避免死锁
     pub async fn write_input(&self, data: &str) -> anyhow::Result<()> {
-        let mut writer = self.writer.lock().await;
-        writer.write_all(data.as_bytes())?;
+        match self.backend.as_ref() {
+            TerminalBackend::Local { writer, .. } => {
+                let mut writer = writer.lock().await;
+                writer.write_all(data.as_bytes())?;
+            },
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => {
+                connection.send(HolderFrame::Input(data.as_bytes().to_vec())).await?;
+            },
+        }
         Ok(())
     }
     
-    // 读取终端输出 - 使用独立的锁，避免死锁
-    pub async fn read_output(&self, buffer: &mut [u8]) -> anyhow::Result<String> {
-        let mut reader = self.reader.lock().await;
-        let n = reader.read(buffer)?;
-        
-        if n == 0 {
-            Ok(String::new()) // EOF
-        } else {
-            let output = String::from_utf8_lossy(&buffer[..n]).to_string();
-            Ok(output)
-        }
+    // 读取终端输出 - 从读取线程的通道接收，EOF时返回空输出
+    pub async fn read_output(&self) -> anyhow::Result<OutputChunk> {
+        let mut output_rx = self.output_rx.lock().await;
+        Ok(output_rx.recv().await.unwrap_or_else(|| OutputChunk::new(String::new())))
     }
     
     // 调整终端大小 - 异步设计，只在调整时持有锁
     pub async fn resize(&self, columns: u32, rows: u32) -> anyhow::Result<()> {
         log::info!("Resizing terminal to {} columns x {} rows", columns, rows);
         
-        let pty_pair = self.pty_pair.lock().await;
-        let size = PtySize {
-            rows: rows as u16,
-            cols: columns as u16,
-            pixel_width: 0,
-            pixel_height: 0,
-        };
-        
-        pty_pair.master.resize(size)?;
+        match self.backend.as_ref() {
+            TerminalBackend::Local { pty_pair, .. } => {
+                let pty_pair = pty_pair.lock().await;
+                let size = PtySize {
+                    rows: rows as u16,
+                    cols: columns as u16,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                };
+                
+                pty_pair.master.resize(size)?;
+            },
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => {
+                connection.send(HolderFrame::Resize { columns: columns as u16, rows: rows as u16 }).await?;
+            },
+        }
         log::debug!("Successfully resized terminal to {}x{}", columns, rows);
         
         Ok(())
     }
     
-    // 关闭终端 - 异步设计，只在关闭时持有锁
-    pub async fn close(&self) -> anyhow::Result<()> {
-        // 关闭读取器和写入器
-        drop(self.reader.lock().await);
-        drop(self.writer.lock().await);
+    // 关闭终端 - 依次发送SIGHUP、SIGTERM、SIGKILL，每一级等待signal_timeout
+    pub async fn close(&self, signal_timeout: Duration) -> anyhow::Result<()> {
+        let child = match self.backend.as_ref() {
+            TerminalBackend::Local { child, .. } => child,
+            // 由保持进程负责逐级发送信号，这里等待其报告shell退出
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => {
+                connection.send(HolderFrame::Terminate { signal_timeout_ms: signal_timeout.as_millis() as u64 }).await?;
+                self.wait_for_exit(signal_timeout * 3 + Duration::from_secs(1)).await;
+                if self.is_running().await {
+                    anyhow::bail!("Held terminal process did not exit");
+                }
+                log::info!("Closed held terminal process");
+                return Ok(());
+            },
+        };
         
-        // 关闭PTY对
-        drop(self.pty_pair.lock().await);
+        #[cfg(unix)]
+        {
+            let pid = child.lock().await.process_id();
+            if let Some(pid) = pid {
+                for signal in [libc::SIGHUP, libc::SIGTERM] {
+                    if !self.is_running().await {
+                        break;
+                    }
+                    log::debug!("Sending signal {} to terminal process {}", signal, pid);
+                    // SAFETY: kill只向指定pid发送信号，不涉及内存访问
+                    unsafe { libc::kill(pid as libc::pid_t, signal) };
+                    self.wait_for_exit(signal_timeout).await;
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = signal_timeout;
         
-        // 终止子进程
-        let mut child = self.child.lock().await;
-        child.kill()?;
-        child.wait()?;
+        // 仍未退出则强制终止
+        let mut child = child.lock().await;
+        if let Ok(None) = child.try_wait() {
+            log::warn!("Terminal process did not exit gracefully, killing it");
+            child.kill()?;
+            child.wait()?;
+        }
         
         log::info!("Closed terminal process");
         Ok(())
     }
     
+    // 等待进程退出，最多等待timeout
+    #[cfg(unix)]
+    async fn wait_for_exit(&self, timeout: Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while tokio::time::Instant::now() < deadline {
+            if !self.is_running().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+    
+    // 获取终端进程的退出码，仍在运行或无法获取时为None
+    pub async fn exit_code(&self) -> Option<u32> {
+        match self.backend.as_ref() {
+            TerminalBackend::Local { child, .. } => {
+                let mut child = child.lock().await;
+                match child.try_wait() {
+                    Ok(Some(status)) => Some(status.exit_code()),
+                    _ => None,
+                }
+            },
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => connection.exit_code(),
+        }
+    }
+    
+    // 终端是否正在读取不回显的输入（如密码）：规范模式下关闭了回显；
+    // readline等行编辑器使用非规范模式并自行回显，不视为隐藏输入；无法判断时为None
+    pub async fn input_hidden(&self) -> Option<bool> {
+        match self.backend.as_ref() {
+            #[cfg(unix)]
+            TerminalBackend::Local { pty_pair, .. } => {
+                let pty_pair = pty_pair.lock().await;
+                termios_hides_input(pty_pair.master.as_raw_fd()?)
+            },
+            #[cfg(not(unix))]
+            TerminalBackend::Local { .. } => None,
+            // PTY主端在保持进程中，通过shell的标准输入（PTY从端）读取终端模式
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => {
+                use std::os::unix::fs::OpenOptionsExt;
+                use std::os::unix::io::AsRawFd;
+                let shell_pid = connection.shell_pid()?;
+                // 每次输入都会检查，打开 /proc 下的文件在阻塞线程池中进行，不占用异步运行时的工作线程
+                tokio::task::spawn_blocking(move || {
+                    let tty = std::fs::OpenOptions::new()
+                        .read(true)
+                        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+                        .open(format!("/proc/{}/fd/0", shell_pid))
+                        .ok()?;
+                    termios_hides_input(tty.as_raw_fd())
+                }).await.ok()?
+            },
+        }
+    }
+    
+    // shell进程ID，无法获取时为None
+    pub async fn shell_pid(&self) -> Option<u32> {
+        match self.backend.as_ref() {
+            TerminalBackend::Local { child, .. } => child.lock().await.process_id(),
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => connection.shell_pid(),
+        }
+    }
+    
+    // PTY的前台进程组：本进程持有PTY时通过tcgetpgrp获取，由保持进程持有时读取shell的 /proc 状态；
+    // 无法获取时为None
+    pub async fn foreground_process_group(&self) -> Option<u32> {
+        match self.backend.as_ref() {
+            #[cfg(unix)]
+            TerminalBackend::Local { pty_pair, .. } => {
+                let pty_pair = pty_pair.lock().await;
+                let fd = pty_pair.master.as_raw_fd()?;
+                // SAFETY: fd由PTY持有且在锁内有效，tcgetpgrp不涉及内存访问
+                let process_group = unsafe { libc::tcgetpgrp(fd) };
+                u32::try_from(process_group).ok().filter(|process_group| *process_group > 0)
+            },
+            #[cfg(not(unix))]
+            TerminalBackend::Local { .. } => None,
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => process::terminal_foreground_group(connection.shell_pid()?),
+        }
+    }
+    
+    // 向前台进程组或shell发送信号，返回接收信号的进程组或进程ID
+    #[cfg(unix)]
+    pub async fn signal(&self, signal: Signal, target: SignalTarget) -> anyhow::Result<u32> {
+        match target {
+            SignalTarget::Foreground => {
+                let process_group = self.foreground_process_group().await
+                    .ok_or_else(|| anyhow::anyhow!("Foreground process group is unavailable"))?;
+                process::kill(-(process_group as i32), signal)?;
+                Ok(process_group)
+            },
+            SignalTarget::Shell => match self.backend.as_ref() {
+                // 持有子进程锁直到信号发送完成，期间shell不会被回收，进程ID不会被复用
+                TerminalBackend::Local { child, .. } => {
+                    let mut child = child.lock().await;
+                    if !matches!(child.try_wait(), Ok(None)) {
+                        anyhow::bail!("Shell process has exited");
+                    }
+                    let pid = child.process_id().ok_or_else(|| anyhow::anyhow!("Shell process is unavailable"))?;
+                    process::kill(pid as i32, signal)?;
+                    Ok(pid)
+                },
+                TerminalBackend::Holder(connection) => {
+                    if connection.has_exited() {
+                        anyhow::bail!("Shell process has exited");
+                    }
+                    let pid = connection.shell_pid().ok_or_else(|| anyhow::anyhow!("Shell process is unavailable"))?;
+                    process::kill(pid as i32, signal)?;
+                    Ok(pid)
+                },
+            },
+        }
+    }
+    
+    #[cfg(not(unix))]
+    pub async fn signal(&self, signal: Signal, _target: SignalTarget) -> anyhow::Result<u32> {
+        anyhow::bail!("Sending {} is not supported on this platform", signal.name())
+    }
+    
     // 检查终端进程是否还在运行
     pub async fn is_running(&self) -> bool {
-        let mut child = self.child.lock().await;
-        match child.try_wait() {
-            Ok(None) => true, // 进程还在运行
-            _ => false, // 进程已经退出或者发生错误
+        match self.backend.as_ref() {
+            TerminalBackend::Local { child, .. } => {
+                let mut child = child.lock().await;
+                match child.try_wait() {
+                    Ok(None) => true, // 进程还在运行
+                    _ => false, // 进程已经退出或者发生错误
+                }
+            },
+            #[cfg(unix)]
+            TerminalBackend::Holder(connection) => !connection.has_exited(),
         }
     }
 }
+
+// 按终端模式判断输入是否不回显：关闭了回显且处于规范模式
+#[cfg(unix)]
+fn termios_hides_input(fd: std::os::unix::io::RawFd) -> Option<bool> {
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: fd在调用期间有效，tcgetattr成功时完整初始化termios
+    if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let termios = unsafe { termios.assume_init() };
+    Some(termios.c_lflag & libc::ECHO == 0 && termios.c_lflag & libc::ICANON != 0)
+}
+
+// 取出缓冲区中完整的UTF-8文本，末尾不完整的字符留到下一次读取，非法字节按有损方式处理
+pub(crate) fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let valid_up_to = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    
+    let output = String::from_utf8_lossy(&pending[..valid_up_to]).to_string();
+    pending.drain(..valid_up_to);
+    output
+}
+
+// 启动PTY读取线程 - 阻塞读取在专用线程中进行，按UTF-8边界切分后发送到通道
+fn spawn_reader_thread(mut reader: Box<dyn Read + Send>) -> anyhow::Result<mpsc::Receiver<OutputChunk>> {
+    let (output_tx, output_rx) = mpsc::channel::<OutputChunk>(64);
+    
+    std::thread::Builder::new()
+        .name("pty-reader".to_string())
+        .spawn(move || {
+            let mut buffer = [0u8; 4096];
+            // 上一次读取末尾不完整的UTF-8字节
+            let mut pending: Vec<u8> = Vec::new();
+            
+            loop {
+                let n = match reader.read(&mut buffer) {
+                    Ok(0) => break, // EOF
+                    Ok(n) => n,
+                    Err(e) => {
+                        log::debug!("PTY reader stopped: {}", e);
+                        break;
+                    }
+                };
+                
+                pending.extend_from_slice(&buffer[..n]);
+                let output = take_utf8(&mut pending);
+                
+                if !output.is_empty() && output_tx.blocking_send(OutputChunk::new(output)).is_err() {
+                    break;
+                }
+            }
+        })?;
+    
+    Ok(output_rx)
+}
@@ -1,11 +1,46 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
+use uuid::Uuid;
 
-use crate::session::session::SessionManager;
+use crate::audit::{query_value, ClientContext};
+use crate::metrics::Metrics;
+use crate::protocol::message::{ClientMessage, ServerMessage};
+use crate::session::error::{SessionError, SessionResult};
+use crate::session::recording::{self, Recording};
+use crate::session::replay::{self, ReplayCommand};
+use crate::session::session::{check_reader, SessionManager, SessionOptions};
+
+// 连接目标 - 实时会话或录制回放
+#[derive(Debug, Clone)]
+pub enum ConnectionTarget {
+    Session(String),
+    Replay { recording_id: String, speed: f64 },
+}
+
+impl ConnectionTarget {
+    // 解析连接路径：.../replay/{recordingId}?speed=2 为回放，其余以最后一段作为会话ID
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty()).rev();
+        let id = segments.next()?.to_string();
+        
+        if segments.next() == Some("replay") {
+            let speed = query_value(query, "speed")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(1.0);
+            return Some(ConnectionTarget::Replay { recording_id: id, speed });
+        }
+        Some(ConnectionTarget::Session(id))
+    }
+}
 
 // 终端服务 - 处理PTY交互的核心逻辑
 pub struct TerminalService {
     session_manager: Arc<SessionManager>,
+    // 进行中的回放，键为回放连接ID
+    replays: Mutex<HashMap<String, mpsc::Sender<ReplayCommand>>>,
 }
 
 impl TerminalService {
@@ -13,27 +48,184 @@ impl TerminalService {
     pub fn new(session_manager: Arc<SessionManager>) -> Self {
         Self {
             session_manager,
+            replays: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    // 将客户端连接到会话或回放，返回后续消息使用的连接ID
+    pub async fn connect(
+        self: &Arc<Self>,
+        target: ConnectionTarget,
+        output_sender: mpsc::Sender<ServerMessage>,
+        client: &mut ClientContext,
+    ) -> anyhow::Result<String> {
+        // 录制ID即会话ID，观看回放与实时观看需要相同的角色
+        match target {
+            ConnectionTarget::Session(session_id) => {
+                self.session_manager.authorize_connection(&session_id, client)?;
+                check_reader(&session_id, client)?;
+                self.handle_terminal_connection(&session_id, output_sender, client).await?;
+                Ok(session_id)
+            },
+            ConnectionTarget::Replay { recording_id, speed } => {
+                self.session_manager.authorize_connection(&recording_id, client)?;
+                check_reader(&recording_id, client)?;
+                self.start_replay(&recording_id, speed, output_sender)
+            },
         }
     }
     
+    // 客户端断开连接，回放连接没有对应的会话
+    pub fn disconnect(&self, session_id: &str, client_id: &str) {
+        self.session_manager.detach_client(session_id, client_id);
+    }
+    
+    // 校验共享令牌，确定客户端在会话中的角色，不计令牌使用次数
+    pub fn authorize_client(&self, session_id: &str, client: &mut ClientContext) -> anyhow::Result<()> {
+        self.session_manager.authorize_client(session_id, client)
+    }
+    
+    // Prometheus指标
+    pub fn metrics(&self) -> &Metrics {
+        self.session_manager.metrics()
+    }
+    
+    // 检查录制是否存在
+    pub fn recording_exists(&self, recording_id: &str) -> bool {
+        recording::recording_path(self.session_manager.recording_directory(), recording_id)
+            .is_ok_and(|path| path.exists())
+    }
+    
+    // 开始回放录制，客户端断开后回放自动结束
+    fn start_replay(self: &Arc<Self>, recording_id: &str, speed: f64, output_sender: mpsc::Sender<ServerMessage>) -> anyhow::Result<String> {
+        let path = recording::recording_path(self.session_manager.recording_directory(), recording_id)?;
+        let recording = Recording::load(&path)?;
+        
+        let replay_id = format!("replay-{}", Uuid::new_v4());
+        let (command_tx, command_rx) = mpsc::channel(16);
+        self.replays.lock().unwrap().insert(replay_id.clone(), command_tx);
+        log::info!("Starting replay {} of recording {} at {}x", replay_id, recording_id, speed);
+        
+        let terminal_service = self.clone();
+        let replay_id_clone = replay_id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = replay::play(recording, output_sender, command_rx, speed).await {
+                log::debug!("Replay {} stopped: {}", replay_id_clone, e);
+            }
+            terminal_service.replays.lock().unwrap().remove(&replay_id_clone);
+            log::info!("Replay {} finished", replay_id_clone);
+        });
+        
+        Ok(replay_id)
+    }
+    
+    // 处理回放连接的消息 - 忽略终端输入和调整大小
+    async fn handle_replay_message(&self, command_tx: mpsc::Sender<ReplayCommand>, message: ClientMessage) -> anyhow::Result<Option<ServerMessage>> {
+        let command = match message {
+            ClientMessage::Pause => ReplayCommand::Pause,
+            ClientMessage::Resume => ReplayCommand::Resume,
+            ClientMessage::Seek { position_ms } => ReplayCommand::Seek(Duration::from_millis(position_ms)),
+            ClientMessage::Speed { speed } => ReplayCommand::Speed(speed),
+            ClientMessage::Ping { timestamp } => return Ok(Some(ServerMessage::Pong { timestamp })),
+            ClientMessage::Input { .. } | ClientMessage::Resize { .. } => return Ok(None),
+            ClientMessage::RequestControl | ClientMessage::TakeControl | ClientMessage::ReleaseControl | ClientMessage::GrantControl { .. } => return Ok(None),
+        };
+        command_tx.send(command).await.map_err(|_| anyhow::anyhow!("Replay has finished"))?;
+        Ok(None)
+    }
+    
     // 处理终端输入
-    pub async fn handle_terminal_input(&self, session_id: &str, input: String) -> anyhow::Result<()> {
+    pub async fn handle_terminal_input(&self, session_id: &str, input: String, client: &ClientContext) -> anyhow::Result<()> {
         // 将输入写入终端
-        self.session_manager.write_to_session(session_id, &input).await?;
+        self.session_manager.write_to_session(session_id, &input, client).await?;
+        Ok(())
+    }
+    
+    // 处理终端大小调整
+    pub async fn handle_terminal_resize(&self, session_id: &str, columns: u32, rows: u32, client: &ClientContext) -> SessionResult<()> {
+        self.session_manager.resize_session(session_id, columns, rows, client).await?;
         Ok(())
     }
     
+    // 处理类型化客户端消息 - 返回需要直接回复给该客户端的消息
+    #[tracing::instrument(level = "trace", name = "client.message", skip_all, fields(session.id = session_id, user.id = client.user_id.as_deref(), transport = client.transport.as_str()))]
+    pub async fn handle_client_message(
+        &self,
+        session_id: &str,
+        message: ClientMessage,
+        client: &ClientContext,
+    ) -> anyhow::Result<Option<ServerMessage>> {
+        let replay = self.replays.lock().unwrap().get(session_id).cloned();
+        if let Some(command_tx) = replay {
+            return self.handle_replay_message(command_tx, message).await;
+        }
+        
+        // 只读观看者不能输入或调整终端大小，单人操控模式下只接受操控者的输入，其他协作者仍可上报窗口大小
+        let dropped = match message {
+            ClientMessage::Input { .. } => !self.session_manager.may_write(session_id, client),
+            ClientMessage::Resize { .. } => !client.can_write(),
+            _ => false,
+        };
+        if dropped {
+            log::debug!("Dropping input from client {} of session {}", client.client_id, session_id);
+            return Ok(None);
+        }
+        
+        match message {
+            ClientMessage::Input { data } => {
+                self.handle_terminal_input(session_id, data, client).await?;
+                Ok(None)
+            },
+            // 无效的大小只回复该客户端，不断开连接
+            ClientMessage::Resize { columns, rows } => match self.handle_terminal_resize(session_id, columns, rows, client).await {
+                Ok(()) => Ok(None),
+                Err(SessionError::Invalid(message)) => Ok(Some(ServerMessage::Error { message })),
+                Err(e) => Err(e.into()),
+            },
+            ClientMessage::Ping { timestamp } => {
+                self.session_manager.update_session_activity(session_id).await?;
+                Ok(Some(ServerMessage::Pong { timestamp }))
+            },
+            // 控制权请求失败时只回复该客户端，不断开连接
+            ClientMessage::RequestControl | ClientMessage::TakeControl | ClientMessage::ReleaseControl | ClientMessage::GrantControl { .. } => {
+                match self.session_manager.handle_control(session_id, client, &message) {
+                    Ok(()) => Ok(None),
+                    Err(e) => Ok(Some(ServerMessage::Error { message: e.to_string() })),
+                }
+            },
+            // 回放控制消息对实时会话无效
+            ClientMessage::Pause | ClientMessage::Resume | ClientMessage::Seek { .. } | ClientMessage::Speed { .. } => {
+                log::debug!("Ignoring replay control message for live session {}", session_id);
+                Ok(None)
+            },
+        }
+    }
+    
     // 处理终端连接
-    pub async fn handle_terminal_connection(&self, session_id: &str, output_sender: mpsc::Sender<String>) -> anyhow::Result<()> {
-        // 添加客户端发送者到会话
-        self.session_manager.add_client_sender(session_id, output_sender).await;
+    pub async fn handle_terminal_connection(
+        &self,
+        session_id: &str,
+        output_sender: mpsc::Sender<ServerMessage>,
+        client: &ClientContext,
+    ) -> anyhow::Result<()> {
+        // 添加客户端发送者到会话，会话不存在或已终止时连接失败
+        self.session_manager.add_client_sender(session_id, output_sender, client).await?;
         Ok(())
     }
     
+    // 检查会话是否存在
+    pub async fn session_exists(&self, session_id: &str) -> bool {
+        self.session_manager.session_exists(session_id).await
+    }
+    
     // 创建新的终端会话
-    pub async fn create_terminal_session(&self) -> anyhow::Result<String> {
+    pub async fn create_terminal_session(&self, client: &ClientContext) -> anyhow::Result<String> {
         // 创建新会话
-        let session_id = self.session_manager.create_session().await?;
+        let options = SessionOptions {
+            user_id: client.user_id.clone(),
+            ..SessionOptions::default()
+        };
+        let session_id = self.session_manager.create_session(options, client).await?;
         Ok(session_id)
     }
 }
@@ -0,0 +1,39 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::config::RedactionRule;
+
+// 敏感信息过滤器 - 按配置的正则规则替换密码、令牌等内容
+#[derive(Clone, Default)]
+pub struct Redactor {
+    rules: Arc<Vec<(Regex, String)>>,
+}
+
+impl Redactor {
+    // 编译配置中的规则，任一规则无效时返回错误
+    pub fn new(rules: &[RedactionRule]) -> anyhow::Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.pattern)
+                    .map_err(|e| anyhow::anyhow!("Invalid redaction pattern '{}': {}", rule.pattern, e))?;
+                Ok((regex, rule.replacement.clone()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self { rules: Arc::new(rules) })
+    }
+
+    // 依次应用所有规则，替换文本支持 $1 等分组引用
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(text);
+        for (regex, replacement) in self.rules.iter() {
+            if let Cow::Owned(replaced) = regex.replace_all(&redacted, replacement.as_str()) {
+                redacted = Cow::Owned(replaced);
+            }
+        }
+        redacted
+    }
+}
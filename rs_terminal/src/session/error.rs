@@ -0,0 +1,44 @@
+use std::fmt;
+
+// 会话操作的错误，REST接口按类型返回对应的状态码
+#[derive(Debug)]
+pub enum SessionError {
+    // 会话不存在或已终止
+    SessionNotFound(String),
+    // 会话中的命令、触发器、共享等不存在
+    NotFound(String),
+    // 调用者无权执行该操作
+    Forbidden(String),
+    // 与当前状态冲突，如同名触发器已存在
+    Conflict(String),
+    // 参数无效
+    Invalid(String),
+    // 当前无法完成，如平台不支持或进程信息无法获取
+    Unavailable(String),
+    // 其他错误（I/O等）
+    Other(anyhow::Error),
+}
+
+pub type SessionResult<T> = Result<T, SessionError>;
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::SessionNotFound(session_id) => write!(f, "Session not found: {}", session_id),
+            SessionError::NotFound(message)
+            | SessionError::Forbidden(message)
+            | SessionError::Conflict(message)
+            | SessionError::Invalid(message)
+            | SessionError::Unavailable(message) => f.write_str(message),
+            SessionError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<anyhow::Error> for SessionError {
+    fn from(e: anyhow::Error) -> Self {
+        SessionError::Other(e)
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Duration, Instant};
+
+use crate::audit::ClientContext;
+use crate::session::error::{SessionError, SessionResult};
+use crate::session::screen::PlainText;
+use crate::session::session::SessionManager;
+
+// 未指定超时时单个expect步骤的等待时间
+const DEFAULT_STEP_TIMEOUT_MS: u64 = 10_000;
+// 单个expect步骤允许的最长等待时间
+const MAX_STEP_TIMEOUT_MS: u64 = 300_000;
+// 单个脚本最多的步骤数
+const MAX_STEPS: usize = 100;
+// 等待匹配时保留的输出长度上限（字节），超出时丢弃最早的输出
+const MAX_BUFFER_SIZE: usize = 64 * 1024;
+// 超时时在结果中返回的最近输出长度（字节）
+const OUTPUT_TAIL_SIZE: usize = 2048;
+// 正则表达式编译后的大小上限
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+// 自动化脚本的一个步骤
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ExpectStep {
+    // 向终端发送数据，需要回车时在data中包含 "\n"
+    Send { data: String },
+    // 等待输出匹配正则表达式，捕获组的值返回给调用方
+    Expect { pattern: String, timeout_ms: Option<u64> },
+}
+
+// 自动化脚本
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpectScript {
+    pub steps: Vec<ExpectStep>,
+    // 匹配前去掉ANSI转义序列，默认开启
+    #[serde(default = "default_strip_ansi")]
+    pub strip_ansi: bool,
+}
+
+fn default_strip_ansi() -> bool {
+    true
+}
+
+// 单个expect步骤的匹配结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepMatch {
+    pub step: usize,
+    pub matched: String,
+    // 按序号排列的捕获组，未参与匹配的组为null
+    pub captures: Vec<Option<String>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub named: HashMap<String, String>,
+}
+
+// 脚本执行结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpectResult {
+    pub success: bool,
+    pub matches: Vec<StepMatch>,
+    // 失败的步骤序号（从0开始）和原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_step: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // 失败时最近的输出，便于排查
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+// 编译后的步骤
+enum CompiledStep {
+    Send(String),
+    Expect(Regex, Duration),
+}
+
+// 检查并编译脚本，错误在执行前返回
+fn compile(script: &ExpectScript) -> anyhow::Result<Vec<CompiledStep>> {
+    if script.steps.is_empty() {
+        anyhow::bail!("Script has no steps");
+    }
+    if script.steps.len() > MAX_STEPS {
+        anyhow::bail!("Script has more than {} steps", MAX_STEPS);
+    }
+    script.steps.iter().enumerate().map(|(index, step)| match step {
+        ExpectStep::Send { data } => Ok(CompiledStep::Send(data.clone())),
+        ExpectStep::Expect { pattern, timeout_ms } => {
+            let regex = RegexBuilder::new(pattern)
+                .size_limit(REGEX_SIZE_LIMIT)
+                .build()
+                .map_err(|e| anyhow::anyhow!("Invalid pattern in step {}: {}", index, e))?;
+            let timeout = timeout_ms.unwrap_or(DEFAULT_STEP_TIMEOUT_MS).min(MAX_STEP_TIMEOUT_MS);
+            Ok(CompiledStep::Expect(regex, Duration::from_millis(timeout)))
+        },
+    }).collect()
+}
+
+// 累积的待匹配输出
+struct OutputBuffer {
+    text: String,
+    plain: Option<PlainText>,
+}
+
+impl OutputBuffer {
+    fn new(strip_ansi: bool) -> Self {
+        Self {
+            text: String::new(),
+            plain: strip_ansi.then(PlainText::default),
+        }
+    }
+
+    fn push(&mut self, output: &str) {
+        match &mut self.plain {
+            Some(plain) => self.text.push_str(&plain.feed(output)),
+            None => self.text.push_str(output),
+        }
+        if self.text.len() > MAX_BUFFER_SIZE {
+            self.discard(self.text.len() - MAX_BUFFER_SIZE);
+        }
+    }
+
+    // 丢弃开头的若干字节，按字符边界对齐
+    fn discard(&mut self, bytes: usize) {
+        let mut boundary = bytes.min(self.text.len());
+        while !self.text.is_char_boundary(boundary) {
+            boundary += 1;
+        }
+        self.text.drain(..boundary);
+    }
+
+    fn tail(&self) -> String {
+        let mut start = self.text.len().saturating_sub(OUTPUT_TAIL_SIZE);
+        while !self.text.is_char_boundary(start) {
+            start += 1;
+        }
+        self.text[start..].to_string()
+    }
+
+    // 尝试匹配，成功时丢弃匹配结尾之前的输出
+    fn try_match(&mut self, step: usize, regex: &Regex) -> Option<StepMatch> {
+        let captures = regex.captures(&self.text)?;
+        let whole = captures.get(0)?;
+        let result = StepMatch {
+            step,
+            matched: whole.as_str().to_string(),
+            captures: captures.iter().skip(1).map(|group| group.map(|group| group.as_str().to_string())).collect(),
+            named: regex.capture_names().flatten()
+                .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+                .collect(),
+        };
+        let end = whole.end();
+        self.discard(end);
+        Some(result)
+    }
+}
+
+// 针对会话的实时输出依次执行脚本步骤
+pub async fn run(session_manager: &SessionManager, session_id: &str, script: &ExpectScript, client: &ClientContext) -> SessionResult<ExpectResult> {
+    let steps = compile(script).map_err(|e| SessionError::Invalid(e.to_string()))?;
+    // 先订阅输出再发送，避免错过发送后立即出现的输出
+    let mut output_rx = session_manager.subscribe_output(session_id).await?;
+    let mut buffer = OutputBuffer::new(script.strip_ansi);
+    let mut matches = Vec::new();
+
+    for (index, step) in steps.iter().enumerate() {
+        let failure = match step {
+            CompiledStep::Send(data) => match session_manager.write_to_session(session_id, data, client).await {
+                Ok(()) => None,
+                Err(e) => Some(format!("Failed to send: {}", e)),
+            },
+            CompiledStep::Expect(regex, timeout) => {
+                match expect(&mut output_rx, &mut buffer, index, regex, *timeout).await {
+                    Ok(step_match) => {
+                        matches.push(step_match);
+                        None
+                    },
+                    Err(e) => Some(e.to_string()),
+                }
+            },
+        };
+        if let Some(error) = failure {
+            log::info!("Expect script on session {} failed at step {}: {}", session_id, index, error);
+            return Ok(ExpectResult {
+                success: false,
+                matches,
+                failed_step: Some(index),
+                error: Some(error),
+                output: Some(buffer.tail()),
+            });
+        }
+    }
+
+    Ok(ExpectResult {
+        success: true,
+        matches,
+        failed_step: None,
+        error: None,
+        output: None,
+    })
+}
+
+// 等待输出匹配，超时或会话结束时返回错误
+async fn expect(
+    output_rx: &mut broadcast::Receiver<String>,
+    buffer: &mut OutputBuffer,
+    step: usize,
+    regex: &Regex,
+    timeout: Duration,
+) -> anyhow::Result<StepMatch> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(step_match) = buffer.try_match(step, regex) {
+            return Ok(step_match);
+        }
+        match tokio::time::timeout_at(deadline, output_rx.recv()).await {
+            Ok(Ok(output)) => buffer.push(&output),
+            Ok(Err(RecvError::Lagged(skipped))) => {
+                log::warn!("Expect script skipped {} output chunks", skipped);
+            },
+            Ok(Err(RecvError::Closed)) => anyhow::bail!("Session closed while waiting for {:?}", regex.as_str()),
+            Err(_) => anyhow::bail!("Timed out after {}ms waiting for {:?}", timeout.as_millis(), regex.as_str()),
+        }
+    }
+}
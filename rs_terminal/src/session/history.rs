@@ -0,0 +1,184 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::HistoryConfig;
+use crate::redaction::Redactor;
+use crate::session::shell_integration::CommandInfo;
+
+// 持久化的一条命令
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub user_id: String,
+    pub session_id: String,
+    #[serde(flatten)]
+    pub command: CommandInfo,
+}
+
+// 命令历史查询条件
+pub struct HistoryQuery {
+    pub user_id: String,
+    pub session_id: Option<String>,
+    // 命令行包含的文本，忽略大小写
+    pub text: Option<String>,
+    // 最多返回的条数，返回最近的命令
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.session_id.as_ref().is_none_or(|session_id| &entry.session_id == session_id)
+            && self.text.as_ref().is_none_or(|text| {
+                entry.command.command.as_ref()
+                    .is_some_and(|command| command.to_lowercase().contains(&text.to_lowercase()))
+            })
+    }
+}
+
+// 按用户保存的命令历史 - JSON Lines追加日志，启动时每个用户只保留最近的命令并压缩文件；
+// 未开启持久化时不保存
+pub struct HistoryStore {
+    // 未开启时为None
+    file: Option<Mutex<File>>,
+    entries: RwLock<HashMap<String, VecDeque<HistoryEntry>>>,
+    max_entries: usize,
+    redactor: Redactor,
+}
+
+impl HistoryStore {
+    pub fn open(config: &HistoryConfig, redactor: Redactor) -> anyhow::Result<Self> {
+        let mut entries = HashMap::new();
+        let file = if config.persist {
+            let path = Path::new(&config.path);
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            entries = Self::load(path, config.max_entries)?;
+            Self::compact(path, &entries)?;
+            log::info!("Loaded command history of {} users from {:?}", entries.len(), path);
+            Some(Mutex::new(OpenOptions::new().create(true).append(true).open(path)?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            file,
+            entries: RwLock::new(entries),
+            max_entries: config.max_entries,
+            redactor,
+        })
+    }
+
+    // 读取所有命令，每个用户只保留最近的命令，跳过写了一半的行
+    fn load(path: &Path, max_entries: usize) -> anyhow::Result<HashMap<String, VecDeque<HistoryEntry>>> {
+        let mut entries: HashMap<String, VecDeque<HistoryEntry>> = HashMap::new();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e.into()),
+        };
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<HistoryEntry>(&line) {
+                Ok(entry) => {
+                    let user_entries = entries.entry(entry.user_id.clone()).or_default();
+                    if user_entries.len() == max_entries {
+                        user_entries.pop_front();
+                    }
+                    user_entries.push_back(entry);
+                },
+                Err(e) => log::warn!("Skipping invalid history entry at {:?}:{}: {}", path, index + 1, e),
+            }
+        }
+        Ok(entries)
+    }
+
+    // 重写文件，只保留加载的命令
+    fn compact(path: &Path, entries: &HashMap<String, VecDeque<HistoryEntry>>) -> anyhow::Result<()> {
+        let temporary_path = path.with_extension("tmp");
+        let mut file = File::create(&temporary_path)?;
+        let mut all: Vec<&HistoryEntry> = entries.values().flatten().collect();
+        all.sort_by_key(|entry| entry.command.started_at);
+        for entry in all {
+            serde_json::to_writer(&mut file, entry)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    // 保存一条执行完的命令，命令行经过敏感信息过滤
+    pub fn append(&self, user_id: &str, session_id: &str, command: &CommandInfo) -> anyhow::Result<()> {
+        let Some(file) = &self.file else { return Ok(()) };
+        let mut command = command.clone();
+        command.command = command.command.map(|text| self.redactor.redact(&text).into_owned());
+        let entry = HistoryEntry {
+            user_id: user_id.to_string(),
+            session_id: session_id.to_string(),
+            command,
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        // 先写文件再更新内存，写入失败时两者保持一致
+        {
+            let mut file = file.lock().unwrap();
+            file.write_all(&line)?;
+            file.flush()?;
+        }
+        let mut entries = self.entries.write().unwrap();
+        let user_entries = entries.entry(entry.user_id.clone()).or_default();
+        if user_entries.len() == self.max_entries {
+            user_entries.pop_front();
+        }
+        user_entries.push_back(entry);
+        Ok(())
+    }
+
+    // 查询用户的命令，最早的在前
+    pub fn search(&self, query: &HistoryQuery) -> Vec<HistoryEntry> {
+        let entries = self.entries.read().unwrap();
+        let Some(user_entries) = entries.get(&query.user_id) else { return Vec::new() };
+        let mut matched: Vec<HistoryEntry> = user_entries.iter().rev()
+            .filter(|entry| query.matches(entry))
+            .take(query.limit)
+            .cloned()
+            .collect();
+        matched.reverse();
+        matched
+    }
+
+    // 按会话和序号查找命令
+    pub fn find(&self, session_id: &str, number: u64) -> Option<HistoryEntry> {
+        self.entries.read().unwrap().values()
+            .flatten()
+            .find(|entry| entry.session_id == session_id && entry.command.number == number)
+            .cloned()
+    }
+
+    // 会话最近执行的若干条命令，最早的在前
+    pub fn session(&self, session_id: &str, limit: usize) -> Vec<CommandInfo> {
+        let entries = self.entries.read().unwrap();
+        let mut commands: Vec<CommandInfo> = entries.values()
+            .flatten()
+            .filter(|entry| entry.session_id == session_id)
+            .map(|entry| entry.command.clone())
+            .collect();
+        commands.sort_by_key(|command| command.number);
+        commands.split_off(commands.len().saturating_sub(limit))
+    }
+}
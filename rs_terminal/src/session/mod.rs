@@ -1,2 +1,17 @@
 // Session模块 - 处理会话管理和会话状态
+#[allow(clippy::module_inception)]
 pub mod session;
+pub mod error;
+pub mod expect;
+pub mod history;
+pub mod recording;
+pub mod presence;
+pub mod replay;
+pub mod screen;
+pub mod search;
+pub mod repository;
+pub mod share;
+pub mod shell_integration;
+pub mod sizing;
+pub mod snapshot;
+pub mod trigger;
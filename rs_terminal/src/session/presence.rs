@@ -0,0 +1,249 @@
+use crate::protocol::message::{ControlEvent, Participant};
+
+// 会话的在线状态和控制权 - 只维护状态，返回需要广播给所有客户端的事件
+pub struct Presence {
+    // 单人操控模式：只有操控者的输入会写入终端
+    single_driver: bool,
+    // 按加入顺序排列的客户端
+    participants: Vec<Participant>,
+    // 当前操控者的客户端ID
+    driver: Option<String>,
+    // 按请求顺序排列的控制权请求
+    requests: Vec<String>,
+}
+
+impl Presence {
+    pub fn new(single_driver: bool) -> Self {
+        Self {
+            single_driver,
+            participants: Vec::new(),
+            driver: None,
+            requests: Vec::new(),
+        }
+    }
+
+    fn find(&self, client_id: &str) -> Option<&Participant> {
+        self.participants.iter().find(|participant| participant.client_id == client_id)
+    }
+
+    fn driver_changed(&self) -> ControlEvent {
+        ControlEvent::ControlChanged {
+            driver: self.driver.as_deref().and_then(|driver| self.find(driver)).cloned(),
+        }
+    }
+
+    // 发送给客户端的当前在线状态
+    pub fn snapshot(&self, client_id: &str) -> ControlEvent {
+        ControlEvent::Participants {
+            client_id: client_id.to_string(),
+            participants: self.participants.clone(),
+            driver: self.driver.clone(),
+            single_driver: self.single_driver,
+        }
+    }
+
+    // 客户端加入，单人操控模式下无人操控时由第一个可输入的客户端操控
+    pub fn join(&mut self, participant: Participant) -> Vec<ControlEvent> {
+        let mut events = vec![ControlEvent::ParticipantJoined { participant: participant.clone() }];
+        let becomes_driver = self.single_driver && self.driver.is_none() && participant.can_write();
+        if becomes_driver {
+            self.driver = Some(participant.client_id.clone());
+        }
+        self.participants.push(participant);
+        if becomes_driver {
+            events.push(self.driver_changed());
+        }
+        events
+    }
+
+    // 客户端离开，操控者离开时控制权交给最早的请求者，没有请求时交给最早加入的可输入客户端
+    pub fn leave(&mut self, client_id: &str) -> Vec<ControlEvent> {
+        let Some(index) = self.participants.iter().position(|participant| participant.client_id == client_id) else {
+            return Vec::new();
+        };
+        let participant = self.participants.remove(index);
+        self.requests.retain(|request| request != client_id);
+
+        let mut events = vec![ControlEvent::ParticipantLeft { participant }];
+        if self.driver.as_deref() == Some(client_id) {
+            self.driver = self.next_requester().or_else(|| {
+                self.participants.iter()
+                    .find(|participant| participant.can_write())
+                    .map(|participant| participant.client_id.clone())
+            });
+            events.push(self.driver_changed());
+        }
+        events
+    }
+
+    fn next_requester(&mut self) -> Option<String> {
+        (!self.requests.is_empty()).then(|| self.requests.remove(0))
+    }
+
+    // 客户端的输入是否应写入终端 - 单人操控模式下只接受操控者的输入，未连接的客户端（REST接口）只能在无人操控时输入
+    pub fn may_write(&self, client_id: &str) -> bool {
+        if !self.single_driver {
+            return true;
+        }
+        match self.driver.as_deref() {
+            Some(driver) => driver == client_id,
+            None => self.find(client_id).is_none(),
+        }
+    }
+
+    // 检查客户端能否参与控制权分配
+    fn check_driver_candidate(&self, client_id: &str) -> anyhow::Result<&Participant> {
+        if !self.single_driver {
+            anyhow::bail!("Session is not in single driver mode");
+        }
+        let participant = self.find(client_id)
+            .ok_or_else(|| anyhow::anyhow!("Client {} is not attached", client_id))?;
+        if !participant.can_write() {
+            anyhow::bail!("Viewers cannot take control");
+        }
+        Ok(participant)
+    }
+
+    // 请求控制权，无人操控时立即获得，否则通知其他客户端
+    pub fn request(&mut self, client_id: &str) -> anyhow::Result<Vec<ControlEvent>> {
+        let participant = self.check_driver_candidate(client_id)?.clone();
+        match self.driver.as_deref() {
+            None => {
+                self.driver = Some(client_id.to_string());
+                Ok(vec![self.driver_changed()])
+            },
+            Some(driver) if driver == client_id => Ok(Vec::new()),
+            Some(_) => {
+                if !self.requests.iter().any(|request| request == client_id) {
+                    self.requests.push(client_id.to_string());
+                }
+                Ok(vec![ControlEvent::ControlRequested { participant }])
+            },
+        }
+    }
+
+    // 直接取得控制权
+    pub fn take(&mut self, client_id: &str) -> anyhow::Result<Vec<ControlEvent>> {
+        self.check_driver_candidate(client_id)?;
+        if self.driver.as_deref() == Some(client_id) {
+            return Ok(Vec::new());
+        }
+        self.driver = Some(client_id.to_string());
+        self.requests.retain(|request| request != client_id);
+        Ok(vec![self.driver_changed()])
+    }
+
+    // 交出控制权，优先交给最早的请求者，没有请求时无人操控
+    pub fn release(&mut self, client_id: &str) -> anyhow::Result<Vec<ControlEvent>> {
+        if self.driver.as_deref() != Some(client_id) {
+            anyhow::bail!("Client {} does not hold control", client_id);
+        }
+        self.driver = self.next_requester();
+        Ok(vec![self.driver_changed()])
+    }
+
+    // 操控者将控制权交给指定客户端
+    pub fn grant(&mut self, client_id: &str, target_client_id: &str) -> anyhow::Result<Vec<ControlEvent>> {
+        if self.driver.as_deref() != Some(client_id) {
+            anyhow::bail!("Client {} does not hold control", client_id);
+        }
+        self.check_driver_candidate(target_client_id)?;
+        self.driver = Some(target_client_id.to_string());
+        self.requests.retain(|request| request != target_client_id);
+        Ok(vec![self.driver_changed()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::TransportKind;
+    use crate::session::share::ShareRole;
+
+    fn participant(client_id: &str, role: ShareRole) -> Participant {
+        Participant {
+            client_id: client_id.to_string(),
+            user_id: None,
+            transport: TransportKind::WebSocket,
+            role: Some(role),
+        }
+    }
+
+    fn driver_of(events: &[ControlEvent]) -> Option<Option<String>> {
+        events.iter().find_map(|event| match event {
+            ControlEvent::ControlChanged { driver } => Some(driver.as_ref().map(|driver| driver.client_id.clone())),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn first_writer_drives() {
+        let mut presence = Presence::new(true);
+        assert_eq!(driver_of(&presence.join(participant("viewer", ShareRole::Viewer))), None);
+        assert_eq!(driver_of(&presence.join(participant("a", ShareRole::Owner))), Some(Some("a".to_string())));
+        assert_eq!(driver_of(&presence.join(participant("b", ShareRole::Collaborator))), None);
+        assert_eq!(presence.driver.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn take_and_release() {
+        let mut presence = Presence::new(true);
+        presence.join(participant("a", ShareRole::Owner));
+        presence.join(participant("b", ShareRole::Collaborator));
+        presence.join(participant("viewer", ShareRole::Viewer));
+
+        assert!(presence.take("viewer").is_err());
+        assert!(presence.take("unknown").is_err());
+        assert_eq!(driver_of(&presence.take("b").unwrap()), Some(Some("b".to_string())));
+        assert!(presence.take("b").unwrap().is_empty());
+
+        assert!(presence.release("a").is_err());
+        assert_eq!(driver_of(&presence.release("b").unwrap()), Some(None));
+        assert!(presence.driver.is_none());
+    }
+
+    #[test]
+    fn control_passes_to_requesters() {
+        let mut presence = Presence::new(true);
+        presence.join(participant("a", ShareRole::Owner));
+        presence.join(participant("b", ShareRole::Collaborator));
+        presence.join(participant("c", ShareRole::Collaborator));
+
+        assert!(matches!(presence.request("c").unwrap()[..], [ControlEvent::ControlRequested { .. }]));
+        assert_eq!(driver_of(&presence.release("a").unwrap()), Some(Some("c".to_string())));
+
+        // 操控者离开且没有请求时交给最早加入的可输入客户端
+        assert_eq!(driver_of(&presence.leave("c")), Some(Some("a".to_string())));
+        assert_eq!(driver_of(&presence.grant("a", "b").unwrap()), Some(Some("b".to_string())));
+        assert!(presence.grant("a", "b").is_err());
+    }
+
+    #[test]
+    fn may_write() {
+        let mut presence = Presence::new(true);
+        // 无人连接时REST调用者可以输入
+        assert!(presence.may_write("rest"));
+
+        presence.join(participant("a", ShareRole::Owner));
+        presence.join(participant("b", ShareRole::Collaborator));
+        assert!(presence.may_write("a"));
+        assert!(!presence.may_write("b"));
+        assert!(!presence.may_write("rest"));
+
+        // 无人操控时只有未连接的客户端可以输入，已连接的客户端需要先取得控制权
+        presence.release("a").unwrap();
+        assert!(!presence.may_write("a"));
+        assert!(!presence.may_write("b"));
+        assert!(presence.may_write("rest"));
+    }
+
+    #[test]
+    fn shared_mode_allows_everyone() {
+        let mut presence = Presence::new(false);
+        presence.join(participant("a", ShareRole::Owner));
+        assert!(presence.driver.is_none());
+        assert!(presence.may_write("a"));
+        assert!(presence.may_write("rest"));
+        assert!(presence.take("a").is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::TerminalSize;
+
+// 录制文件扩展名
+const RECORDING_EXTENSION: &str = "cast";
+
+// asciicast v2 文件头
+#[derive(Serialize)]
+struct AsciicastHeader<'a> {
+    version: u32,
+    width: u32,
+    height: u32,
+    timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    env: AsciicastEnv<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct AsciicastEnv<'a> {
+    shell: &'a str,
+    term: &'a str,
+}
+
+// 会话录制器 - 以asciinema v2格式写入输出、输入和调整大小事件
+pub struct SessionRecorder {
+    file: Mutex<File>,
+    // 录制开始时间（Unix时间，秒），事件时间戳相对于该时间
+    started_at: f64,
+    // 是否记录输入事件
+    record_input: bool,
+}
+
+impl SessionRecorder {
+    // 创建新录制文件并写入文件头
+    pub fn create(
+        directory: &str,
+        session_id: &str,
+        terminal_size: &TerminalSize,
+        shell: &str,
+        title: Option<&str>,
+        record_input: bool,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let path = recording_path(directory, session_id)?;
+        let started_at = unix_time_secs();
+
+        let header = AsciicastHeader {
+            version: 2,
+            width: terminal_size.columns,
+            height: terminal_size.rows,
+            timestamp: started_at as u64,
+            title,
+            env: AsciicastEnv {
+                shell,
+                term: "xterm-256color",
+            },
+        };
+        let mut file = File::create(&path)?;
+        serde_json::to_writer(&mut file, &header)?;
+        file.write_all(b"\n")?;
+
+        log::info!("Recording session {} to {:?}", session_id, path);
+        Ok(Self {
+            file: Mutex::new(file),
+            started_at,
+            record_input,
+        })
+    }
+
+    // 继续写入已有的录制文件（服务器重启后重新接管会话时），时间戳沿用原文件头
+    pub fn resume(directory: &str, session_id: &str, record_input: bool) -> anyhow::Result<Option<Self>> {
+        let path = recording_path(directory, session_id)?;
+        let header = match File::open(&path) {
+            Ok(file) => {
+                let mut line = String::new();
+                BufReader::new(file).read_line(&mut line)?;
+                serde_json::from_str::<serde_json::Value>(&line)?
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let started_at = header.get("timestamp").and_then(|timestamp| timestamp.as_f64()).unwrap_or_else(unix_time_secs);
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        log::info!("Resumed recording of session {} in {:?}", session_id, path);
+        Ok(Some(Self {
+            file: Mutex::new(file),
+            started_at,
+            record_input,
+        }))
+    }
+
+    // 记录终端输出
+    pub fn record_output(&self, data: &str) {
+        self.write_event("o", data);
+    }
+
+    // 记录终端输入（需在配置中开启）
+    pub fn record_input(&self, data: &str) {
+        if self.record_input {
+            self.write_event("i", data);
+        }
+    }
+
+    // 记录终端大小变化
+    pub fn record_resize(&self, columns: u32, rows: u32) {
+        self.write_event("r", &format!("{}x{}", columns, rows));
+    }
+
+    // 写入一个事件：[相对时间, 类型, 数据]，每个事件立即落盘，崩溃时不丢失已录制内容
+    fn write_event(&self, code: &str, data: &str) {
+        let elapsed = (unix_time_secs() - self.started_at).max(0.0);
+        let mut line = match serde_json::to_vec(&(elapsed, code, data)) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to encode recording event: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(&line) {
+            log::error!("Failed to write recording event: {}", e);
+        }
+    }
+}
+
+// 录制事件
+#[derive(Debug, Clone)]
+pub struct RecordingEvent {
+    // 相对录制开始的时间（秒）
+    pub time: f64,
+    // 事件类型：o（输出）、i（输入）、r（调整大小）、m（标记）
+    pub code: String,
+    pub data: String,
+}
+
+impl RecordingEvent {
+    // 解析调整大小事件的 "列x行" 数据
+    pub fn resize(&self) -> Option<(u32, u32)> {
+        if self.code != "r" {
+            return None;
+        }
+        let (columns, rows) = self.data.split_once('x')?;
+        Some((columns.parse().ok()?, rows.parse().ok()?))
+    }
+}
+
+// 已录制的会话，从.cast文件加载
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: u64,
+    pub title: Option<String>,
+    pub events: Vec<RecordingEvent>,
+}
+
+#[derive(Deserialize)]
+struct RecordedHeader {
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    timestamp: u64,
+    title: Option<String>,
+}
+
+impl Recording {
+    // 加载录制文件，跳过崩溃时可能写了一半的事件
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header_line = lines.next().ok_or_else(|| anyhow::anyhow!("Empty recording {:?}", path))??;
+        let header: RecordedHeader = serde_json::from_str(&header_line)
+            .map_err(|e| anyhow::anyhow!("Invalid recording header in {:?}: {}", path, e))?;
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            match serde_json::from_str::<(f64, String, String)>(&line) {
+                Ok((time, code, data)) => events.push(RecordingEvent { time, code, data }),
+                Err(e) => log::debug!("Skipping invalid recording event in {:?}: {}", path, e),
+            }
+        }
+
+        Ok(Self {
+            width: header.width,
+            height: header.height,
+            timestamp: header.timestamp,
+            title: header.title,
+            events,
+        })
+    }
+
+    // 录制时长（秒）
+    pub fn duration(&self) -> f64 {
+        self.events.last().map(|event| event.time).unwrap_or_default()
+    }
+
+    // 录制摘要
+    pub fn summary(&self, session_id: &str) -> RecordingSummary {
+        let count = |code: &str| self.events.iter().filter(|event| event.code == code).count();
+        RecordingSummary {
+            session_id: session_id.to_string(),
+            width: self.width,
+            height: self.height,
+            timestamp: self.timestamp,
+            title: self.title.clone(),
+            duration_ms: (self.duration() * 1000.0) as u64,
+            output_events: count("o"),
+            input_events: count("i"),
+            resize_events: count("r"),
+        }
+    }
+}
+
+// 录制摘要
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingSummary {
+    pub session_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: u64,
+    pub title: Option<String>,
+    pub duration_ms: u64,
+    pub output_events: usize,
+    pub input_events: usize,
+    pub resize_events: usize,
+}
+
+// 录制文件信息
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingInfo {
+    pub session_id: String,
+    pub file_name: String,
+    pub size: u64,
+    pub modified_at: u64,
+}
+
+// 列出录制目录中的所有录制文件，按修改时间排序
+pub fn list_recordings(directory: &str) -> anyhow::Result<Vec<RecordingInfo>> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut recordings = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(RECORDING_EXTENSION) {
+            continue;
+        }
+        let (Some(session_id), Ok(metadata)) = (path.file_stem().and_then(|stem| stem.to_str()), entry.metadata()) else { continue };
+        let modified_at = metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|modified| modified.as_millis() as u64)
+            .unwrap_or_default();
+
+        recordings.push(RecordingInfo {
+            session_id: session_id.to_string(),
+            file_name: format!("{}.{}", session_id, RECORDING_EXTENSION),
+            size: metadata.len(),
+            modified_at,
+        });
+    }
+    recordings.sort_by_key(|recording| recording.modified_at);
+    Ok(recordings)
+}
+
+// 会话录制文件路径，拒绝可能造成路径穿越的会话ID
+pub fn recording_path(directory: &str, session_id: &str) -> anyhow::Result<PathBuf> {
+    if session_id.is_empty() || !session_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Invalid session id: {}", session_id);
+    }
+    Ok(Path::new(directory).join(format!("{}.{}", session_id, RECORDING_EXTENSION)))
+}
+
+// 当前Unix时间（秒，带小数）
+fn unix_time_secs() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+}
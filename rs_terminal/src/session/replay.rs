@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::protocol::message::{ControlEvent, ServerMessage};
+use crate::session::recording::Recording;
+
+// 播放倍速范围
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 32.0;
+
+// 跳转时先完全重置客户端终端，再重放目标位置之前的输出
+const TERMINAL_RESET: &str = "\x1bc";
+
+// 回放控制命令
+#[derive(Debug, Clone, Copy)]
+pub enum ReplayCommand {
+    Pause,
+    Resume,
+    Seek(Duration),
+    Speed(f64),
+}
+
+// 限制播放倍速，非法值视为1倍速
+pub fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_finite() && speed > 0.0 {
+        speed.clamp(MIN_SPEED, MAX_SPEED)
+    } else {
+        1.0
+    }
+}
+
+// 回放器 - 按录制时的节奏将事件发送给客户端
+struct ReplayPlayer {
+    recording: Recording,
+    output_tx: mpsc::Sender<ServerMessage>,
+    // 下一个待发送的事件
+    index: usize,
+    // anchor时刻对应的录制时间（秒）
+    position: f64,
+    anchor: Instant,
+    paused: bool,
+    speed: f64,
+    finished: bool,
+}
+
+impl ReplayPlayer {
+    // 将录制时间推进到当前时刻
+    fn sync_position(&mut self) {
+        if !self.paused {
+            self.position += self.anchor.elapsed().as_secs_f64() * self.speed;
+        }
+        self.anchor = Instant::now();
+    }
+
+    // 距离下一个事件的等待时间，暂停或播放结束时为None
+    fn next_delay(&self) -> Option<Duration> {
+        if self.paused {
+            return None;
+        }
+        let event = self.recording.events.get(self.index)?;
+        Some(Duration::from_secs_f64(((event.time - self.position) / self.speed).max(0.0)))
+    }
+
+    async fn send(&self, message: ServerMessage) -> anyhow::Result<()> {
+        self.output_tx.send(message).await.map_err(|_| anyhow::anyhow!("Replay client disconnected"))
+    }
+
+    // 发送输出，过长时拆分为多条消息
+    async fn send_output(&self, output: &str) -> anyhow::Result<()> {
+        for message in ServerMessage::output_chunks(output) {
+            self.send(message).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_status(&self) -> anyhow::Result<()> {
+        self.send(ServerMessage::Control(ControlEvent::ReplayStatus {
+            position_ms: (self.position * 1000.0) as u64,
+            duration_ms: (self.recording.duration() * 1000.0) as u64,
+            paused: self.paused,
+            speed: self.speed,
+        })).await
+    }
+
+    // 发送所有已到时间的事件，相邻的输出合并为一条消息
+    async fn emit_due_events(&mut self) -> anyhow::Result<()> {
+        let mut output = String::new();
+        while let Some(event) = self.recording.events.get(self.index) {
+            if event.time > self.position {
+                break;
+            }
+            self.index += 1;
+
+            if event.code == "o" {
+                output.push_str(&event.data);
+            } else if let Some((columns, rows)) = event.resize() {
+                if !output.is_empty() {
+                    self.send_output(&std::mem::take(&mut output)).await?;
+                }
+                self.send(ServerMessage::Control(ControlEvent::Resize { columns, rows })).await?;
+            }
+        }
+        if !output.is_empty() {
+            self.send_output(&output).await?;
+        }
+
+        if self.index >= self.recording.events.len() && !self.finished {
+            self.finished = true;
+            self.send(ServerMessage::Control(ControlEvent::ReplayFinished)).await?;
+        }
+        Ok(())
+    }
+
+    // 跳转到指定位置 - 重置终端后立即重放之前的全部输出
+    async fn seek(&mut self, target: f64) -> anyhow::Result<()> {
+        let target = target.clamp(0.0, self.recording.duration());
+        let (mut columns, mut rows) = (self.recording.width, self.recording.height);
+        let mut output = TERMINAL_RESET.to_string();
+        let mut index = 0;
+
+        for event in self.recording.events.iter().take_while(|event| event.time <= target) {
+            if event.code == "o" {
+                output.push_str(&event.data);
+            } else if let Some(size) = event.resize() {
+                (columns, rows) = size;
+            }
+            index += 1;
+        }
+
+        self.index = index;
+        self.position = target;
+        self.anchor = Instant::now();
+        self.finished = false;
+        self.send(ServerMessage::Control(ControlEvent::Resize { columns, rows })).await?;
+        self.send_output(&output).await
+    }
+
+    async fn apply(&mut self, command: ReplayCommand) -> anyhow::Result<()> {
+        self.sync_position();
+        match command {
+            ReplayCommand::Pause => self.paused = true,
+            ReplayCommand::Resume => self.paused = false,
+            ReplayCommand::Seek(position) => self.seek(position.as_secs_f64()).await?,
+            ReplayCommand::Speed(speed) => self.speed = clamp_speed(speed),
+        }
+        self.send_status().await
+    }
+}
+
+// 回放录制 - 直到客户端断开或命令通道关闭
+pub async fn play(
+    recording: Recording,
+    output_tx: mpsc::Sender<ServerMessage>,
+    mut commands: mpsc::Receiver<ReplayCommand>,
+    speed: f64,
+) -> anyhow::Result<()> {
+    let mut player = ReplayPlayer {
+        output_tx: output_tx.clone(),
+        index: 0,
+        position: 0.0,
+        anchor: Instant::now(),
+        paused: false,
+        speed: clamp_speed(speed),
+        finished: false,
+        recording,
+    };
+
+    player.send(ServerMessage::Control(ControlEvent::Resize {
+        columns: player.recording.width,
+        rows: player.recording.height,
+    })).await?;
+    player.send_status().await?;
+
+    loop {
+        player.sync_position();
+        let delay = player.next_delay();
+
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(command) => player.apply(command).await?,
+                None => return Ok(()),
+            },
+            _ = tokio::time::sleep(delay.unwrap_or_default()), if delay.is_some() => {
+                player.sync_position();
+                player.emit_due_events().await?;
+            },
+            _ = output_tx.closed() => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{RegistryConfig, TerminalSize};
+use crate::session::session::SessionStatus;
+
+// 会话状态变更记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusTransition {
+    pub status: SessionStatus,
+    pub at: u64,
+    pub reason: String,
+}
+
+// 会话记录 - 会话的元数据、归属和生命周期，会话终止后仍可查询
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRecord {
+    pub id: String,
+    pub user_id: String,
+    pub title: Option<String>,
+    pub working_directory: String,
+    pub shell_type: String,
+    pub command: Vec<String>,
+    pub status: SessionStatus,
+    pub terminal_size: TerminalSize,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub last_active_time: u64,
+    pub expired_at: u64,
+    pub terminated_at: Option<u64>,
+    pub termination_reason: Option<String>,
+    pub exit_code: Option<u32>,
+    pub transitions: Vec<StatusTransition>,
+    // shell集成上报的当前目录，活动会话取自内存，终止时保存最后的目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_directory: Option<String>,
+}
+
+impl SessionRecord {
+    // 记录状态变更
+    pub fn transition(&mut self, status: SessionStatus, reason: &str) {
+        let now = now_millis();
+        self.status = status;
+        self.updated_at = now;
+        if status == SessionStatus::Terminated {
+            self.terminated_at = Some(now);
+            self.termination_reason = Some(reason.to_string());
+        }
+        self.transitions.push(StatusTransition {
+            status,
+            at: now,
+            reason: reason.to_string(),
+        });
+    }
+}
+
+// 会话查询条件
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub status: Option<SessionStatus>,
+    pub user_id: Option<String>,
+}
+
+impl SessionFilter {
+    fn matches(&self, record: &SessionRecord) -> bool {
+        self.status.is_none_or(|status| record.status == status)
+            && self.user_id.as_ref().is_none_or(|user_id| &record.user_id == user_id)
+    }
+}
+
+// 会话存储 - 可替换的会话记录持久化后端
+pub trait SessionRepository: Send + Sync {
+    // 保存会话记录（新增或覆盖）
+    fn save(&self, record: &SessionRecord) -> anyhow::Result<()>;
+
+    // 按ID查找会话记录
+    fn find(&self, session_id: &str) -> anyhow::Result<Option<SessionRecord>>;
+
+    // 查询会话记录，按创建时间排序
+    fn list(&self, filter: &SessionFilter) -> anyhow::Result<Vec<SessionRecord>>;
+}
+
+// 根据配置创建会话存储
+pub fn open(config: &RegistryConfig) -> anyhow::Result<Arc<dyn SessionRepository>> {
+    match config.backend.as_str() {
+        "memory" => Ok(Arc::new(InMemorySessionRepository::default())),
+        "file" => Ok(Arc::new(FileSessionRepository::open(&config.path)?)),
+        other => anyhow::bail!("Unknown session registry backend '{}', expected 'memory' or 'file'", other),
+    }
+}
+
+// 按条件筛选并排序
+fn select(records: &HashMap<String, SessionRecord>, filter: &SessionFilter) -> Vec<SessionRecord> {
+    let mut selected: Vec<SessionRecord> = records.values().filter(|record| filter.matches(record)).cloned().collect();
+    selected.sort_by_key(|record| record.created_at);
+    selected
+}
+
+// 内存会话存储 - 服务器重启后记录丢失
+#[derive(Default)]
+pub struct InMemorySessionRepository {
+    records: RwLock<HashMap<String, SessionRecord>>,
+}
+
+impl SessionRepository for InMemorySessionRepository {
+    fn save(&self, record: &SessionRecord) -> anyhow::Result<()> {
+        self.records.write().unwrap().insert(record.id.clone(), record.clone());
+        Ok(())
+    }
+
+    fn find(&self, session_id: &str) -> anyhow::Result<Option<SessionRecord>> {
+        Ok(self.records.read().unwrap().get(session_id).cloned())
+    }
+
+    fn list(&self, filter: &SessionFilter) -> anyhow::Result<Vec<SessionRecord>> {
+        Ok(select(&self.records.read().unwrap(), filter))
+    }
+}
+
+// 文件会话存储 - JSON Lines追加日志，每次保存追加一行完整记录，
+// 启动时以最后一行为准加载并压缩文件
+pub struct FileSessionRepository {
+    records: RwLock<HashMap<String, SessionRecord>>,
+    file: Mutex<File>,
+}
+
+impl FileSessionRepository {
+    // 打开（或创建）存储文件
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let records = Self::load(&path)?;
+        Self::compact(&path, &records)?;
+        log::info!("Loaded {} session records from {:?}", records.len(), path);
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            records: RwLock::new(records),
+            file: Mutex::new(file),
+        })
+    }
+
+    // 读取所有记录，跳过崩溃时可能写了一半的行
+    fn load(path: &Path) -> anyhow::Result<HashMap<String, SessionRecord>> {
+        let mut records = HashMap::new();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(records),
+            Err(e) => return Err(e.into()),
+        };
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<SessionRecord>(&line) {
+                Ok(record) => {
+                    records.insert(record.id.clone(), record);
+                },
+                Err(e) => log::warn!("Skipping invalid session record at {:?}:{}: {}", path, index + 1, e),
+            }
+        }
+        Ok(records)
+    }
+
+    // 重写文件，每个会话只保留最新记录
+    fn compact(path: &Path, records: &HashMap<String, SessionRecord>) -> anyhow::Result<()> {
+        let temporary_path = path.with_extension("tmp");
+        let mut file = File::create(&temporary_path)?;
+        for record in select(records, &SessionFilter::default()) {
+            serde_json::to_writer(&mut file, &record)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+}
+
+impl SessionRepository for FileSessionRepository {
+    fn save(&self, record: &SessionRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        // 先写文件再更新内存，写入失败时两者保持一致
+        {
+            let mut file = self.file.lock().unwrap();
+            file.write_all(&line)?;
+            file.flush()?;
+        }
+        self.records.write().unwrap().insert(record.id.clone(), record.clone());
+        Ok(())
+    }
+
+    fn find(&self, session_id: &str) -> anyhow::Result<Option<SessionRecord>> {
+        Ok(self.records.read().unwrap().get(session_id).cloned())
+    }
+
+    fn list(&self, filter: &SessionFilter) -> anyhow::Result<Vec<SessionRecord>> {
+        Ok(select(&self.records.read().unwrap(), filter))
+    }
+}
+
+// 当前时间（毫秒）
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
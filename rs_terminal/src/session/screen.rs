@@ -0,0 +1,1125 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, Parser, Perform};
+
+use crate::config::TerminalSize;
+
+// 制表位间隔
+const TAB_WIDTH: usize = 8;
+
+// 颜色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    #[default]
+    Default,
+    // 256色调色板，0-15为标准色和高亮色
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+// 字符样式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+impl Style {
+    // 生成从默认样式切换到该样式的SGR参数
+    fn sgr_params(&self) -> String {
+        let mut params = vec!["0".to_string()];
+        for (enabled, code) in [
+            (self.bold, "1"), (self.dim, "2"), (self.italic, "3"), (self.underline, "4"),
+            (self.blink, "5"), (self.inverse, "7"), (self.hidden, "8"), (self.strikethrough, "9"),
+        ] {
+            if enabled {
+                params.push(code.to_string());
+            }
+        }
+        for (color, base) in [(self.foreground, 30), (self.background, 40)] {
+            match color {
+                Color::Default => {},
+                Color::Indexed(index) if index < 8 => params.push((base + index as u32).to_string()),
+                Color::Indexed(index) if index < 16 => params.push((base + 60 + index as u32 - 8).to_string()),
+                Color::Indexed(index) => params.push(format!("{};5;{}", base + 8, index)),
+                Color::Rgb(r, g, b) => params.push(format!("{};2;{};{};{}", base + 8, r, g, b)),
+            }
+        }
+        params.join(";")
+    }
+}
+
+// 屏幕上的一个字符格
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub character: char,
+    pub style: Style,
+    // 显示宽度：1为普通字符，2为宽字符，0为宽字符右半部分的占位格
+    pub width: u8,
+}
+
+impl Cell {
+    fn blank(style: Style) -> Self {
+        Self {
+            character: ' ',
+            style: Style { background: style.background, ..Style::default() },
+            width: 1,
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(Style::default())
+    }
+}
+
+// 屏幕上的一行
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    // 该行因自动换行延续到下一行
+    pub wrapped: bool,
+}
+
+impl Row {
+    fn new(columns: usize, style: Style) -> Self {
+        Self {
+            cells: vec![Cell::blank(style); columns],
+            wrapped: false,
+        }
+    }
+
+    // 去掉行尾空白后的有效格数
+    pub fn trimmed_len(&self) -> usize {
+        self.cells.iter().rposition(|cell| *cell != Cell::default()).map_or(0, |index| index + 1)
+    }
+
+    // 行的纯文本内容，去掉行尾空白
+    pub fn text(&self) -> String {
+        self.cells[..self.trimmed_len()].iter()
+            .filter(|cell| cell.width > 0)
+            .map(|cell| cell.character)
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    // 带SGR样式的行内容，结尾恢复默认样式
+    pub fn write_ansi(&self, output: &mut String) {
+        let mut current = Style::default();
+        for cell in &self.cells[..self.trimmed_len()] {
+            if cell.width == 0 {
+                continue;
+            }
+            if cell.style != current {
+                let _ = write!(output, "\x1b[{}m", cell.style.sgr_params());
+                current = cell.style;
+            }
+            output.push(cell.character);
+        }
+        if current != Style::default() {
+            output.push_str("\x1b[0m");
+        }
+    }
+}
+
+// 光标
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cursor {
+    pub row: usize,
+    pub column: usize,
+    // 当前写入字符使用的样式
+    pub style: Style,
+    // 已写到行尾，下一个字符写入前换行
+    pending_wrap: bool,
+    // 使用DEC特殊图形字符集（画线字符）
+    line_drawing: bool,
+}
+
+// shell集成标记，来自shell通过OSC 133上报的提示符和命令边界及OSC 7上报的当前目录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellMark {
+    // 开始显示提示符
+    PromptStart,
+    // 命令开始执行，command为提示符结束到此时输入的命令行
+    CommandStart { command: Option<String> },
+    // 命令执行结束
+    CommandFinished { exit_code: Option<i32> },
+    WorkingDirectory(String),
+}
+
+// 终端模式
+#[derive(Debug, Clone, Copy)]
+pub struct Modes {
+    pub application_cursor: bool,
+    pub application_keypad: bool,
+    pub bracketed_paste: bool,
+    pub cursor_visible: bool,
+    pub auto_wrap: bool,
+    pub origin: bool,
+    pub insert: bool,
+    // 鼠标跟踪模式（1000/1002/1003），0表示关闭
+    pub mouse_tracking: u16,
+    pub sgr_mouse: bool,
+    pub alternate_screen: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            application_cursor: false,
+            application_keypad: false,
+            bracketed_paste: false,
+            cursor_visible: true,
+            auto_wrap: true,
+            origin: false,
+            insert: false,
+            mouse_tracking: 0,
+            sgr_mouse: false,
+            alternate_screen: false,
+        }
+    }
+}
+
+// 屏幕模型 - 当前屏幕网格、光标、模式、备用屏幕和滚动历史
+pub struct Screen {
+    columns: usize,
+    rows: usize,
+    // 当前显示的网格（主屏幕或备用屏幕）
+    grid: Vec<Row>,
+    // 备用屏幕激活时保存的主屏幕
+    primary: Option<Vec<Row>>,
+    // 滚出主屏幕顶部的行，最早的在前
+    scrollback: VecDeque<Row>,
+    scrollback_limit: usize,
+    // 已从滚动历史中丢弃的行数，用于计算稳定的行号
+    discarded_lines: u64,
+    cursor: Cursor,
+    saved_cursor: Option<Cursor>,
+    // 滚动区域，闭区间
+    scroll_top: usize,
+    scroll_bottom: usize,
+    modes: Modes,
+    title: String,
+    // 提示符结束（OSC 133;B）时光标的位置：行号（从0开始，与滚动历史连续编号）和列
+    command_mark: Option<(u64, usize)>,
+    // 尚未取走的shell集成标记
+    shell_marks: Vec<ShellMark>,
+}
+
+impl Screen {
+    fn new(size: &TerminalSize, scrollback_limit: usize) -> Self {
+        let columns = (size.columns as usize).max(1);
+        let rows = (size.rows as usize).max(1);
+        Self {
+            columns,
+            rows,
+            grid: vec![Row::new(columns, Style::default()); rows],
+            primary: None,
+            scrollback: VecDeque::new(),
+            scrollback_limit,
+            discarded_lines: 0,
+            cursor: Cursor::default(),
+            saved_cursor: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            modes: Modes::default(),
+            title: String::new(),
+            command_mark: None,
+            shell_marks: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> TerminalSize {
+        TerminalSize { columns: self.columns as u32, rows: self.rows as u32 }
+    }
+
+    // 当前显示的各行
+    pub fn visible_rows(&self) -> &[Row] {
+        &self.grid
+    }
+
+    // 主屏幕最近的若干行滚动历史，最早的在前；备用屏幕激活时同样返回主屏幕的历史
+    pub fn scrollback(&self, limit: usize) -> impl Iterator<Item = &Row> {
+        self.scrollback.iter().skip(self.scrollback.len().saturating_sub(limit))
+    }
+
+    // 主屏幕保留的全部输出：滚动历史和主屏幕内容，以及第一行的行号（从1开始）
+    pub fn history(&self) -> (u64, impl Iterator<Item = &Row>) {
+        let primary = self.primary.as_ref().unwrap_or(&self.grid);
+        (self.discarded_lines + 1, self.scrollback.iter().chain(primary.iter()))
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    pub fn modes(&self) -> &Modes {
+        &self.modes
+    }
+
+    // 程序通过OSC 0/2设置的窗口标题
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    // 调整大小，不重排已换行的内容；行数减少时光标以上的行进入滚动历史
+    fn resize(&mut self, size: &TerminalSize) {
+        let columns = (size.columns as usize).max(1);
+        let rows = (size.rows as usize).max(1);
+
+        let overflow = (self.cursor.row + 1).saturating_sub(rows);
+        let removed: Vec<Row> = self.grid.drain(..overflow).collect();
+        if !self.modes.alternate_screen {
+            self.scrollback.extend(removed);
+            self.trim_scrollback();
+        }
+        self.cursor.row -= overflow;
+
+        for grid in std::iter::once(&mut self.grid).chain(self.primary.as_mut()) {
+            grid.truncate(rows);
+            grid.resize(rows, Row::new(columns, Style::default()));
+            for row in grid.iter_mut() {
+                row.cells.resize(columns, Cell::default());
+            }
+        }
+        self.columns = columns;
+        self.rows = rows;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.column = self.cursor.column.min(columns - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn trim_scrollback(&mut self) {
+        let excess = self.scrollback.len().saturating_sub(self.scrollback_limit);
+        self.scrollback.drain(..excess);
+        self.discarded_lines += excess as u64;
+    }
+
+    fn blank_row(&self) -> Row {
+        Row::new(self.columns, self.cursor.style)
+    }
+
+    // 滚动区域内容上移，主屏幕滚出顶部的行进入滚动历史
+    fn scroll_up(&mut self, count: usize) {
+        for _ in 0..count.min(self.scroll_bottom - self.scroll_top + 1) {
+            let row = self.grid.remove(self.scroll_top);
+            self.grid.insert(self.scroll_bottom, self.blank_row());
+            if self.scroll_top == 0 && !self.modes.alternate_screen {
+                self.scrollback.push_back(row);
+            }
+        }
+        self.trim_scrollback();
+    }
+
+    // 滚动区域内容下移
+    fn scroll_down(&mut self, count: usize) {
+        for _ in 0..count.min(self.scroll_bottom - self.scroll_top + 1) {
+            self.grid.remove(self.scroll_bottom);
+            self.grid.insert(self.scroll_top, self.blank_row());
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+    }
+
+    // 移动光标，原点模式下行号相对滚动区域
+    fn move_to(&mut self, row: usize, column: usize) {
+        let (top, bottom) = if self.modes.origin { (self.scroll_top, self.scroll_bottom) } else { (0, self.rows - 1) };
+        self.cursor.row = (top + row).min(bottom);
+        self.cursor.column = column.min(self.columns - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_row(&mut self, row: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_column(&mut self, column: usize) {
+        self.cursor.column = column.min(self.columns - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    // 清除当前行的 [start, end) 列
+    fn erase_cells(&mut self, start: usize, end: usize) {
+        let blank = Cell::blank(self.cursor.style);
+        let row = &mut self.grid[self.cursor.row];
+        for cell in &mut row.cells[start.min(self.columns)..end.min(self.columns)] {
+            *cell = blank;
+        }
+    }
+
+    fn erase_rows(&mut self, start: usize, end: usize) {
+        for index in start..end.min(self.rows) {
+            self.grid[index] = self.blank_row();
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        match mode {
+            0 => {
+                self.erase_cells(self.cursor.column, self.columns);
+                self.erase_rows(self.cursor.row + 1, self.rows);
+            },
+            1 => {
+                self.erase_rows(0, self.cursor.row);
+                self.erase_cells(0, self.cursor.column + 1);
+            },
+            2 => self.erase_rows(0, self.rows),
+            3 => {
+                self.discarded_lines += self.scrollback.len() as u64;
+                self.scrollback.clear();
+            },
+            _ => {},
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        match mode {
+            0 => self.erase_cells(self.cursor.column, self.columns),
+            1 => self.erase_cells(0, self.cursor.column + 1),
+            2 => self.erase_cells(0, self.columns),
+            _ => {},
+        }
+    }
+
+    // 在光标处插入空白格，行尾内容被挤出
+    fn insert_cells(&mut self, count: usize) {
+        let blank = Cell::blank(self.cursor.style);
+        let column = self.cursor.column;
+        let cells = &mut self.grid[self.cursor.row].cells;
+        for _ in 0..count.min(self.columns - column) {
+            cells.pop();
+            cells.insert(column, blank);
+        }
+    }
+
+    fn delete_cells(&mut self, count: usize) {
+        let blank = Cell::blank(self.cursor.style);
+        let column = self.cursor.column;
+        let cells = &mut self.grid[self.cursor.row].cells;
+        for _ in 0..count.min(self.columns - column) {
+            cells.remove(column);
+            cells.push(blank);
+        }
+    }
+
+    // 在光标行插入空行，仅在滚动区域内生效
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom - self.cursor.row + 1) {
+            self.grid.remove(self.scroll_bottom);
+            self.grid.insert(self.cursor.row, self.blank_row());
+        }
+        self.cursor.column = 0;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom - self.cursor.row + 1) {
+            self.grid.remove(self.cursor.row);
+            self.grid.insert(self.scroll_bottom, self.blank_row());
+        }
+        self.cursor.column = 0;
+    }
+
+    fn enter_alternate_screen(&mut self, save_cursor: bool) {
+        if self.modes.alternate_screen {
+            return;
+        }
+        if save_cursor {
+            self.saved_cursor = Some(self.cursor);
+        }
+        let alternate = vec![Row::new(self.columns, Style::default()); self.rows];
+        self.primary = Some(std::mem::replace(&mut self.grid, alternate));
+        self.modes.alternate_screen = true;
+    }
+
+    fn leave_alternate_screen(&mut self, restore_cursor: bool) {
+        let Some(primary) = self.primary.take() else { return };
+        self.grid = primary;
+        self.modes.alternate_screen = false;
+        if restore_cursor {
+            self.restore_cursor();
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        if let Some(saved) = self.saved_cursor {
+            self.cursor = saved;
+            self.cursor.row = self.cursor.row.min(self.rows - 1);
+            self.cursor.column = self.cursor.column.min(self.columns - 1);
+        }
+    }
+
+    fn set_tab_stop_column(&mut self) {
+        let next = (self.cursor.column / TAB_WIDTH + 1) * TAB_WIDTH;
+        self.move_column(next);
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            1 => self.modes.application_cursor = enabled,
+            6 => {
+                self.modes.origin = enabled;
+                self.move_to(0, 0);
+            },
+            7 => self.modes.auto_wrap = enabled,
+            25 => self.modes.cursor_visible = enabled,
+            47 | 1047 if enabled => self.enter_alternate_screen(false),
+            47 | 1047 => self.leave_alternate_screen(false),
+            1049 if enabled => {
+                self.enter_alternate_screen(true);
+                self.move_to(0, 0);
+            },
+            1049 => self.leave_alternate_screen(true),
+            1000 | 1002 | 1003 => self.modes.mouse_tracking = if enabled { mode } else { 0 },
+            1006 => self.modes.sgr_mouse = enabled,
+            2004 => self.modes.bracketed_paste = enabled,
+            _ => log::trace!("Ignoring private mode {} = {}", mode, enabled),
+        }
+    }
+
+    // 处理SGR参数，支持 38;5;n、38;2;r;g;b 及冒号分隔的子参数形式
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let groups: Vec<&[u16]> = params.iter().collect();
+        if groups.is_empty() {
+            self.cursor.style = Style::default();
+            return;
+        }
+        let mut index = 0;
+        while index < groups.len() {
+            let group = groups[index];
+            let style = &mut self.cursor.style;
+            match group[0] {
+                0 => *style = Style::default(),
+                1 => style.bold = true,
+                2 => style.dim = true,
+                3 => style.italic = true,
+                4 => style.underline = group.get(1).is_none_or(|&kind| kind != 0),
+                5 | 6 => style.blink = true,
+                7 => style.inverse = true,
+                8 => style.hidden = true,
+                9 => style.strikethrough = true,
+                21 => style.underline = true,
+                22 => {
+                    style.bold = false;
+                    style.dim = false;
+                },
+                23 => style.italic = false,
+                24 => style.underline = false,
+                25 => style.blink = false,
+                27 => style.inverse = false,
+                28 => style.hidden = false,
+                29 => style.strikethrough = false,
+                code @ 30..=37 => style.foreground = Color::Indexed((code - 30) as u8),
+                39 => style.foreground = Color::Default,
+                code @ 40..=47 => style.background = Color::Indexed((code - 40) as u8),
+                49 => style.background = Color::Default,
+                code @ 90..=97 => style.foreground = Color::Indexed((code - 90 + 8) as u8),
+                code @ 100..=107 => style.background = Color::Indexed((code - 100 + 8) as u8),
+                code @ (38 | 48) => {
+                    // 子参数形式在同一组内，分号形式占用后续的组
+                    let (color, consumed) = if group.len() > 1 {
+                        // 冒号形式的真彩色可带色彩空间ID：38:2:<色彩空间>:r:g:b
+                        let params = match &group[1..] {
+                            [2, _, rgb @ ..] if rgb.len() >= 3 => [&[2], rgb].concat(),
+                            params => params.to_vec(),
+                        };
+                        (parse_extended_color(&params), 0)
+                    } else {
+                        let rest: Vec<u16> = groups[index + 1..].iter().map(|group| group[0]).collect();
+                        let color = parse_extended_color(&rest);
+                        let consumed = match rest.first() {
+                            Some(5) => 2,
+                            Some(2) => 4,
+                            _ => 0,
+                        };
+                        (color, consumed)
+                    };
+                    if let Some(color) = color {
+                        if code == 38 {
+                            style.foreground = color;
+                        } else {
+                            style.background = color;
+                        }
+                    }
+                    index += consumed;
+                },
+                code => log::trace!("Ignoring SGR parameter {}", code),
+            }
+            index += 1;
+        }
+    }
+
+    fn reset(&mut self) {
+        let scrollback = std::mem::take(&mut self.scrollback);
+        let discarded_lines = self.discarded_lines;
+        let shell_marks = std::mem::take(&mut self.shell_marks);
+        *self = Screen::new(&self.size(), self.scrollback_limit);
+        self.scrollback = scrollback;
+        self.discarded_lines = discarded_lines;
+        self.shell_marks = shell_marks;
+    }
+    
+    // 光标所在行的行号，与滚动历史连续编号
+    fn cursor_line(&self) -> u64 {
+        self.discarded_lines + self.scrollback.len() as u64 + self.cursor.row as u64
+    }
+    
+    // 从标记位置到光标之间的文本，自动换行的行直接拼接
+    fn text_since(&self, (mark_line, mark_column): (u64, usize)) -> String {
+        let cursor_line = self.cursor_line();
+        let mut text = String::new();
+        for (line, row) in (self.discarded_lines..).zip(self.scrollback.iter().chain(self.grid.iter())) {
+            if line < mark_line {
+                continue;
+            }
+            if line > cursor_line {
+                break;
+            }
+            let start = if line == mark_line { mark_column } else { 0 };
+            let end = if line == cursor_line { self.cursor.column } else { row.cells.len() };
+            let segment: String = row.cells[start.min(end)..end].iter()
+                .filter(|cell| cell.width > 0)
+                .map(|cell| cell.character)
+                .collect();
+            if row.wrapped || line == cursor_line {
+                text.push_str(&segment);
+            } else {
+                text.push_str(segment.trim_end());
+                text.push('\n');
+            }
+        }
+        text.trim().to_string()
+    }
+    
+    // 处理OSC 133提示符和命令标记，备用屏幕中的标记忽略
+    fn shell_mark(&mut self, kind: &[u8], params: &[&[u8]]) {
+        if self.modes.alternate_screen {
+            return;
+        }
+        match kind {
+            b"A" => {
+                self.command_mark = None;
+                self.shell_marks.push(ShellMark::PromptStart);
+            },
+            b"B" => self.command_mark = Some((self.cursor_line(), self.cursor.column)),
+            b"C" => {
+                let command = self.command_mark.take()
+                    .map(|mark| self.text_since(mark))
+                    .filter(|command| !command.is_empty());
+                self.shell_marks.push(ShellMark::CommandStart { command });
+            },
+            b"D" => {
+                let exit_code = params.first().and_then(|code| std::str::from_utf8(code).ok()?.parse().ok());
+                self.shell_marks.push(ShellMark::CommandFinished { exit_code });
+            },
+            _ => {},
+        }
+    }
+
+    // 生成重绘当前状态的转义序列：先输出主屏幕的滚动历史和内容，再恢复模式、备用屏幕和光标
+    pub fn repaint(&self) -> String {
+        let mut output = String::from("\x1bc");
+
+        let primary = self.primary.as_ref().unwrap_or(&self.grid);
+        let lines: Vec<&Row> = self.scrollback.iter().chain(primary.iter()).collect();
+        for (index, row) in lines.iter().enumerate() {
+            if index > 0 {
+                output.push_str("\r\n");
+            }
+            row.write_ansi(&mut output);
+        }
+
+        if self.modes.alternate_screen {
+            output.push_str("\x1b[?1049h");
+            for (index, row) in self.grid.iter().enumerate() {
+                let _ = write!(output, "\x1b[{};1H", index + 1);
+                row.write_ansi(&mut output);
+            }
+        }
+
+        if self.scroll_top != 0 || self.scroll_bottom != self.rows - 1 {
+            let _ = write!(output, "\x1b[{};{}r", self.scroll_top + 1, self.scroll_bottom + 1);
+        }
+        for (enabled, mode) in [
+            (self.modes.application_cursor, "1"),
+            (self.modes.origin, "6"),
+            (self.modes.bracketed_paste, "2004"),
+            (self.modes.sgr_mouse, "1006"),
+        ] {
+            if enabled {
+                let _ = write!(output, "\x1b[?{}h", mode);
+            }
+        }
+        if self.modes.mouse_tracking != 0 {
+            let _ = write!(output, "\x1b[?{}h", self.modes.mouse_tracking);
+        }
+        if !self.modes.auto_wrap {
+            output.push_str("\x1b[?7l");
+        }
+        if self.modes.insert {
+            output.push_str("\x1b[4h");
+        }
+        if self.modes.application_keypad {
+            output.push_str("\x1b=");
+        }
+        if !self.title.is_empty() {
+            let _ = write!(output, "\x1b]2;{}\x07", self.title);
+        }
+
+        let _ = write!(output, "\x1b[{};{}H", self.cursor.row + 1, self.cursor.column + 1);
+        let _ = write!(output, "\x1b[{}m", self.cursor.style.sgr_params());
+        if !self.modes.cursor_visible {
+            output.push_str("\x1b[?25l");
+        }
+        output
+    }
+}
+
+// 解析 5;n 或 2;r;g;b 形式的扩展颜色
+fn parse_extended_color(params: &[u16]) -> Option<Color> {
+    match params {
+        [5, index, ..] => Some(Color::Indexed(*index as u8)),
+        [2, r, g, b, ..] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+// DEC特殊图形字符集中的画线字符
+fn line_drawing_character(character: char) -> char {
+    match character {
+        'j' => '┘', 'k' => '┐', 'l' => '┌', 'm' => '└', 'n' => '┼',
+        'q' => '─', 't' => '├', 'u' => '┤', 'v' => '┴', 'w' => '┬', 'x' => '│',
+        'a' => '▒', '`' => '◆', 'f' => '°', 'g' => '±', '~' => '·',
+        'o' => '⎺', 'p' => '⎻', 'r' => '⎼', 's' => '⎽',
+        'y' => '≤', 'z' => '≥', '{' => 'π', '|' => '≠', '}' => '£',
+        other => other,
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, character: char) {
+        let character = if self.cursor.line_drawing { line_drawing_character(character) } else { character };
+        let width = match character.width() {
+            Some(width @ 1..=2) => width,
+            // 组合字符等零宽字符不占格
+            _ => return,
+        };
+
+        if self.cursor.pending_wrap || (width == 2 && self.cursor.column + 1 >= self.columns && self.columns > 1) {
+            if self.modes.auto_wrap {
+                self.grid[self.cursor.row].wrapped = true;
+                self.cursor.column = 0;
+                self.linefeed();
+            }
+            self.cursor.pending_wrap = false;
+        }
+        if self.modes.insert {
+            self.insert_cells(width);
+        }
+
+        let style = self.cursor.style;
+        let column = self.cursor.column;
+        let cells = &mut self.grid[self.cursor.row].cells;
+        cells[column] = Cell { character, style, width: width as u8 };
+        if width == 2 && column + 1 < self.columns {
+            cells[column + 1] = Cell { character: ' ', style, width: 0 };
+        }
+
+        if column + width >= self.columns {
+            self.cursor.column = self.columns - 1;
+            self.cursor.pending_wrap = true;
+        } else {
+            self.cursor.column = column + width;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x08 => {
+                self.cursor.column = self.cursor.column.saturating_sub(1);
+                self.cursor.pending_wrap = false;
+            },
+            0x09 => self.set_tab_stop_column(),
+            0x0a..=0x0c => self.linefeed(),
+            0x0d => {
+                self.cursor.column = 0;
+                self.cursor.pending_wrap = false;
+            },
+            0x0e => self.cursor.line_drawing = true,
+            0x0f => self.cursor.line_drawing = false,
+            _ => {},
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        match params {
+            [b"0" | b"2", title, ..] => self.title = String::from_utf8_lossy(title).into_owned(),
+            [b"7", url @ ..] => {
+                if let Some(path) = file_url_path(&url.join(&b';')) {
+                    self.shell_marks.push(ShellMark::WorkingDirectory(path));
+                }
+            },
+            [b"133", kind, rest @ ..] => self.shell_mark(kind, rest),
+            _ => {},
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        let values: Vec<u16> = params.iter().map(|group| group[0]).collect();
+        // 缺省或为0时使用默认值
+        let arg = |index: usize, default: u16| values.get(index).copied().filter(|&value| value != 0).unwrap_or(default) as usize;
+        let private = intermediates.first() == Some(&b'?');
+
+        match (action, private) {
+            ('A', false) => self.move_row(self.cursor.row.saturating_sub(arg(0, 1))),
+            ('B', false) | ('e', false) => self.move_row(self.cursor.row + arg(0, 1)),
+            ('C', false) | ('a', false) => self.move_column(self.cursor.column + arg(0, 1)),
+            ('D', false) => self.move_column(self.cursor.column.saturating_sub(arg(0, 1))),
+            ('E', false) => {
+                self.move_row(self.cursor.row + arg(0, 1));
+                self.cursor.column = 0;
+            },
+            ('F', false) => {
+                self.move_row(self.cursor.row.saturating_sub(arg(0, 1)));
+                self.cursor.column = 0;
+            },
+            ('G', false) | ('`', false) => self.move_column(arg(0, 1) - 1),
+            ('H', false) | ('f', false) => self.move_to(arg(0, 1) - 1, arg(1, 1) - 1),
+            ('d', false) => {
+                let column = self.cursor.column;
+                self.move_to(arg(0, 1) - 1, column);
+            },
+            ('J', _) => self.erase_in_display(values.first().copied().unwrap_or(0)),
+            ('K', _) => self.erase_in_line(values.first().copied().unwrap_or(0)),
+            ('L', false) => self.insert_lines(arg(0, 1)),
+            ('M', false) => self.delete_lines(arg(0, 1)),
+            ('@', false) => self.insert_cells(arg(0, 1)),
+            ('P', false) => self.delete_cells(arg(0, 1)),
+            ('X', false) => self.erase_cells(self.cursor.column, self.cursor.column + arg(0, 1)),
+            ('S', false) => self.scroll_up(arg(0, 1)),
+            ('T', false) => self.scroll_down(arg(0, 1)),
+            ('m', false) if intermediates.is_empty() => self.select_graphic_rendition(params),
+            ('r', false) => {
+                let top = arg(0, 1) - 1;
+                let bottom = values.get(1).copied().filter(|&value| value != 0).map_or(self.rows, usize::from).min(self.rows).saturating_sub(1);
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            },
+            ('h', true) => values.iter().for_each(|&mode| self.set_private_mode(mode, true)),
+            ('l', true) => values.iter().for_each(|&mode| self.set_private_mode(mode, false)),
+            ('h', false) if values.contains(&4) => self.modes.insert = true,
+            ('l', false) if values.contains(&4) => self.modes.insert = false,
+            ('s', false) => self.saved_cursor = Some(self.cursor),
+            ('u', false) => self.restore_cursor(),
+            _ => log::trace!("Ignoring CSI {:?} {:?} {}", values, intermediates, action),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.saved_cursor = Some(self.cursor),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.cursor.column = 0;
+                self.linefeed();
+            },
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([], b'=') => self.modes.application_keypad = true,
+            ([], b'>') => self.modes.application_keypad = false,
+            ([b'('], b'0') => self.cursor.line_drawing = true,
+            ([b'('], _) => self.cursor.line_drawing = false,
+            _ => {},
+        }
+    }
+}
+
+// 终端模拟器 - 将PTY输出送入VT解析器，维护屏幕模型
+pub struct TerminalEmulator {
+    parser: Parser,
+    screen: Screen,
+}
+
+impl TerminalEmulator {
+    pub fn new(size: &TerminalSize, scrollback_limit: usize) -> Self {
+        Self {
+            parser: Parser::new(),
+            screen: Screen::new(size, scrollback_limit),
+        }
+    }
+
+    // 解析一段PTY输出
+    pub fn process(&mut self, data: &str) {
+        self.parser.advance(&mut self.screen, data.as_bytes());
+    }
+
+    pub fn resize(&mut self, size: &TerminalSize) {
+        self.screen.resize(size);
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    // 取走已解析的shell集成标记
+    pub fn take_shell_marks(&mut self) -> Vec<ShellMark> {
+        std::mem::take(&mut self.screen.shell_marks)
+    }
+}
+
+// 解析OSC 7上报的 file://主机/路径，路径按百分号编码解码
+fn file_url_path(url: &[u8]) -> Option<String> {
+    let rest = url.strip_prefix(b"file://")?;
+    let path = &rest[rest.iter().position(|&byte| byte == b'/')?..];
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut index = 0;
+    while index < path.len() {
+        let hex = path.get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (path[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            },
+        }
+    }
+    Some(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+// 纯文本提取 - 去掉转义序列，只保留可打印字符和换行、回车、制表符，可处理跨输出块的转义序列
+#[derive(Default)]
+pub struct PlainText {
+    parser: Parser,
+    collector: TextCollector,
+}
+
+#[derive(Default)]
+struct TextCollector {
+    text: String,
+}
+
+impl Perform for TextCollector {
+    fn print(&mut self, character: char) {
+        self.text.push(character);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        if matches!(byte, b'\n' | b'\r' | b'\t') {
+            self.text.push(byte as char);
+        }
+    }
+}
+
+impl PlainText {
+    // 解析一段输出，返回其中的纯文本
+    pub fn feed(&mut self, data: &str) -> String {
+        self.parser.advance(&mut self.collector, data.as_bytes());
+        std::mem::take(&mut self.collector.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator(columns: u32, rows: u32) -> TerminalEmulator {
+        TerminalEmulator::new(&TerminalSize { columns, rows }, 100)
+    }
+
+    fn visible_text(emulator: &TerminalEmulator) -> Vec<String> {
+        emulator.screen().visible_rows().iter().map(Row::text).collect()
+    }
+
+    #[test]
+    fn cursor_movement_and_erase() {
+        let mut emulator = emulator(10, 3);
+        emulator.process("hello\x1b[1;3Hxy\r\nworld\x1b[2D\x1b[K");
+        assert_eq!(visible_text(&emulator), ["hexyo", "wor", ""]);
+        assert_eq!((emulator.screen().cursor().row, emulator.screen().cursor().column), (1, 3));
+
+        emulator.process("\x1b[2J\x1b[3;2Hz");
+        assert_eq!(visible_text(&emulator), ["", "", " z"]);
+    }
+
+    #[test]
+    fn select_graphic_rendition() {
+        let mut emulator = emulator(10, 1);
+        emulator.process("\x1b[1;31ma\x1b[38;5;200mb\x1b[38:2::1:2:3mc\x1b[48;2;4;5;6;38:2:7:8:9md\x1b[0me");
+        let cells = &emulator.screen().visible_rows()[0].cells;
+        assert!(cells[0].style.bold);
+        assert_eq!(cells[0].style.foreground, Color::Indexed(1));
+        assert_eq!(cells[1].style.foreground, Color::Indexed(200));
+        assert_eq!(cells[2].style.foreground, Color::Rgb(1, 2, 3));
+        assert_eq!(cells[3].style.background, Color::Rgb(4, 5, 6));
+        assert_eq!(cells[3].style.foreground, Color::Rgb(7, 8, 9));
+        assert_eq!(cells[4].style, Style::default());
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut emulator = emulator(5, 4);
+        emulator.process("\x1b[2;3r");
+        assert_eq!((emulator.screen.scroll_top, emulator.screen.scroll_bottom), (1, 2));
+        emulator.process("1\r\n2\r\n3\r\n4");
+        // 滚动区域不在顶部，滚出的行不进入滚动历史
+        assert_eq!(visible_text(&emulator), ["1", "3", "4", ""]);
+        assert_eq!(emulator.screen().scrollback(10).count(), 0);
+
+        // 底部为0时使用屏幕底部
+        emulator.process("\x1b[2;0r");
+        assert_eq!((emulator.screen.scroll_top, emulator.screen.scroll_bottom), (1, 3));
+        // 无效的区域被忽略
+        emulator.process("\x1b[3;2r");
+        assert_eq!((emulator.screen.scroll_top, emulator.screen.scroll_bottom), (1, 3));
+    }
+
+    #[test]
+    fn auto_wrap() {
+        let mut emulator = emulator(5, 3);
+        emulator.process("abcdefg");
+        assert_eq!(visible_text(&emulator), ["abcde", "fg", ""]);
+        assert!(emulator.screen().visible_rows()[0].wrapped);
+        assert!(!emulator.screen().visible_rows()[1].wrapped);
+
+        // 关闭自动换行后覆盖最后一列
+        emulator.process("\x1b[?7l\x1b[3;1Hvwxyz12");
+        assert_eq!(visible_text(&emulator)[2], "vwxy2");
+        assert!(!emulator.screen().visible_rows()[2].wrapped);
+    }
+
+    #[test]
+    fn wide_characters_wrap_as_a_whole() {
+        let mut emulator = emulator(5, 2);
+        emulator.process("abcd中");
+        assert_eq!(visible_text(&emulator), ["abcd", "中"]);
+        let cells = &emulator.screen().visible_rows()[1].cells;
+        assert_eq!((cells[0].width, cells[1].width), (2, 0));
+    }
+
+    #[test]
+    fn scrollback_and_line_numbers() {
+        let mut emulator = TerminalEmulator::new(&TerminalSize { columns: 5, rows: 2 }, 2);
+        emulator.process("1\r\n2\r\n3\r\n4\r\n5");
+        let scrollback: Vec<String> = emulator.screen().scrollback(10).map(Row::text).collect();
+        assert_eq!(scrollback, ["2", "3"]);
+        let (first_line_number, rows) = emulator.screen().history();
+        assert_eq!(first_line_number, 2);
+        assert_eq!(rows.map(Row::text).collect::<Vec<_>>(), ["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn alternate_screen_keeps_primary() {
+        let mut emulator = emulator(5, 2);
+        emulator.process("main\x1b[?1049hfull\r\n\r\n\r\nalt");
+        assert!(emulator.screen().modes().alternate_screen);
+        assert_eq!(visible_text(&emulator), ["", "alt"]);
+        let (_, rows) = emulator.screen().history();
+        assert_eq!(rows.map(Row::text).collect::<Vec<_>>(), ["main", ""]);
+
+        emulator.process("\x1b[?1049l");
+        assert!(!emulator.screen().modes().alternate_screen);
+        assert_eq!(visible_text(&emulator), ["main", ""]);
+        assert_eq!((emulator.screen().cursor().row, emulator.screen().cursor().column), (0, 4));
+    }
+
+    #[test]
+    fn osc_title_and_working_directory() {
+        let mut emulator = emulator(10, 2);
+        emulator.process("\x1b]2;build\x07\x1b]7;file://host/tmp/a%20b\x1b\\");
+        assert_eq!(emulator.screen().title(), "build");
+        assert_eq!(emulator.take_shell_marks(), [ShellMark::WorkingDirectory("/tmp/a b".to_string())]);
+        assert!(emulator.take_shell_marks().is_empty());
+    }
+
+    #[test]
+    fn osc_133_command_boundaries() {
+        let mut emulator = emulator(8, 4);
+        emulator.process("\x1b]133;A\x07$ \x1b]133;B\x07ls -la /t\x1b]133;C\x07\r\nout\r\n\x1b]133;D;2\x07");
+        assert_eq!(emulator.take_shell_marks(), [
+            ShellMark::PromptStart,
+            // 自动换行的命令行拼接为一行
+            ShellMark::CommandStart { command: Some("ls -la /t".to_string()) },
+            ShellMark::CommandFinished { exit_code: Some(2) },
+        ]);
+
+        // 没有提示符结束标记时无法读取命令行
+        emulator.process("\x1b]133;A\x07\x1b]133;C\x07\x1b]133;D\x07");
+        assert_eq!(emulator.take_shell_marks(), [
+            ShellMark::PromptStart,
+            ShellMark::CommandStart { command: None },
+            ShellMark::CommandFinished { exit_code: None },
+        ]);
+
+        // 备用屏幕中的标记忽略
+        emulator.process("\x1b[?1049h\x1b]133;A\x07");
+        assert!(emulator.take_shell_marks().is_empty());
+    }
+
+    #[test]
+    fn repaint_restores_state() {
+        let mut source = emulator(10, 3);
+        source.process("\x1b[31mred\x1b[0m\r\nplain\x1b[?2004h\x1b]2;title\x07\x1b[2;3H");
+        let mut target = emulator(10, 3);
+        target.process(&source.screen().repaint());
+        assert_eq!(visible_text(&target), visible_text(&source));
+        assert_eq!(target.screen().visible_rows()[0].cells[0].style.foreground, Color::Indexed(1));
+        assert!(target.screen().modes().bracketed_paste);
+        assert_eq!(target.screen().title(), "title");
+        assert_eq!((target.screen().cursor().row, target.screen().cursor().column), (1, 2));
+    }
+
+    #[test]
+    fn plain_text_strips_sequences_across_chunks() {
+        let mut plain = PlainText::default();
+        assert_eq!(plain.feed("a\x1b[3"), "a");
+        assert_eq!(plain.feed("1mb\r\n\x1b]0;t\x07c"), "b\r\nc");
+    }
+}
@@ -0,0 +1,204 @@
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::session::screen::Row;
+
+// 正则表达式编译后的大小上限，防止构造过大的正则
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+// 搜索条件
+pub struct SearchQuery {
+    pattern: Regex,
+    // 每个匹配附带的前后行数
+    context: usize,
+    // 最多返回的匹配行数
+    limit: usize,
+}
+
+impl SearchQuery {
+    // 编译搜索条件，非正则搜索按字面文本匹配
+    pub fn new(query: &str, regex: bool, ignore_case: bool, context: usize, limit: usize) -> anyhow::Result<Self> {
+        if query.is_empty() {
+            anyhow::bail!("Search query is empty");
+        }
+        let pattern = if regex { query.to_string() } else { regex::escape(query) };
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid regular expression: {}", e))?;
+        Ok(Self { pattern, context, limit })
+    }
+}
+
+// 匹配在行内的位置（字符偏移，左闭右开）
+#[derive(Serialize)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+// 前后文中的一行
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextLine {
+    pub line_number: u64,
+    pub text: String,
+}
+
+// 一条匹配行
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub line_number: u64,
+    pub text: String,
+    pub ranges: Vec<MatchRange>,
+    pub before: Vec<ContextLine>,
+    pub after: Vec<ContextLine>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub matches: Vec<SearchMatch>,
+    // 匹配行数超过上限，结果被截断
+    pub truncated: bool,
+    // 当前保留的最早和最新行号，更早的输出已被丢弃
+    pub first_line_number: u64,
+    pub last_line_number: u64,
+}
+
+// 保留输出中的一个逻辑行：自动换行的多行合并为一行，行号为第一行的行号
+struct LogicalLine {
+    line_number: u64,
+    text: String,
+}
+
+fn logical_lines(first_line_number: u64, rows: &[Row]) -> Vec<LogicalLine> {
+    let mut lines: Vec<LogicalLine> = Vec::new();
+    let mut continues = false;
+    for (line_number, row) in (first_line_number..).zip(rows) {
+        // 自动换行的行保留行尾空格，与下一行拼接
+        let text = if row.wrapped {
+            row.cells.iter().filter(|cell| cell.width > 0).map(|cell| cell.character).collect()
+        } else {
+            row.text()
+        };
+        match lines.last_mut() {
+            Some(line) if continues => line.text.push_str(&text),
+            _ => lines.push(LogicalLine { line_number, text }),
+        }
+        continues = row.wrapped;
+    }
+    // 主屏幕底部未使用的空行不参与搜索
+    while lines.last().is_some_and(|line| line.text.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn context_lines(lines: &[LogicalLine]) -> Vec<ContextLine> {
+    lines.iter()
+        .map(|line| ContextLine { line_number: line.line_number, text: line.text.clone() })
+        .collect()
+}
+
+// 在会话保留的输出（滚动历史和主屏幕的各行，first_line_number为第一行的行号）中搜索
+pub fn search(first_line_number: u64, rows: &[Row], query: &SearchQuery) -> SearchResult {
+    let lines = logical_lines(first_line_number, rows);
+    let mut matches = Vec::new();
+    let mut truncated = false;
+
+    for (index, line) in lines.iter().enumerate() {
+        let ranges: Vec<MatchRange> = query.pattern.find_iter(&line.text)
+            .filter(|found| !found.is_empty())
+            .map(|found| MatchRange {
+                start: line.text[..found.start()].chars().count(),
+                end: line.text[..found.end()].chars().count(),
+            })
+            .collect();
+        if ranges.is_empty() {
+            continue;
+        }
+        if matches.len() == query.limit {
+            truncated = true;
+            break;
+        }
+        matches.push(SearchMatch {
+            line_number: line.line_number,
+            text: line.text.clone(),
+            ranges,
+            before: context_lines(&lines[index.saturating_sub(query.context)..index]),
+            after: context_lines(&lines[index + 1..(index + 1 + query.context).min(lines.len())]),
+        });
+    }
+
+    SearchResult {
+        matches,
+        truncated,
+        first_line_number,
+        last_line_number: lines.last().map_or(first_line_number.saturating_sub(1), |line| line.line_number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TerminalSize;
+    use crate::session::screen::TerminalEmulator;
+
+    fn search_output(output: &str, columns: u32, scrollback_limit: usize, query: &SearchQuery) -> SearchResult {
+        let mut emulator = TerminalEmulator::new(&TerminalSize { columns, rows: 3 }, scrollback_limit);
+        emulator.process(output);
+        let (first_line_number, rows) = emulator.screen().history();
+        search(first_line_number, &rows.cloned().collect::<Vec<_>>(), query)
+    }
+
+    #[test]
+    fn literal_and_regex_queries() {
+        assert!(SearchQuery::new("", false, false, 0, 10).is_err());
+        assert!(SearchQuery::new("(", true, false, 0, 10).is_err());
+
+        // 非正则搜索按字面文本匹配
+        let literal = SearchQuery::new("a.c", false, false, 0, 10).unwrap();
+        let result = search_output("abc\r\na.c", 10, 10, &literal);
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].line_number, 2);
+
+        let regex = SearchQuery::new("A.C", true, true, 0, 10).unwrap();
+        let result = search_output("abc\r\na.c", 10, 10, &regex);
+        assert_eq!(result.matches.iter().map(|found| found.line_number).collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn ranges_use_character_offsets() {
+        let query = SearchQuery::new("é", false, false, 0, 10).unwrap();
+        let result = search_output("café é", 10, 10, &query);
+        let ranges: Vec<(usize, usize)> = result.matches[0].ranges.iter().map(|range| (range.start, range.end)).collect();
+        assert_eq!(ranges, [(3, 4), (5, 6)]);
+    }
+
+    #[test]
+    fn wrapped_rows_match_as_one_line() {
+        let query = SearchQuery::new("hello world", false, false, 0, 10).unwrap();
+        let result = search_output("hello world", 6, 10, &query);
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].line_number, 1);
+        assert_eq!(result.last_line_number, 1);
+    }
+
+    #[test]
+    fn context_limit_and_line_numbers() {
+        let query = SearchQuery::new("match", false, false, 1, 1).unwrap();
+        // 滚动历史只保留2行，前两行被丢弃
+        let result = search_output("one\r\nmatch 1\r\nthree\r\nmatch 2\r\nfive\r\nmatch 3\r\nseven", 10, 2, &query);
+        assert_eq!((result.first_line_number, result.last_line_number), (3, 7));
+        assert_eq!(result.matches.len(), 1);
+        assert!(result.truncated);
+
+        let first = &result.matches[0];
+        assert_eq!(first.line_number, 4);
+        assert!(first.before.iter().map(|line| &line.text).eq(["three"]));
+        assert!(first.after.iter().map(|line| &line.text).eq(["five"]));
+    }
+}
@@ -2,22 +2,103 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock};
 use uuid::Uuid;
 
-use crate::config::Config;
-use crate::pty::terminal::TerminalProcess;
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::audit::{AuditAction, AuditLog, ClientContext, TransportKind};
+use crate::config::{Config, ShellConfig, TerminalSize, TriggerAction, TriggerRule, MAX_TERMINAL_COLUMNS, MAX_TERMINAL_ROWS};
+use crate::content_log;
+use crate::metrics::Metrics;
+use crate::protocol::message::{ClientMessage, ControlEvent, ServerMessage};
+use crate::pty::process::{ForegroundProcess, Signal, SignalTarget};
+use crate::pty::terminal::{OutputChunk, TerminalProcess};
+use crate::session::error::{SessionError, SessionResult};
+use crate::session::history::{HistoryEntry, HistoryQuery, HistoryStore};
+use crate::session::presence::Presence;
+use crate::session::recording::SessionRecorder;
+use crate::session::repository::{now_millis, SessionFilter, SessionRecord, SessionRepository};
+use crate::session::screen::TerminalEmulator;
+use crate::session::search::{self, SearchQuery, SearchResult};
+use crate::session::share::{owns_session, Share, ShareRole, ShareStore};
+use crate::session::shell_integration::{self, CommandInfo, ShellState};
+use crate::session::sizing::SizeNegotiation;
+use crate::session::snapshot::{self, ScreenCapture, ScreenFormat};
+use crate::session::trigger::{self, Firing, TriggerSet, WebhookClient};
+use crate::telemetry;
+
+// 每个输出订阅者可积压的输出块数量，超过后最早的输出被跳过
+const OUTPUT_SUBSCRIBER_CAPACITY: usize = 256;
+// 空闲触发器的检查间隔
+const IDLE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
 // 会话状态枚举
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) enum SessionStatus {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SessionStatus {
     Active,
     Terminated,
 }
 
+impl SessionStatus {
+    // 状态名称，与REST API一致
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionStatus::Active => "ACTIVE",
+            SessionStatus::Terminated => "TERMINATED",
+        }
+    }
+    
+    // 解析状态名称，忽略大小写
+    pub fn parse(status: &str) -> Option<Self> {
+        match status.to_ascii_uppercase().as_str() {
+            "ACTIVE" => Some(SessionStatus::Active),
+            "TERMINATED" => Some(SessionStatus::Terminated),
+            _ => None,
+        }
+    }
+}
+
+// 创建会话的参数，未指定的项使用配置中的默认值
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    pub user_id: Option<String>,
+    pub title: Option<String>,
+    pub shell_type: Option<String>,
+    pub working_directory: Option<String>,
+    pub terminal_size: Option<TerminalSize>,
+    // 是否录制会话，未指定时按录制配置决定
+    pub record: Option<bool>,
+    // 是否开启单人操控模式，未指定时按协作配置决定
+    pub single_driver: Option<bool>,
+    // 是否注入shell集成脚本，未指定时按shell集成配置决定
+    pub shell_integration: Option<bool>,
+}
+
+// 客户端ID和对应的输出通道
+type ClientSender = (String, tokio::sync::mpsc::Sender<ServerMessage>);
+
 // 终端会话
 #[derive(Clone)]
 pub(crate) struct Session {
     terminal: TerminalProcess,
-    // 客户端发送通道 - 线程安全的发送者列表
-    client_senders: Arc<Mutex<Vec<tokio::sync::mpsc::Sender<String>>>>,
+    // 创建会话的用户，匿名会话为空
+    user_id: String,
+    // 客户端发送通道 - 线程安全的发送者列表，以客户端ID区分
+    client_senders: Arc<Mutex<Vec<ClientSender>>>,
+    // 在线客户端和控制权
+    presence: Arc<Mutex<Presence>>,
+    // 各客户端上报的窗口大小
+    sizes: Arc<Mutex<SizeNegotiation>>,
+    // 已应用到终端的大小，调整大小时持有该锁，保证按协商顺序应用
+    applied_size: Arc<tokio::sync::Mutex<TerminalSize>>,
+    // 服务端屏幕模型，新客户端连接时据此重绘
+    emulator: Arc<Mutex<TerminalEmulator>>,
+    // 实时输出的订阅通道
+    output_tx: tokio::sync::broadcast::Sender<String>,
+    // 会话的触发器
+    triggers: Arc<Mutex<TriggerSet>>,
+    // shell集成上报的当前目录和命令
+    shell: Arc<Mutex<ShellState>>,
     // 会话状态 - 使用AtomicU8确保原子更新
     status: Arc<std::sync::atomic::AtomicU8>,
     // 会话过期时间
@@ -26,11 +107,16 @@ pub(crate) struct Session {
     last_active_time: Arc<std::sync::atomic::AtomicU64>,
     // 是否已启动终端输出监听任务
     listener_started: Arc<std::sync::atomic::AtomicBool>,
+    // 会话录制器，未开启录制时为None
+    recorder: Option<Arc<SessionRecorder>>,
+    // 最近一次输入的span，随后的输出span关联到它
+    last_input: Arc<Mutex<Option<opentelemetry::trace::SpanContext>>>,
 }
 
 impl Session {
     // 创建新会话
-    pub(crate) fn new(terminal: TerminalProcess, session_timeout: u64) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(terminal: TerminalProcess, user_id: String, session_timeout: u64, recorder: Option<Arc<SessionRecorder>>, single_driver: bool, sizes: SizeNegotiation, scrollback_lines: usize, triggers: TriggerSet) -> Self {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -38,11 +124,36 @@ impl Session {
         
         Self {
             terminal,
+            user_id,
             client_senders: Arc::new(Mutex::new(Vec::new())),
+            presence: Arc::new(Mutex::new(Presence::new(single_driver))),
+            emulator: Arc::new(Mutex::new(TerminalEmulator::new(&sizes.effective(), scrollback_lines))),
+            applied_size: Arc::new(tokio::sync::Mutex::new(sizes.effective())),
+            sizes: Arc::new(Mutex::new(sizes)),
+            output_tx: tokio::sync::broadcast::channel(OUTPUT_SUBSCRIBER_CAPACITY).0,
+            triggers: Arc::new(Mutex::new(triggers)),
+            shell: Arc::new(Mutex::new(ShellState::default())),
             status: Arc::new(std::sync::atomic::AtomicU8::new(SessionStatus::Active as u8)),
             expired_at: now + session_timeout,
             last_active_time: Arc::new(std::sync::atomic::AtomicU64::new(now)),
             listener_started: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            recorder,
+            last_input: Arc::new(Mutex::new(None)),
+        }
+    }
+    
+    // 客户端能否向终端输入
+    fn may_write(&self, client: &ClientContext) -> bool {
+        client.can_write() && (client.transport == TransportKind::System || self.presence.lock().unwrap().may_write(&client.client_id))
+    }
+    
+    // 向会话的所有客户端发送控制事件
+    fn broadcast(&self, events: Vec<ControlEvent>) {
+        let client_senders = self.client_senders.lock().unwrap();
+        for event in events {
+            for (_, sender) in client_senders.iter() {
+                let _ = sender.try_send(ServerMessage::Control(event.clone()));
+            }
         }
     }
     
@@ -84,22 +195,71 @@ impl Session {
     }
 }
 
+// 检查客户端请求的终端大小
+fn check_size(size: &TerminalSize) -> SessionResult<()> {
+    if !size.is_valid() {
+        return Err(SessionError::Invalid(format!(
+            "Invalid terminal size {}x{}, expected 1-{} columns and 1-{} rows",
+            size.columns, size.rows, MAX_TERMINAL_COLUMNS, MAX_TERMINAL_ROWS,
+        )));
+    }
+    Ok(())
+}
+
+// 共享、触发器的管理和关闭会话只允许会话所有者
+fn check_owner(session_id: &str, client: &ClientContext) -> SessionResult<()> {
+    if !client.is_owner() {
+        return Err(SessionError::Forbidden(format!("Only the owner can manage session {}", session_id)));
+    }
+    Ok(())
+}
+
+// 读取会话内容（屏幕、输出、历史、录制等）须有角色，即所有者或出示了有效共享令牌
+pub fn check_reader(session_id: &str, client: &ClientContext) -> SessionResult<()> {
+    if client.role.is_none() {
+        return Err(SessionError::Forbidden(format!("Client is not allowed to view session {}", session_id)));
+    }
+    Ok(())
+}
+
 // 会话管理器 - 完全线程安全设计
 #[derive(Clone)]
 pub struct SessionManager {
     // 使用RwLock保护会话映射，允许多读单写
     sessions: Arc<RwLock<HashMap<String, Session>>>,
+    // 会话记录存储，保存会话元数据和生命周期
+    repository: Arc<dyn SessionRepository>,
+    // 审计日志
+    audit: Arc<AuditLog>,
+    // Prometheus指标
+    metrics: Arc<Metrics>,
+    // 会话共享令牌
+    shares: Arc<ShareStore>,
+    // 按用户持久化的命令历史
+    history: Arc<HistoryStore>,
+    // 触发器的webhook发送
+    webhooks: WebhookClient,
     config: Arc<Config>,
 }
 
 impl SessionManager {
     // 创建新的会话管理器
-    pub fn new(config: Arc<Config>) -> Self {
+    pub fn new(config: Arc<Config>, repository: Arc<dyn SessionRepository>, audit: Arc<AuditLog>, metrics: Arc<Metrics>, history: Arc<HistoryStore>) -> Self {
         let session_manager = Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
+            repository,
+            audit,
+            metrics,
+            shares: Arc::new(ShareStore::default()),
+            history,
+            webhooks: WebhookClient::new(config.triggers.webhook_timeout_ms),
             config: config.clone(),
         };
         
+        // 重新接管上次运行留下的会话，未能接管的会话记为已终止
+        session_manager.restore_sessions();
+        session_manager.terminate_stale_records();
+        
         // 启动会话过期检查任务
         let session_manager_clone = session_manager.clone();
         tokio::spawn(async move {
@@ -109,21 +269,384 @@ impl SessionManager {
         session_manager
     }
     
-
+    // 从状态目录重新发现仍在运行的会话保持进程，重建会话映射
+    fn restore_sessions(&self) {
+        if !self.config.persistence.enabled {
+            return;
+        }
+        
+        #[cfg(unix)]
+        {
+            let state_directory = std::path::Path::new(&self.config.persistence.state_directory);
+            let mut sessions_write = self.sessions.write().unwrap();
+            let mut with_triggers = Vec::new();
+            for (metadata, connection, output_rx) in crate::pty::holder::discover(state_directory) {
+                log::info!("Reattached session {} (holder pid {}, shell pid {:?}, command {:?})",
+                          metadata.session_id, metadata.holder_pid, metadata.shell_pid, metadata.command);
+                let terminal = TerminalProcess::from_holder(connection, output_rx);
+                let recording = &self.config.recording;
+                let recorder = match SessionRecorder::resume(&recording.directory, &metadata.session_id, recording.record_input) {
+                    Ok(recorder) => recorder.map(Arc::new),
+                    Err(e) => {
+                        log::error!("Failed to resume recording of session {}: {}", metadata.session_id, e);
+                        None
+                    }
+                };
+                
+                // 使用内存存储时重启后没有记录，按保持进程的元数据补建
+                let record = match self.repository.find(&metadata.session_id) {
+                    Ok(Some(record)) => record,
+                    _ => {
+                        let mut record = self.new_record(&metadata.session_id, &SessionOptions::default(), "", &metadata.command);
+                        record.created_at = metadata.created_at;
+                        record.transition(SessionStatus::Active, "Reattached");
+                        self.save_record(&record);
+                        record
+                    }
+                };
+                let sizes = SizeNegotiation::new(self.config.collaboration.size_policy, Some(record.user_id.clone()), record.terminal_size);
+                let triggers = self.shell_triggers(&record.shell_type);
+                let session = Session::new(terminal, record.user_id, self.config.terminal.session_timeout, recorder, self.config.collaboration.single_driver, sizes, self.config.terminal.scrollback_lines, triggers);
+                if let Some(last) = self.history.session(&metadata.session_id, 1).pop() {
+                    session.shell.lock().unwrap().continue_numbering(last.number);
+                }
+                if !session.triggers.lock().unwrap().is_empty() {
+                    with_triggers.push((metadata.session_id.clone(), session.clone()));
+                }
+                sessions_write.insert(metadata.session_id, session);
+            }
+            log::info!("Restored {} sessions from {:?}", sessions_write.len(), state_directory);
+            
+            // 有触发器的会话需要立即读取输出
+            for (session_id, session) in with_triggers {
+                let session_manager = self.clone();
+                tokio::spawn(async move {
+                    session_manager.start_listener(&session_id, &session).await;
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        log::warn!("Session persistence is only supported on Unix, ignoring");
+    }
+    
+    // 将存储中仍为活动状态、但本进程没有对应会话的记录标记为已终止
+    fn terminate_stale_records(&self) {
+        let filter = SessionFilter {
+            status: Some(SessionStatus::Active),
+            user_id: None,
+        };
+        let records = match self.repository.list(&filter) {
+            Ok(records) => records,
+            Err(e) => {
+                log::error!("Failed to list session records: {}", e);
+                return;
+            }
+        };
+        
+        for mut record in records {
+            if !self.sessions.read().unwrap().contains_key(&record.id) {
+                log::info!("Session {} did not survive the restart, marking it terminated", record.id);
+                record.transition(SessionStatus::Terminated, "Server restarted");
+                self.save_record(&record);
+            }
+        }
+    }
+    
+    // 对该shell类型生效的配置触发器
+    fn shell_triggers(&self, shell_type: &str) -> TriggerSet {
+        let rules = self.config.triggers.rules.iter()
+            .filter(|rule| rule.shells.is_empty() || rule.shells.iter().any(|shell| shell == shell_type))
+            .cloned()
+            .collect();
+        TriggerSet::new(rules)
+    }
+    
+    // 构建新的会话记录
+    fn new_record(&self, session_id: &str, options: &SessionOptions, shell_type: &str, command: &[String]) -> SessionRecord {
+        let now = now_millis();
+        SessionRecord {
+            id: session_id.to_string(),
+            user_id: options.user_id.clone().unwrap_or_default(),
+            title: options.title.clone(),
+            working_directory: options.working_directory.clone()
+                .unwrap_or_else(|| self.config.terminal.default_working_directory.clone()),
+            shell_type: shell_type.to_string(),
+            command: command.to_vec(),
+            status: SessionStatus::Active,
+            terminal_size: options.terminal_size.clone()
+                .unwrap_or_else(|| self.config.terminal.default_terminal_size.clone()),
+            created_at: now,
+            updated_at: now,
+            last_active_time: now,
+            expired_at: now + self.config.terminal.session_timeout,
+            terminated_at: None,
+            termination_reason: None,
+            exit_code: None,
+            transitions: Vec::new(),
+            current_directory: None,
+        }
+    }
+    
+    // 保存会话记录，存储失败不影响会话本身
+    fn save_record(&self, record: &SessionRecord) {
+        if let Err(e) = self.repository.save(record) {
+            log::error!("Failed to save record for session {}: {}", record.id, e);
+        }
+    }
+    
+    // 修改并保存会话记录
+    fn update_record<F: FnOnce(&mut SessionRecord)>(&self, session_id: &str, update: F) {
+        match self.repository.find(session_id) {
+            Ok(Some(mut record)) => {
+                update(&mut record);
+                self.save_record(&record);
+            },
+            Ok(None) => log::warn!("No record found for session {}", session_id),
+            Err(e) => log::error!("Failed to load record for session {}: {}", session_id, e),
+        }
+    }
+    
+    // 查找活动会话
+    fn find_session(&self, session_id: &str) -> SessionResult<Session> {
+        self.sessions.read().unwrap().get(session_id).cloned()
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))
+    }
+    
+    // 获取会话记录，活动会话使用内存中的最新活动时间
+    pub fn get_session_record(&self, session_id: &str) -> anyhow::Result<Option<SessionRecord>> {
+        let record = self.repository.find(session_id)?;
+        Ok(record.map(|record| self.with_live_state(record)))
+    }
+    
+    // 查询会话记录，包括已终止的会话
+    pub fn list_session_records(&self, filter: &SessionFilter) -> anyhow::Result<Vec<SessionRecord>> {
+        let records = self.repository.list(filter)?;
+        Ok(records.into_iter().map(|record| self.with_live_state(record)).collect())
+    }
+    
+    // 用内存中的会话状态覆盖记录中的活动时间
+    fn with_live_state(&self, mut record: SessionRecord) -> SessionRecord {
+        if let Some(session) = self.sessions.read().unwrap().get(&record.id) {
+            record.last_active_time = session.get_last_active_time();
+            record.expired_at = session.expired_at;
+            record.current_directory = session.shell.lock().unwrap().current_directory.clone();
+        }
+        record
+    }
+    
+    // 审计日志，供REST API记录执行命令等操作
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+    
+    // Prometheus指标
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+    
+    // 渲染会话当前的屏幕快照，scrollback为附带的滚动历史行数
+    pub fn render_screen(&self, session_id: &str, format: ScreenFormat, scrollback: usize, client: &ClientContext) -> SessionResult<String> {
+        let session = self.find_session(session_id)?;
+        check_reader(session_id, client)?;
+        // 持锁时只复制所需的行，渲染在锁外进行，避免阻塞输出处理
+        let capture = ScreenCapture::new(session.emulator.lock().unwrap().screen(), scrollback);
+        Ok(snapshot::render(&capture, format)?)
+    }
+    
+    // 会话的shell集成状态：当前目录、正在执行和最近执行完的命令
+    pub fn shell_state(&self, session_id: &str, client: &ClientContext) -> SessionResult<ShellState> {
+        let session = self.find_session(session_id)?;
+        check_reader(session_id, client)?;
+        let state = session.shell.lock().unwrap().clone();
+        Ok(state)
+    }
+    
+    // 保存执行完的命令
+    fn record_history(&self, session_id: &str, session: &Session, events: &[ControlEvent]) {
+        if !self.history.is_enabled() {
+            return;
+        }
+        for event in events {
+            if let ControlEvent::CommandFinished { command } = event
+                && let Err(e) = self.history.append(&session.user_id, session_id, command) {
+                log::error!("Failed to save command history of session {}: {}", session_id, e);
+            }
+        }
+    }
+    
+    // 会话最近执行完的命令，已终止的会话从持久化的命令历史中查询；只允许会话所有者和共享持有者查看
+    pub fn session_history(&self, session_id: &str, limit: usize, client: &ClientContext) -> SessionResult<Vec<CommandInfo>> {
+        check_reader(session_id, client)?;
+        if let Some(session) = self.sessions.read().unwrap().get(session_id) {
+            return Ok(session.shell.lock().unwrap().history(limit));
+        }
+        if self.history.is_enabled() && self.repository.find(session_id)?.is_some() {
+            return Ok(self.history.session(session_id, limit));
+        }
+        Err(SessionError::SessionNotFound(session_id.to_string()))
+    }
+    
+    // 跨会话查询命令历史 - 指定会话时调用者须为该会话的所有者或共享持有者（角色已由authorize_client确定），
+    // 按会话所有者查询；未指定会话时只查询调用者自己的命令
+    pub fn search_history(&self, session_id: Option<String>, text: Option<String>, limit: usize, client: &ClientContext) -> SessionResult<Vec<HistoryEntry>> {
+        if !self.history.is_enabled() {
+            return Err(SessionError::Unavailable("Command history is not persisted".to_string()));
+        }
+        let user_id = match &session_id {
+            Some(session_id) => {
+                check_reader(session_id, client)?;
+                self.session_owner(session_id).ok_or_else(|| SessionError::SessionNotFound(session_id.clone()))?
+            },
+            None => client.user_id.clone().ok_or_else(|| SessionError::Invalid("Missing userId".to_string()))?,
+        };
+        Ok(self.history.search(&HistoryQuery { user_id, session_id, text, limit }))
+    }
+    
+    // 在会话中重新执行第number条命令 - 只执行本次运行中记录的命令，持久化的历史已脱敏，不能重新执行；
+    // 有命令正在运行时拒绝，避免输入被当前程序读取
+    pub async fn rerun_command(&self, session_id: &str, number: u64, client: &ClientContext) -> SessionResult<CommandInfo> {
+        let session = self.find_session(session_id)?;
+        let command = {
+            let shell = session.shell.lock().unwrap();
+            if let Some(current) = &shell.current_command {
+                return Err(SessionError::Conflict(format!("Command {} is still running", current.number)));
+            }
+            shell.command(number)
+        };
+        let Some(command) = command else {
+            if self.history.find(session_id, number).is_some() {
+                return Err(SessionError::Conflict(format!("Command {} was loaded from history and cannot be rerun", number)));
+            }
+            return Err(SessionError::NotFound(format!("Command not found: {}", number)));
+        };
+        let Some(text) = &command.command else {
+            return Err(SessionError::Invalid(format!("Command line of command {} was not captured", number)));
+        };
+        self.write_input(session_id, &format!("{}\n", text), client, Some(&format!("rerun #{}: {}", number, text))).await?;
+        Ok(command)
+    }
+    
+    // 在会话保留的输出中搜索
+    pub fn search_session(&self, session_id: &str, query: &SearchQuery, client: &ClientContext) -> SessionResult<SearchResult> {
+        let session = self.find_session(session_id)?;
+        check_reader(session_id, client)?;
+        // 持锁时只复制保留的行，搜索在锁外进行，避免阻塞输出处理
+        let (first_line_number, rows) = {
+            let emulator = session.emulator.lock().unwrap();
+            let (first_line_number, rows) = emulator.screen().history();
+            (first_line_number, rows.cloned().collect::<Vec<_>>())
+        };
+        Ok(search::search(first_line_number, &rows, query))
+    }
+    
+    // 导出Prometheus指标，活动会话数按当前会话重新统计
+    pub fn render_metrics(&self) -> anyhow::Result<String> {
+        let filter = SessionFilter {
+            status: Some(SessionStatus::Active),
+            user_id: None,
+        };
+        let records = self.repository.list(&filter)?;
+        {
+            let sessions_read = self.sessions.read().unwrap();
+            let active = records.iter().filter(|record| sessions_read.contains_key(&record.id));
+            self.metrics.set_active_sessions(active.map(|record| record.shell_type.as_str()));
+        }
+        self.metrics.render()
+    }
+    
+    // 录制文件目录
+    pub fn recording_directory(&self) -> &str {
+        &self.config.recording.directory
+    }
+    
+    // 是否由会话保持进程持有终端，服务器关闭时不应终止shell
+    pub fn is_persistent(&self) -> bool {
+        cfg!(unix) && self.config.persistence.enabled
+    }
     
     // 创建新会话 - 线程安全，只需要&self
-    pub async fn create_session(&self) -> anyhow::Result<String> {
+    #[tracing::instrument(level = "debug", name = "session.create", skip_all, fields(session.id, user.id = options.user_id.as_deref(), transport = client.transport.as_str()))]
+    pub async fn create_session(&self, options: SessionOptions, client: &ClientContext) -> anyhow::Result<String> {
         // 生成会话ID
+        if let Some(size) = &options.terminal_size {
+            check_size(size)?;
+        }
         let session_id = Uuid::new_v4().to_string();
+        tracing::Span::current().record("session.id", session_id.as_str());
         
-        // 获取默认shell配置
-        let default_shell_config = self.config.get_default_shell_config();
+        // 获取shell配置，未知的shell类型使用默认shell
+        let shell_type = match options.shell_type.as_deref() {
+            Some(shell_type) if self.config.get_shell_config(shell_type).is_some() => shell_type.to_string(),
+            Some(shell_type) => {
+                log::warn!("Shell type '{}' not found, using default shell type '{}'", shell_type, self.config.terminal.default_shell_type);
+                self.config.terminal.default_shell_type.clone()
+            },
+            None => self.config.terminal.default_shell_type.clone(),
+        };
+        let mut shell_config = self.config.get_shell_config(&shell_type)
+            .unwrap_or_else(|| self.config.get_default_shell_config())
+            .clone();
+        if let Some(working_directory) = &options.working_directory {
+            shell_config.working_directory = Some(working_directory.clone());
+        }
+        if let Some(terminal_size) = &options.terminal_size {
+            shell_config.terminal_size = Some(terminal_size.clone());
+        }
+        
+        // 按会话参数或shell类型注入shell集成脚本，写入脚本失败时不注入
+        let integration = &self.config.shell_integration;
+        if options.shell_integration.unwrap_or(integration.enabled || integration.shells.contains(&shell_type)) {
+            match shell_integration::install(&integration.directory) {
+                Ok(directory) => {
+                    if !shell_integration::inject(&mut shell_config, &directory) {
+                        log::warn!("Shell integration is not supported for command {:?}", shell_config.command);
+                    }
+                },
+                Err(e) => log::error!("Failed to install shell integration scripts to {}: {}", integration.directory, e),
+            }
+        }
         
         // 创建终端进程 - 完全异步，不持有任何锁
-        let terminal = TerminalProcess::new_with_config(default_shell_config).await?;
+        let terminal = match self.spawn_terminal(&session_id, &shell_config).await {
+            Ok(terminal) => terminal,
+            Err(e) => {
+                self.metrics.spawn_failed(&shell_type);
+                return Err(e);
+            }
+        };
+        
+        // 记录会话元数据和归属
+        let mut record = self.new_record(&session_id, &options, &shell_type, &shell_config.command);
+        record.transition(SessionStatus::Active, "Created");
+        self.save_record(&record);
+        
+        // 按会话参数或shell类型开启录制，录制失败不影响会话
+        let recording = &self.config.recording;
+        let should_record = options.record.unwrap_or(recording.enabled || recording.shells.contains(&shell_type));
+        let recorder = if should_record {
+            match SessionRecorder::create(
+                &recording.directory,
+                &session_id,
+                &record.terminal_size,
+                &shell_config.command[0],
+                options.title.as_deref(),
+                recording.record_input,
+            ) {
+                Ok(recorder) => Some(Arc::new(recorder)),
+                Err(e) => {
+                    log::error!("Failed to start recording session {}: {}", session_id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
         
         // 创建会话对象
-        let session = Session::new(terminal.clone(), self.config.terminal.session_timeout);
+        let single_driver = options.single_driver.unwrap_or(self.config.collaboration.single_driver);
+        let sizes = SizeNegotiation::new(self.config.collaboration.size_policy, options.user_id.clone(), record.terminal_size.clone());
+        let triggers = self.shell_triggers(&shell_type);
+        let session = Session::new(terminal.clone(), record.user_id.clone(), self.config.terminal.session_timeout, recorder, single_driver, sizes, self.config.terminal.scrollback_lines, triggers);
         
         // 添加到会话映射 - 只持有写锁一小段时间
         {
@@ -132,35 +655,55 @@ impl SessionManager {
         }
         
         log::info!("Created new session with ID: {} using shell: {:?}", 
-                  session_id, default_shell_config.command);
+                  session_id, shell_config.command);
+        self.audit.record(AuditAction::Create, &session_id, client, Some(&shell_type));
         
         // 注意：我们不再在这里启动终端输出监听任务
         // 终端输出监听任务将在第一个客户端连接时启动
         // 这样可以确保只有在有客户端连接时才会读取终端输出，避免输出被丢弃
+        // 有触发器的会话需要判断输出和空闲，立即启动监听，输出由屏幕模型保留
+        if !session.triggers.lock().unwrap().is_empty() {
+            self.start_listener(&session_id, &session).await;
+        }
         
         Ok(session_id)
     }
     
+    // 创建终端进程 - 启用持久化时由会话保持进程持有PTY
+    async fn spawn_terminal(&self, session_id: &str, shell_config: &ShellConfig) -> anyhow::Result<TerminalProcess> {
+        #[cfg(unix)]
+        if self.is_persistent() {
+            let persistence = &self.config.persistence;
+            let state_directory = std::path::Path::new(&persistence.state_directory);
+            return TerminalProcess::new_held(shell_config, state_directory, session_id, persistence.replay_buffer_size).await;
+        }
+        #[cfg(not(unix))]
+        let _ = session_id;
+        
+        TerminalProcess::new_with_config(shell_config).await
+    }
+    
     // 启动终端输出监听任务 - 独立异步任务，不阻塞主线程
-    async fn spawn_terminal_listener(&self, terminal: TerminalProcess, session_id: String) {
+    async fn spawn_terminal_listener(&self, terminal: TerminalProcess, recorder: Option<Arc<SessionRecorder>>, session_id: String) {
         let session_manager_clone = self.clone();
         tokio::spawn(async move { 
-            let mut buffer = [0; 1024];
             let session_id = session_id.clone();
+            let mut last_idle_check = tokio::time::Instant::now();
             
             loop {
                 // 检查终端进程是否还在运行
                 if !terminal.is_running().await {
                     log::info!("Terminal process for session {} has exited, stopping listener", session_id);
+                    session_manager_clone.finish_exited_session(&session_id, &terminal).await;
                     break;
                 }
                 
                 // 读取终端输出 - 使用非阻塞方式，避免长时间等待
-                let output = tokio::select! {
+                let chunk = tokio::select! {
                     // 尝试读取终端输出，超时时间为50毫秒
                     output_result = tokio::time::timeout(
                         tokio::time::Duration::from_millis(50),
-                        terminal.read_output(&mut buffer)
+                        terminal.read_output()
                     ) => {
                         match output_result {
                             Ok(Ok(output)) => output,
@@ -171,63 +714,110 @@ impl SessionManager {
                             },
                             Err(_) => {
                                 // 超时，没有数据可读，继续循环
-                                String::new()
+                                OutputChunk::new(String::new())
                             }
                         }
                     }
                 };
+                let output = chunk.data;
                 
                 if !output.is_empty() {
-                    log::info!("Terminal output for session {}: {:?}", session_id, output);
+                    let span = tracing::trace_span!("session.output", session.id = %session_id, bytes = output.len(), clients = tracing::field::Empty);
+                    // 同步处理期间进入span，跨await的触发器动作改用instrument，避免span守卫跨越await
+                    let entered = span.enter();
+                    
+                    // 录制输出
+                    if let Some(recorder) = &recorder {
+                        recorder.record_output(&output);
+                    }
+                    
+                    if let Some(payload) = content_log::payload(&output) {
+                        log::debug!("Terminal output for session {}: {}", session_id, payload);
+                    }
                     
                     // 从会话管理器中获取最新的会话和client_senders
-                    let senders = {
+                    let (senders, firings, events) = {
                         // 获取读锁
                         let sessions_read = session_manager_clone.sessions.read().unwrap();
                         
                         // 查找会话
                         match sessions_read.get(&session_id) {
                             Some(session) => {
-                                // 获取client_senders并克隆
+                                // 获取client_senders并克隆，持有锁时更新屏幕模型，保证新客户端的重绘与后续输出衔接
                                 let client_senders_lock = session.client_senders.lock().unwrap();
+                                let marks = {
+                                    let mut emulator = session.emulator.lock().unwrap();
+                                    emulator.process(&output);
+                                    emulator.take_shell_marks()
+                                };
+                                let events: Vec<ControlEvent> = {
+                                    let mut shell = session.shell.lock().unwrap();
+                                    marks.into_iter().filter_map(|mark| shell.apply(mark)).collect()
+                                };
+                                let firings = session.triggers.lock().unwrap().on_output(&output);
+                                if session.output_tx.receiver_count() > 0 {
+                                    let _ = session.output_tx.send(output.clone());
+                                }
                                 let senders_count = client_senders_lock.len();
-                                log::info!("Found {} client senders for session {}", senders_count, session_id);
-                                client_senders_lock.clone()
+                                log::debug!("Found {} client senders for session {}", senders_count, session_id);
+                                if let Some(input) = session.last_input.lock().unwrap().take() {
+                                    telemetry::link(&span, input);
+                                }
+                                span.record("clients", senders_count);
+                                (client_senders_lock.clone(), firings, events)
                             },
                             None => {
                                 log::warn!("Session {} not found when sending terminal output", session_id);
-                                Vec::new()
+                                (Vec::new(), Vec::new(), Vec::new())
                             }
                         }
                     };
                     
-                    log::info!("Sending terminal output to {} clients for session {}", senders.len(), session_id);
+                    log::debug!("Sending terminal output to {} clients for session {}", senders.len(), session_id);
                     
-                    // 异步发送，不阻塞主循环
-                    for (i, sender) in senders.iter().enumerate() {
-                        // 克隆sender，确保它有'static生命周期
-                        let sender_clone = sender.clone();
-                        let output_clone = output.clone();
-                        let session_id_clone = session_id.clone();
-                        let session_manager_clone = session_manager_clone.clone();
-                        log::info!("Sending output to client {} for session {}", i, session_id_clone);
-                        tokio::spawn(async move {
-                            if let Err(e) = sender_clone.send(output_clone).await {
-                                log::info!("Client sender closed for session {}: {}", session_id_clone, e);
-                                // 异步移除失效的发送者
-                                
-                                // 获取读锁查找会话
-                                let sessions_read = session_manager_clone.sessions.read().unwrap();
-                                if let Some(session) = sessions_read.get(&session_id_clone) {
-                                    let mut client_senders_lock = session.client_senders.lock().unwrap();
-                                    client_senders_lock.retain(|s| !std::ptr::eq(s, &sender_clone));
-                                    log::info!("Removed closed sender from session {}", session_id_clone);
-                                }
-                            } else {
-                                log::info!("Sent terminal output to client for session {}", session_id_clone);
-                            }
-                        });
+                    // 按顺序投递到每个客户端通道，保证输出顺序不被打乱；通道已满的客户端跟不上输出，
+                    // 丢弃一块后屏幕就会错乱，因此断开该客户端，重新连接时按屏幕模型重绘
+                    let message = ServerMessage::Output { data: output };
+                    let mut closed_senders = Vec::new();
+                    for (client_id, sender) in senders.iter() {
+                        match sender.try_send(message.clone()) {
+                            Ok(_) => {},
+                            Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                                session_manager_clone.metrics.output_chunk_dropped();
+                                log::warn!("Client {} of session {} cannot keep up with output, disconnecting", client_id, session_id);
+                                closed_senders.push(client_id.clone());
+                            },
+                            Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
+                                log::info!("Client sender closed for session {}", session_id);
+                                closed_senders.push(client_id.clone());
+                            },
+                        }
+                    }
+                    
+                    session_manager_clone.metrics.pty_read(chunk.read_at.elapsed());
+                    
+                    // 移除失效和跟不上输出的发送者
+                    if !closed_senders.is_empty() {
+                        let session = session_manager_clone.sessions.read().unwrap().get(&session_id).cloned();
+                        if let Some(session) = session {
+                            session_manager_clone.remove_clients(&session_id, &session, &closed_senders);
+                            log::info!("Removed {} closed senders from session {}", closed_senders.len(), session_id);
+                        }
                     }
+                    
+                    // 客户端收到输出后再通知shell状态变化、执行触发器动作
+                    if !events.is_empty() || !firings.is_empty() {
+                        let session = session_manager_clone.sessions.read().unwrap().get(&session_id).cloned();
+                        if let Some(session) = session {
+                            session_manager_clone.record_history(&session_id, &session, &events);
+                            session.broadcast(events);
+                            drop(entered);
+                            session_manager_clone.fire_triggers(&session_id, &session, firings).instrument(span.clone()).await;
+                        }
+                    }
+                } else if last_idle_check.elapsed() >= IDLE_CHECK_INTERVAL {
+                    last_idle_check = tokio::time::Instant::now();
+                    session_manager_clone.check_idle_triggers(&session_id).await;
                 }
                 
                 // 短暂休眠，避免CPU占用过高
@@ -236,45 +826,152 @@ impl SessionManager {
         });
     }
     
-    // 添加客户端发送通道 - 线程安全，只需要&self
-    pub async fn add_client_sender(&self, session_id: &str, sender: tokio::sync::mpsc::Sender<String>) {
+    // 添加客户端发送通道 - 线程安全，只需要&self；会话不存在或已终止时返回错误
+    #[tracing::instrument(level = "debug", name = "session.attach", skip_all, fields(session.id = session_id, user.id = client.user_id.as_deref(), transport = client.transport.as_str()))]
+    pub async fn add_client_sender(&self, session_id: &str, sender: tokio::sync::mpsc::Sender<ServerMessage>, client: &ClientContext) -> SessionResult<()> {
         // 只持有读锁一小段时间
-        let session = {
-            let sessions_read = self.sessions.read().unwrap();
-            match sessions_read.get(session_id) {
-                Some(session) => session.clone(),
-                None => {
-                    log::error!("Session not found: {}", session_id);
-                    return;
-                }
-            }
-        };
+        let session = self.find_session(session_id)?;
+        if session.get_status() == SessionStatus::Terminated {
+            return Err(SessionError::SessionNotFound(session_id.to_string()));
+        }
         
-        // 添加发送者到会话
+        // 添加发送者到会话，先向新客户端发送当前在线状态，再通知所有客户端有人加入
+        let events = {
+            let mut presence = session.presence.lock().unwrap();
+            let events = presence.join(client.participant());
+            let _ = sender.try_send(ServerMessage::Control(presence.snapshot(&client.client_id)));
+            let size = session.sizes.lock().unwrap().effective();
+            let _ = sender.try_send(ServerMessage::Control(ControlEvent::Resize { columns: size.columns, rows: size.rows }));
+            events
+        };
         {
             let mut client_senders = session.client_senders.lock().unwrap();
-            client_senders.push(sender);
+            // 输出监听已启动时，先按屏幕模型重绘当前画面，代替原始输出历史
+            if session.listener_started.load(std::sync::atomic::Ordering::SeqCst) {
+                let repaint = session.emulator.lock().unwrap().screen().repaint();
+                for message in ServerMessage::output_chunks(&repaint) {
+                    if sender.try_send(message).is_err() {
+                        log::warn!("Repaint for client {} of session {} exceeds its channel capacity", client.client_id, session_id);
+                        break;
+                    }
+                }
+            }
+            client_senders.push((client.client_id.clone(), sender));
             log::info!("Added client sender for session: {}", session_id);
         } // 在这里释放client_senders锁
+        session.broadcast(events);
+        self.audit.record(AuditAction::Attach, session_id, client, client.role.map(|role| role.as_str()));
         
+        self.start_listener(session_id, &session).await;
+        Ok(())
+    }
+    
+    // 订阅会话的实时输出，供自动化脚本等服务端功能使用；没有客户端连接时也会开始读取输出
+    pub async fn subscribe_output(&self, session_id: &str) -> SessionResult<tokio::sync::broadcast::Receiver<String>> {
+        let session = self.find_session(session_id)?;
+        let output_rx = session.output_tx.subscribe();
+        self.start_listener(session_id, &session).await;
+        Ok(output_rx)
+    }
+    
+    // 启动终端输出监听任务
+    async fn start_listener(&self, session_id: &str, session: &Session) {
         // 检查是否需要启动终端输出监听任务
         // 使用compare_exchange确保只有一个线程能启动监听任务
-        if !session.listener_started.load(std::sync::atomic::Ordering::SeqCst) {
-            if session.listener_started.compare_exchange(
+        if !session.listener_started.load(std::sync::atomic::Ordering::SeqCst)
+            && session.listener_started.compare_exchange(
                 false, true, 
                 std::sync::atomic::Ordering::SeqCst, 
                 std::sync::atomic::Ordering::SeqCst
             ).is_ok() {
-                // 启动终端输出监听任务
-                log::info!("Starting terminal output listener for session: {}", session_id);
-                self.spawn_terminal_listener(session.terminal.clone(), session_id.to_string()).await;
+            // 启动终端输出监听任务
+            log::info!("Starting terminal output listener for session: {}", session_id);
+            self.spawn_terminal_listener(session.terminal.clone(), session.recorder.clone(), session_id.to_string()).await;
+        }
+    }
+    
+    // 移除客户端，通知其他客户端有人离开，操控者离开时转交控制权，并重新协商终端大小
+    fn remove_clients(&self, session_id: &str, session: &Session, client_ids: &[String]) {
+        session.client_senders.lock().unwrap().retain(|(client_id, _)| !client_ids.contains(client_id));
+        for client_id in client_ids {
+            self.audit.forget_client(session_id, client_id);
+        }
+        let events = {
+            let mut presence = session.presence.lock().unwrap();
+            client_ids.iter().flat_map(|client_id| presence.leave(client_id)).collect::<Vec<_>>()
+        };
+        session.broadcast(events);
+        
+        let changed = {
+            let mut sizes = session.sizes.lock().unwrap();
+            // 逐个移除，不能在第一个变化处短路
+            client_ids.iter().filter(|client_id| sizes.remove(client_id).is_some()).count() > 0
+        };
+        if changed {
+            let session_manager = self.clone();
+            let session_id = session_id.to_string();
+            let session = session.clone();
+            tokio::spawn(async move {
+                if let Err(e) = session_manager.apply_size(&session_id, &session).await {
+                    log::error!("Failed to resize session {} after clients left: {}", session_id, e);
+                }
+            });
+        }
+    }
+    
+    // 客户端断开连接
+    pub fn detach_client(&self, session_id: &str, client_id: &str) {
+        let session = self.sessions.read().unwrap().get(session_id).cloned();
+        if let Some(session) = session {
+            self.remove_clients(session_id, &session, &[client_id.to_string()]);
+            log::info!("Client {} detached from session {}", client_id, session_id);
+        }
+    }
+    
+    // 客户端的输入是否应写入终端 - 单人操控模式下只接受操控者的输入，服务器内部操作（触发器）不受限制
+    pub fn may_write(&self, session_id: &str, client: &ClientContext) -> bool {
+        match self.sessions.read().unwrap().get(session_id) {
+            Some(session) => session.may_write(client),
+            None => client.can_write(),
+        }
+    }
+    
+    // 处理控制权请求，变化广播给会话的所有客户端
+    pub fn handle_control(&self, session_id: &str, client: &ClientContext, message: &ClientMessage) -> anyhow::Result<()> {
+        let session = self.find_session(session_id)?;
+        let events = {
+            let mut presence = session.presence.lock().unwrap();
+            match message {
+                ClientMessage::RequestControl => presence.request(&client.client_id)?,
+                ClientMessage::TakeControl => presence.take(&client.client_id)?,
+                ClientMessage::ReleaseControl => presence.release(&client.client_id)?,
+                ClientMessage::GrantControl { client_id } => presence.grant(&client.client_id, client_id)?,
+                _ => anyhow::bail!("Not a control message"),
             }
+        };
+        if !events.is_empty() {
+            log::info!("Control changed in session {} by client {}: {:?}", session_id, client.client_id, message);
         }
+        session.broadcast(events);
+        Ok(())
     }
     
     // 写入数据到会话 - 线程安全，只需要&self
-    pub async fn write_to_session(&self, session_id: &str, data: &str) -> anyhow::Result<()> {
-        log::debug!("write_to_session called with session_id: {}, data: {:?}", session_id, data);
+    pub async fn write_to_session(&self, session_id: &str, data: &str, client: &ClientContext) -> SessionResult<()> {
+        self.write_input(session_id, data, client, None).await
+    }
+    
+    // 在会话中执行命令，记录一条执行审计日志代替逐行的输入记录
+    pub async fn execute_command(&self, session_id: &str, command: &str, client: &ClientContext) -> SessionResult<()> {
+        self.write_input(session_id, &format!("{}\n", command), client, Some(command)).await
+    }
+    
+    // 写入终端输入，execute为执行审计日志的内容，None时按行记录输入
+    #[tracing::instrument(level = "trace", name = "session.input", skip_all, fields(session.id = session_id, user.id = client.user_id.as_deref(), transport = client.transport.as_str(), bytes = data.len()))]
+    async fn write_input(&self, session_id: &str, data: &str, client: &ClientContext, execute: Option<&str>) -> SessionResult<()> {
+        if let Some(payload) = content_log::payload(data) {
+            log::debug!("write_to_session called with session_id: {}, data: {}", session_id, payload);
+        }
         
         let session = {
             log::debug!("Acquiring read lock for sessions map");
@@ -287,24 +984,151 @@ impl SessionManager {
                 },
                 None => {
                     log::debug!("Session not found: {}", session_id);
-                    anyhow::bail!("Session not found: {}", session_id)
+                    return Err(SessionError::SessionNotFound(session_id.to_string()));
                 },
             }
         };
         
+        // 所有写入终端的途径（连接、REST执行、expect脚本、重新执行）都经过此处检查权限和控制权
+        if !session.may_write(client) {
+            return Err(SessionError::Forbidden(format!("Client is not allowed to write to session {}", session_id)));
+        }
+        
         // 更新最后活动时间
         session.update_last_active_time();
+        self.metrics.bytes_received(client.transport, data.len());
+        
+        // 写入前检查终端模式，判断这次输入是否为不回显的密码；无法判断时按隐藏处理，避免密码写入审计日志
+        let hidden = session.terminal.input_hidden().await.unwrap_or(true);
         
         log::debug!("Got session clone, about to call write_input");
         // 释放会话管理器锁后，执行异步写入
         session.terminal.write_input(data).await?;
-        log::debug!("Wrote data to session {}: {:?}", session_id, data);
+        if let Some(recorder) = &session.recorder {
+            recorder.record_input(data);
+        }
+        session.triggers.lock().unwrap().touch();
+        let changed = session.sizes.lock().unwrap().touch(&client.client_id).is_some();
+        if changed {
+            self.apply_size(session_id, &session).await?;
+        }
+        match execute {
+            Some(detail) => self.audit.record(AuditAction::Execute, session_id, client, Some(if hidden { "[hidden]" } else { detail })),
+            None => self.audit.record_input(session_id, client, data, hidden),
+        }
+        if let Some(span_context) = telemetry::current_span_context() {
+            *session.last_input.lock().unwrap() = Some(span_context);
+        }
+        if let Some(payload) = content_log::payload(data) {
+            log::debug!("Wrote data to session {}: {}", session_id, payload);
+        }
         
         Ok(())
     }
     
+    // shell自行退出后移除会话，记录退出码
+    async fn finish_exited_session(&self, session_id: &str, terminal: &TerminalProcess) {
+        let removed = self.sessions.write().unwrap().remove(session_id);
+        let Some(session) = removed else { return };
+        session.set_status(SessionStatus::Terminated);
+        
+        let exit_code = terminal.exit_code().await;
+        log::info!("Session {} ended with exit code {:?}", session_id, exit_code);
+        self.audit.record(AuditAction::Terminate, session_id, &ClientContext::system(), Some("Shell exited"));
+        self.audit.forget_session(session_id);
+        self.shares.remove_session(session_id);
+        self.terminate_record(session_id, &session, exit_code, "Shell exited");
+        
+        let firings = session.triggers.lock().unwrap().on_exit(exit_code);
+        self.fire_triggers(session_id, &session, firings).await;
+    }
+    
+    // 检查会话的空闲触发器
+    async fn check_idle_triggers(&self, session_id: &str) {
+        let session = self.sessions.read().unwrap().get(session_id).cloned();
+        let Some(session) = session else { return };
+        let firings = session.triggers.lock().unwrap().on_idle_check();
+        self.fire_triggers(session_id, &session, firings).await;
+    }
+    
+    // 执行触发器动作，失败只记录日志
+    async fn fire_triggers(&self, session_id: &str, session: &Session, firings: Vec<Firing>) {
+        for firing in firings {
+            log::info!("Trigger {} fired in session {}: {:?}", firing.name, session_id, firing.cause);
+            match &firing.action {
+                TriggerAction::Webhook { url } => self.webhooks.send(url, session_id, &firing),
+                TriggerAction::Control => session.broadcast(vec![ControlEvent::Trigger {
+                    name: firing.name.clone(),
+                    cause: firing.cause.clone(),
+                }]),
+                TriggerAction::Input { data } => {
+                    if let Err(e) = self.write_to_session(session_id, data, &ClientContext::system()).await {
+                        log::warn!("Trigger {} failed to write to session {}: {}", firing.name, session_id, e);
+                    }
+                },
+            }
+        }
+    }
+    
+    // 为会话添加触发器，webhook地址必须在配置允许的范围内
+    pub async fn add_trigger(&self, session_id: &str, mut rule: TriggerRule, client: &ClientContext) -> SessionResult<()> {
+        let session = self.find_session(session_id)?;
+        check_owner(session_id, client)?;
+        trigger::check_webhook_allowed(&rule, &self.config.triggers.webhook_url_prefixes)
+            .map_err(|e| SessionError::Forbidden(e.to_string()))?;
+        rule.shells.clear();
+        let name = rule.name.clone();
+        {
+            let mut triggers = session.triggers.lock().unwrap();
+            if triggers.contains(&name) {
+                return Err(SessionError::Conflict(format!("Trigger '{}' already exists", name)));
+            }
+            triggers.add(rule).map_err(|e| SessionError::Invalid(e.to_string()))?;
+        }
+        log::info!("Added trigger {} to session {}", name, session_id);
+        self.audit.record(AuditAction::TriggerAdd, session_id, client, Some(&name));
+        
+        // 触发器需要读取输出，没有客户端连接时也启动监听
+        self.start_listener(session_id, &session).await;
+        Ok(())
+    }
+    
+    // 列出会话的触发器
+    pub fn list_triggers(&self, session_id: &str, client: &ClientContext) -> SessionResult<Vec<TriggerRule>> {
+        let session = self.find_session(session_id)?;
+        check_reader(session_id, client)?;
+        let rules = session.triggers.lock().unwrap().rules();
+        Ok(rules)
+    }
+    
+    // 删除会话的触发器
+    pub fn remove_trigger(&self, session_id: &str, name: &str, client: &ClientContext) -> SessionResult<()> {
+        let session = self.find_session(session_id)?;
+        check_owner(session_id, client)?;
+        if !session.triggers.lock().unwrap().remove(name) {
+            return Err(SessionError::NotFound(format!("Trigger not found: {}", name)));
+        }
+        log::info!("Removed trigger {} from session {}", name, session_id);
+        self.audit.record(AuditAction::TriggerRemove, session_id, client, Some(name));
+        Ok(())
+    }
+    
+    // 将会话记录标记为已终止，并统计会话时长
+    fn terminate_record(&self, session_id: &str, session: &Session, exit_code: Option<u32>, reason: &str) {
+        self.update_record(session_id, |record| {
+            record.exit_code = exit_code;
+            record.last_active_time = session.get_last_active_time();
+            record.current_directory = session.shell.lock().unwrap().current_directory.clone();
+            record.transition(SessionStatus::Terminated, reason);
+            let lifetime = std::time::Duration::from_millis(now_millis().saturating_sub(record.created_at));
+            self.metrics.session_terminated(&record.shell_type, reason, lifetime);
+        });
+    }
+    
     // 关闭会话 - 线程安全，只需要&self，幂等设计
-    pub async fn close_session(&self, session_id: &str) -> anyhow::Result<()> {
+    #[tracing::instrument(level = "debug", name = "session.close", skip_all, fields(session.id = session_id, user.id = client.user_id.as_deref(), transport = client.transport.as_str(), reason))]
+    pub async fn close_session(&self, session_id: &str, reason: &str, client: &ClientContext) -> SessionResult<()> {
+        check_owner(session_id, client)?;
         // 先从映射中移除会话，避免竞争条件
         let session = {
             let mut sessions_write = self.sessions.write().unwrap();
@@ -314,61 +1138,241 @@ impl SessionManager {
                     session.set_status(SessionStatus::Terminated);
                     session
                 },
-                None => anyhow::bail!("Session not found: {}", session_id),
+                None => return Err(SessionError::SessionNotFound(session_id.to_string())),
             }
         };
         
         // 释放会话管理器锁后，执行异步关闭
-        session.terminal.close().await?;
-        log::info!("Closed session: {}", session_id);
+        let signal_timeout = std::time::Duration::from_millis(self.config.shutdown.signal_timeout_ms);
+        let close_result = session.terminal.close(signal_timeout).await;
+        let exit_code = session.terminal.exit_code().await;
+        self.terminate_record(session_id, &session, exit_code, reason);
+        self.audit.record(AuditAction::Terminate, session_id, client, Some(reason));
+        self.audit.forget_session(session_id);
+        self.shares.remove_session(session_id);
+        
+        // 会话已从映射中移除，监听任务不会再处理shell退出，在此触发退出触发器
+        let firings = session.triggers.lock().unwrap().on_exit(exit_code);
+        self.fire_triggers(session_id, &session, firings).await;
+        close_result?;
+        log::info!("Closed session: {} ({})", session_id, reason);
         
         Ok(())
     }
     
-    // 检查会话是否存在 - 线程安全，只需要&self
-    pub async fn session_exists(&self, session_id: &str) -> bool {
-        let sessions_read = self.sessions.read().unwrap();
-        sessions_read.contains_key(session_id)
+    // 为会话创建共享，expires_in为有效期（秒），不超过配置的最长有效期
+    pub async fn create_share(
+        &self,
+        session_id: &str,
+        role: ShareRole,
+        expires_in: Option<u64>,
+        max_uses: Option<u32>,
+        client: &ClientContext,
+    ) -> SessionResult<Share> {
+        if !self.session_exists(session_id).await {
+            return Err(SessionError::SessionNotFound(session_id.to_string()));
+        }
+        check_owner(session_id, client)?;
+        let sharing = &self.config.sharing;
+        let expires_in = expires_in.unwrap_or(sharing.default_expires_in).min(sharing.max_expires_in);
+        let share = self.shares.create(session_id, role, expires_in * 1000, max_uses, client.user_id.clone());
+        log::info!("Created {} share {} for session {}", role.as_str(), share.id, session_id);
+        self.audit.record(AuditAction::ShareCreate, session_id, client, Some(&format!("{} {}", share.id, role.as_str())));
+        Ok(share)
     }
     
-    // 获取会话状态 - 线程安全，只需要&self
-    pub async fn get_session_status(&self, session_id: &str) -> anyhow::Result<SessionStatus> {
+    // 列出会话的共享
+    pub async fn list_shares(&self, session_id: &str, client: &ClientContext) -> SessionResult<Vec<Share>> {
+        if !self.session_exists(session_id).await {
+            return Err(SessionError::SessionNotFound(session_id.to_string()));
+        }
+        check_owner(session_id, client)?;
+        Ok(self.shares.list(session_id))
+    }
+    
+    // 撤销共享，之后该令牌无法再连接
+    pub fn revoke_share(&self, session_id: &str, share_id: &str, client: &ClientContext) -> SessionResult<Share> {
+        check_owner(session_id, client)?;
+        let share = self.shares.revoke(session_id, share_id)
+            .ok_or_else(|| SessionError::NotFound(format!("Share not found: {}", share_id)))?;
+        log::info!("Revoked share {} for session {}", share_id, session_id);
+        self.audit.record(AuditAction::ShareRevoke, session_id, client, Some(share_id));
+        Ok(share)
+    }
+    
+    // 校验客户端出示的共享令牌并确定其角色，不计令牌使用次数；没有令牌时只有非空用户与会话创建者一致才是所有者，否则没有角色
+    pub fn authorize_client(&self, session_id: &str, client: &mut ClientContext) -> anyhow::Result<()> {
+        if client.role.is_some() {
+            return Ok(());
+        }
+        if let Some(token) = &client.share_token {
+            client.role = Some(self.shares.validate(session_id, token)?);
+        } else if self.session_owner(session_id).is_some_and(|owner| owns_session(&owner, client.user_id.as_deref())) {
+            client.role = Some(ShareRole::Owner);
+        }
+        Ok(())
+    }
+    
+    // 客户端连接到会话时确定角色，出示的共享令牌计一次使用
+    pub fn authorize_connection(&self, session_id: &str, client: &mut ClientContext) -> anyhow::Result<()> {
+        if let Some(token) = &client.share_token {
+            client.role = Some(self.shares.redeem(session_id, token)?);
+            return Ok(());
+        }
+        self.authorize_client(session_id, client)
+    }
+    
+    // 会话的创建者，已终止的会话从存储中查找
+    fn session_owner(&self, session_id: &str) -> Option<String> {
+        if let Some(session) = self.sessions.read().unwrap().get(session_id) {
+            return Some(session.user_id.clone());
+        }
+        self.repository.find(session_id).ok().flatten().map(|record| record.user_id)
+    }
+    
+    // 关闭所有会话 - 并发终止所有shell
+    pub async fn close_all_sessions(&self) {
+        let session_ids = {
+            let sessions_read = self.sessions.read().unwrap();
+            sessions_read.keys().cloned().collect::<Vec<String>>()
+        };
+        
+        let close_tasks = session_ids.into_iter().map(|session_id| async move {
+            if let Err(e) = self.close_session(&session_id, "Server shutdown", &ClientContext::system()).await {
+                log::error!("Failed to close session {}: {}", session_id, e);
+            }
+        });
+        futures_util::future::join_all(close_tasks).await;
+    }
+    
+    // 向所有会话的所有客户端广播消息
+    pub fn broadcast_to_all(&self, message: ServerMessage) {
         let sessions_read = self.sessions.read().unwrap();
-        match sessions_read.get(session_id) {
-            Some(session) => Ok(session.get_status()),
-            None => anyhow::bail!("Session not found: {}", session_id),
+        for (session_id, session) in sessions_read.iter() {
+            let client_senders = session.client_senders.lock().unwrap();
+            for (_, sender) in client_senders.iter() {
+                if let Err(e) = sender.try_send(message.clone()) {
+                    log::debug!("Failed to broadcast message to client of session {}: {}", session_id, e);
+                }
+            }
         }
     }
     
-
+    // 获取当前会话数量
+    pub fn session_count(&self) -> usize {
+        self.sessions.read().unwrap().len()
+    }
     
-    // 获取所有会话及其状态 - 线程安全，只需要&self
-    pub async fn get_all_sessions_with_status(&self) -> Vec<(String, SessionStatus)> {
+    // 检查会话是否存在 - 线程安全，只需要&self
+    pub async fn session_exists(&self, session_id: &str) -> bool {
         let sessions_read = self.sessions.read().unwrap();
-        sessions_read.iter()
-            .map(|(id, session)| (id.clone(), session.get_status()))
-            .collect()
+        sessions_read.contains_key(session_id)
+    }
+    
+    // 获取会话状态 - 已终止的会话从会话记录中查询
+    pub async fn get_session_status(&self, session_id: &str) -> SessionResult<SessionStatus> {
+        if let Some(session) = self.sessions.read().unwrap().get(session_id) {
+            return Ok(session.get_status());
+        }
+        match self.repository.find(session_id)? {
+            Some(record) => Ok(record.status),
+            None => Err(SessionError::SessionNotFound(session_id.to_string())),
+        }
     }
     
-    // 调整终端大小 - 线程安全，只需要&self
-    pub async fn resize_session(&self, session_id: &str, columns: u32, rows: u32) -> anyhow::Result<()> {
+    // 上报客户端窗口大小，按大小策略协商后调整终端，返回终端实际大小
+    // 已连接的客户端上报自己的大小，REST等未连接的调用者设置无人上报时使用的大小
+    #[tracing::instrument(level = "debug", name = "session.resize", skip_all, fields(session.id = session_id, user.id = client.user_id.as_deref(), transport = client.transport.as_str(), columns, rows))]
+    pub async fn resize_session(&self, session_id: &str, columns: u32, rows: u32, client: &ClientContext) -> SessionResult<TerminalSize> {
+        check_size(&TerminalSize { columns, rows })?;
+        if !client.can_write() {
+            return Err(SessionError::Forbidden(format!("Client is not allowed to resize session {}", session_id)));
+        }
+        
         // 只持有读锁一小段时间，获取会话引用
         let session = {
             let sessions_read = self.sessions.read().unwrap();
             match sessions_read.get(session_id) {
                 Some(session) => session.clone(),
-                None => anyhow::bail!("Session not found: {}", session_id),
+                None => return Err(SessionError::SessionNotFound(session_id.to_string())),
             }
         };
         
         // 更新最后活动时间
         session.update_last_active_time();
+        self.audit.record(AuditAction::Resize, session_id, client, Some(&format!("{}x{}", columns, rows)));
+        
+        let attached = session.client_senders.lock().unwrap().iter().any(|(client_id, _)| client_id == &client.client_id);
+        let (size, effective) = {
+            let mut sizes = session.sizes.lock().unwrap();
+            let size = if attached {
+                sizes.report(&client.client_id, client.user_id.as_deref(), TerminalSize { columns, rows })
+            } else {
+                sizes.set_base(TerminalSize { columns, rows })
+            };
+            (size, sizes.effective())
+        };
         
-        // 释放会话管理器锁后，执行异步调整大小
+        // 释放锁后，执行异步调整大小
+        if size.is_some() {
+            self.apply_size(session_id, &session).await?;
+        }
+        Ok(effective)
+    }
+    
+    // 向会话的前台进程组或shell发送信号，返回接收信号的进程组或进程ID
+    pub async fn signal_session(&self, session_id: &str, signal: Signal, target: SignalTarget, client: &ClientContext) -> SessionResult<u32> {
+        if !client.can_write() {
+            return Err(SessionError::Forbidden(format!("Client is not allowed to signal session {}", session_id)));
+        }
+        let session = self.find_session(session_id)?;
+        // shell退出后其进程ID可能被其他进程复用
+        if !session.terminal.is_running().await {
+            return Err(SessionError::Conflict(format!("Shell of session {} has exited", session_id)));
+        }
+        
+        session.update_last_active_time();
+        let pid = session.terminal.signal(signal, target).await?;
+        log::info!("Sent {} to {} {} of session {}", signal.name(), target.as_str(), pid, session_id);
+        self.audit.record(AuditAction::Signal, session_id, client, Some(&format!("{} to {} {}", signal.name(), target.as_str(), pid)));
+        Ok(pid)
+    }
+    
+    // 查询会话PTY的前台进程
+    pub async fn foreground_process(&self, session_id: &str, client: &ClientContext) -> SessionResult<ForegroundProcess> {
+        let session = self.find_session(session_id)?;
+        check_reader(session_id, client)?;
+        let process_group = session.terminal.foreground_process_group().await
+            .ok_or_else(|| SessionError::Unavailable("Foreground process group is unavailable".to_string()))?;
+        let shell_pid = session.terminal.shell_pid().await;
+        // 读取 /proc 是阻塞操作，可能需要扫描所有进程
+        tokio::task::spawn_blocking(move || ForegroundProcess::describe(process_group, shell_pid))
+            .await
+            .map_err(|e| SessionError::Other(e.into()))
+    }
+    
+    // 将协商后的大小应用到终端，并通知所有客户端 - 同一会话的调整串行执行，执行时读取最新的协商结果，
+    // 并发的调整不会以旧的大小覆盖新的大小
+    async fn apply_size(&self, session_id: &str, session: &Session) -> anyhow::Result<()> {
+        let mut applied_size = session.applied_size.lock().await;
+        let size = session.sizes.lock().unwrap().effective();
+        if *applied_size == size {
+            return Ok(());
+        }
+        let TerminalSize { columns, rows } = size.clone();
         session.terminal.resize(columns, rows).await?;
+        *applied_size = size.clone();
+        session.emulator.lock().unwrap().resize(&size);
+        if let Some(recorder) = &session.recorder {
+            recorder.record_resize(columns, rows);
+        }
+        self.update_record(session_id, |record| {
+            record.terminal_size = TerminalSize { columns, rows };
+            record.updated_at = now_millis();
+        });
+        session.broadcast(vec![ControlEvent::Resize { columns, rows }]);
         
         log::info!("Resized session {} to {} columns x {} rows", session_id, columns, rows);
-        
         Ok(())
     }
     
@@ -403,12 +1407,19 @@ impl SessionManager {
                 // 获取会话的最后活动时间
                 let last_active_time = session.get_last_active_time();
                 
+                // 没有客户端连接时不会启动监听任务，在这里发现已退出的shell
+                if !session.terminal.is_running().await {
+                    self.finish_exited_session(&session_id, &session.terminal).await;
+                    continue;
+                }
+                
                 // 检查会话是否过期
                 if session.is_expired() {
                     log::info!("Session {} has expired (last active: {}), closing it", session_id, last_active_time);
+                    self.metrics.session_expired();
                     
                     // 关闭会话 - close_session方法已经包含了从映射中移除的逻辑
-                    if let Err(e) = self.close_session(&session_id).await {
+                    if let Err(e) = self.close_session(&session_id, "Session expired", &ClientContext::system()).await {
                         log::error!("Failed to close expired session {}: {}", session_id, e);
                     }
                 } else {
@@ -420,13 +1431,13 @@ impl SessionManager {
     }
     
     // 更新会话最后活动时间
-    pub async fn update_session_activity(&self, session_id: &str) -> anyhow::Result<()> {
+    pub async fn update_session_activity(&self, session_id: &str) -> SessionResult<()> {
         // 获取会话引用
         let session = {
             let sessions_read = self.sessions.read().unwrap();
             match sessions_read.get(session_id) {
                 Some(session) => session.clone(),
-                None => anyhow::bail!("Session not found: {}", session_id),
+                None => return Err(SessionError::SessionNotFound(session_id.to_string())),
             }
         };
         
@@ -436,3 +1447,18 @@ impl SessionManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_requires_a_role() {
+        let mut client = ClientContext::new(TransportKind::Http, None, Some("alice".to_string()));
+        assert!(matches!(check_reader("s1", &client), Err(SessionError::Forbidden(_))));
+        for role in [ShareRole::Viewer, ShareRole::Collaborator, ShareRole::Owner] {
+            client.role = Some(role);
+            assert!(check_reader("s1", &client).is_ok());
+        }
+    }
+}
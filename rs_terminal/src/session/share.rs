@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::repository::now_millis;
+
+// 共享角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareRole {
+    // 只读观看，输入和调整大小被丢弃
+    Viewer,
+    // 协作者，可以输入
+    Collaborator,
+    // 会话所有者（未出示令牌且用户与创建者一致）和服务器内部操作，不能通过共享授予
+    Owner,
+}
+
+impl ShareRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareRole::Viewer => "viewer",
+            ShareRole::Collaborator => "collaborator",
+            ShareRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "viewer" => Some(ShareRole::Viewer),
+            "collaborator" => Some(ShareRole::Collaborator),
+            _ => None,
+        }
+    }
+
+    // 是否允许向终端输入
+    pub fn can_write(&self) -> bool {
+        matches!(self, ShareRole::Collaborator | ShareRole::Owner)
+    }
+}
+
+// 用户是否为会话所有者 - 匿名客户端和没有用户的会话都不属于任何人
+pub fn owns_session(owner: &str, user_id: Option<&str>) -> bool {
+    !owner.is_empty() && user_id == Some(owner)
+}
+
+// 会话共享 - 持有令牌的客户端以指定角色连接到会话
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub id: String,
+    pub session_id: String,
+    // 只在创建时返回，列表中为空
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub token: String,
+    pub role: ShareRole,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    // 最大使用次数，每次连接计一次；None为不限
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+}
+
+impl Share {
+    fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.max_uses.is_some_and(|max_uses| self.uses >= max_uses)
+    }
+}
+
+// 共享存储，键为令牌；会话关闭时一并删除
+#[derive(Default)]
+pub struct ShareStore {
+    shares: Mutex<HashMap<String, Share>>,
+}
+
+impl ShareStore {
+    // 创建共享，expires_in为有效期（毫秒）
+    pub fn create(&self, session_id: &str, role: ShareRole, expires_in: u64, max_uses: Option<u32>, created_by: Option<String>) -> Share {
+        let now = now_millis();
+        let share = Share {
+            id: Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            token: Uuid::new_v4().simple().to_string(),
+            role,
+            created_by,
+            created_at: now,
+            expires_at: now + expires_in,
+            max_uses,
+            uses: 0,
+        };
+        let mut shares = self.shares.lock().unwrap();
+        shares.retain(|_, share| !share.is_expired(now));
+        shares.insert(share.token.clone(), share.clone());
+        share
+    }
+
+    // 列出会话未过期的共享，按创建时间排序，不包含令牌
+    pub fn list(&self, session_id: &str) -> Vec<Share> {
+        let now = now_millis();
+        let mut shares = self.shares.lock().unwrap().values()
+            .filter(|share| share.session_id == session_id && !share.is_expired(now))
+            .map(|share| Share { token: String::new(), ..share.clone() })
+            .collect::<Vec<_>>();
+        shares.sort_by_key(|share| share.created_at);
+        shares
+    }
+
+    // 撤销共享，已连接的客户端不受影响
+    pub fn revoke(&self, session_id: &str, share_id: &str) -> Option<Share> {
+        let mut shares = self.shares.lock().unwrap();
+        let token = shares.values()
+            .find(|share| share.session_id == session_id && share.id == share_id)
+            .map(|share| share.token.clone())?;
+        shares.remove(&token)
+    }
+
+    // 校验令牌，返回共享角色，不计使用次数；REST请求每次都会校验
+    pub fn validate(&self, session_id: &str, token: &str) -> anyhow::Result<ShareRole> {
+        let mut shares = self.shares.lock().unwrap();
+        Ok(find_usable(&mut shares, session_id, token)?.role)
+    }
+
+    // 使用令牌连接会话，返回共享角色并计一次使用
+    pub fn redeem(&self, session_id: &str, token: &str) -> anyhow::Result<ShareRole> {
+        let mut shares = self.shares.lock().unwrap();
+        let share = find_usable(&mut shares, session_id, token)?;
+        share.uses += 1;
+        Ok(share.role)
+    }
+
+    // 删除会话的所有共享
+    pub fn remove_session(&self, session_id: &str) {
+        self.shares.lock().unwrap().retain(|_, share| share.session_id != session_id);
+    }
+}
+
+// 查找属于会话且未过期、未用尽的共享
+fn find_usable<'a>(shares: &'a mut HashMap<String, Share>, session_id: &str, token: &str) -> anyhow::Result<&'a mut Share> {
+    let share = shares.get_mut(token)
+        .filter(|share| share.session_id == session_id)
+        .ok_or_else(|| anyhow::anyhow!("Share not found for session {}", session_id))?;
+    if share.is_expired(now_millis()) {
+        anyhow::bail!("Share {} has expired", share.id);
+    }
+    if share.is_exhausted() {
+        anyhow::bail!("Share {} has no uses left", share.id);
+    }
+    Ok(share)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles() {
+        assert_eq!(ShareRole::parse("viewer"), Some(ShareRole::Viewer));
+        assert_eq!(ShareRole::parse("collaborator"), Some(ShareRole::Collaborator));
+        // 所有者不能通过共享授予
+        assert_eq!(ShareRole::parse("owner"), None);
+        assert!(!ShareRole::Viewer.can_write());
+        assert!(ShareRole::Collaborator.can_write());
+        assert!(ShareRole::Owner.can_write());
+    }
+
+    #[test]
+    fn ownership_requires_matching_user() {
+        assert!(owns_session("alice", Some("alice")));
+        assert!(!owns_session("alice", Some("bob")));
+        assert!(!owns_session("alice", None));
+        // 匿名创建的会话不能被匿名客户端或空用户接管
+        assert!(!owns_session("", None));
+        assert!(!owns_session("", Some("")));
+    }
+
+    #[test]
+    fn redeem_checks_session_and_uses() {
+        let store = ShareStore::default();
+        let share = store.create("s1", ShareRole::Viewer, 60_000, Some(2), None);
+        assert!(store.redeem("s2", &share.token).is_err());
+        assert!(store.redeem("s1", "unknown").is_err());
+        assert_eq!(store.redeem("s1", &share.token).unwrap(), ShareRole::Viewer);
+        assert_eq!(store.redeem("s1", &share.token).unwrap(), ShareRole::Viewer);
+        assert!(store.redeem("s1", &share.token).is_err());
+        assert_eq!(store.list("s1")[0].uses, 2);
+    }
+
+    #[test]
+    fn validate_does_not_count_uses() {
+        let store = ShareStore::default();
+        let share = store.create("s1", ShareRole::Collaborator, 60_000, Some(1), None);
+        assert!(store.validate("s2", &share.token).is_err());
+        for _ in 0..3 {
+            assert_eq!(store.validate("s1", &share.token).unwrap(), ShareRole::Collaborator);
+        }
+        assert_eq!(store.list("s1")[0].uses, 0);
+
+        // 连接后用尽，校验同样失败
+        assert_eq!(store.redeem("s1", &share.token).unwrap(), ShareRole::Collaborator);
+        assert!(store.validate("s1", &share.token).is_err());
+    }
+
+    #[test]
+    fn expired_shares() {
+        let store = ShareStore::default();
+        let expired = store.create("s1", ShareRole::Collaborator, 0, None, None);
+        assert!(store.redeem("s1", &expired.token).is_err());
+        assert!(store.list("s1").is_empty());
+
+        // 创建时清理已过期的共享
+        store.create("s1", ShareRole::Viewer, 60_000, None, None);
+        assert!(!store.shares.lock().unwrap().contains_key(&expired.token));
+    }
+
+    #[test]
+    fn list_hides_tokens() {
+        let store = ShareStore::default();
+        let share = store.create("s1", ShareRole::Viewer, 60_000, None, Some("alice".to_string()));
+        store.create("s2", ShareRole::Viewer, 60_000, None, None);
+
+        let listed = store.list("s1");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, share.id);
+        assert!(listed[0].token.is_empty());
+        assert!(!serde_json::to_value(&listed[0]).unwrap().as_object().unwrap().contains_key("token"));
+        assert!(serde_json::to_value(&share).unwrap()["token"].is_string());
+    }
+
+    #[test]
+    fn revoke_and_remove_session() {
+        let store = ShareStore::default();
+        let first = store.create("s1", ShareRole::Viewer, 60_000, None, None);
+        let second = store.create("s1", ShareRole::Viewer, 60_000, None, None);
+        assert!(store.revoke("s2", &first.id).is_none());
+        assert_eq!(store.revoke("s1", &first.id).map(|share| share.id), Some(first.id.clone()));
+        assert!(store.redeem("s1", &first.token).is_err());
+
+        store.remove_session("s1");
+        assert!(store.redeem("s1", &second.token).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ShellConfig;
+use crate::protocol::message::ControlEvent;
+use crate::session::repository::now_millis;
+use crate::session::screen::ShellMark;
+
+// 每个会话在内存中保留的已执行命令数量
+const MAX_SESSION_HISTORY: usize = 1000;
+
+// bash集成脚本，通过 --init-file 加载，先按登录shell或交互shell执行用户的初始化文件
+const BASH_SCRIPT: &str = r#"# rs_terminal shell集成：OSC 133标记提示符和命令边界，OSC 7上报当前目录
+if [ -n "$RS_TERMINAL_SHELL_LOGIN" ]; then
+    unset RS_TERMINAL_SHELL_LOGIN
+    if [ -r /etc/profile ]; then . /etc/profile; fi
+    for __rs_terminal_profile in ~/.bash_profile ~/.bash_login ~/.profile; do
+        if [ -r "$__rs_terminal_profile" ]; then . "$__rs_terminal_profile"; break; fi
+    done
+    unset __rs_terminal_profile
+elif [ -f ~/.bashrc ]; then
+    . ~/.bashrc
+fi
+if [ -z "$__rs_terminal_loaded" ]; then
+    __rs_terminal_loaded=1
+    __rs_terminal_prompt() {
+        local status=$?
+        if [ -n "$__rs_terminal_started" ]; then printf '\033]133;D;%s\007' "$status"; fi
+        __rs_terminal_started=1
+        printf '\033]7;file://%s%s\007' "$HOSTNAME" "$PWD"
+        printf '\033]133;A\007'
+        return $status
+    }
+    PROMPT_COMMAND="__rs_terminal_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+    PS1="${PS1}\[\033]133;B\007\]"
+    PS0="${PS0}\033]133;C\007"
+fi
+"#;
+
+// zsh通过ZDOTDIR指向集成目录，.zshenv加载用户的 .zshenv
+const ZSH_ENV_SCRIPT: &str = r#"# rs_terminal shell集成
+__rs_terminal_zdotdir=$ZDOTDIR
+ZDOTDIR=${RS_TERMINAL_ZDOTDIR:-$HOME}
+[[ -f $ZDOTDIR/.zshenv ]] && source $ZDOTDIR/.zshenv
+# 用户的 .zshenv 可能修改ZDOTDIR
+RS_TERMINAL_ZDOTDIR=$ZDOTDIR
+ZDOTDIR=$__rs_terminal_zdotdir
+unset __rs_terminal_zdotdir
+"#;
+
+// 登录shell读取用户的 .zprofile，之后 .zshrc 恢复ZDOTDIR，.zlogin直接从用户目录读取
+const ZSH_PROFILE_SCRIPT: &str = r#"# rs_terminal shell集成
+[[ -f ${RS_TERMINAL_ZDOTDIR:-$HOME}/.zprofile ]] && source ${RS_TERMINAL_ZDOTDIR:-$HOME}/.zprofile
+"#;
+
+const ZSH_RC_SCRIPT: &str = r#"# rs_terminal shell集成：OSC 133标记提示符和命令边界，OSC 7上报当前目录
+ZDOTDIR=${RS_TERMINAL_ZDOTDIR:-$HOME}
+unset RS_TERMINAL_ZDOTDIR
+[[ -f $ZDOTDIR/.zshrc ]] && source $ZDOTDIR/.zshrc
+if [[ -z $__rs_terminal_loaded ]]; then
+    __rs_terminal_loaded=1
+    __rs_terminal_precmd() {
+        local ret=$?
+        if [[ -n $__rs_terminal_running ]]; then printf '\033]133;D;%s\007' $ret; fi
+        __rs_terminal_running=
+        printf '\033]7;file://%s%s\007' $HOST $PWD
+        printf '\033]133;A\007'
+        [[ $PS1 == *$'\033]133;B\007'* ]] || PS1="$PS1%{"$'\033]133;B\007'"%}"
+    }
+    __rs_terminal_preexec() {
+        __rs_terminal_running=1
+        printf '\033]133;C\007'
+    }
+    # 放在最前面，保证取到的是命令的退出码
+    precmd_functions=(__rs_terminal_precmd $precmd_functions)
+    preexec_functions+=(__rs_terminal_preexec)
+fi
+"#;
+
+// fish集成脚本，通过 --init-command 在用户配置之后加载
+const FISH_SCRIPT: &str = r#"# rs_terminal shell集成：OSC 133标记提示符和命令边界，OSC 7上报当前目录
+if not set -q __rs_terminal_loaded
+    set -g __rs_terminal_loaded 1
+    function __rs_terminal_preexec --on-event fish_preexec
+        printf '\e]133;C\a'
+    end
+    function __rs_terminal_postexec --on-event fish_postexec
+        printf '\e]133;D;%s\a' $status
+    end
+    function __rs_terminal_cwd --on-variable PWD
+        printf '\e]7;file://%s%s\a' $hostname $PWD
+    end
+    __rs_terminal_cwd
+    functions -c fish_prompt __rs_terminal_fish_prompt
+    function fish_prompt
+        printf '\e]133;A\a'
+        __rs_terminal_fish_prompt
+        printf '\e]133;B\a'
+    end
+end
+"#;
+
+// 写入集成脚本，返回脚本目录的绝对路径
+pub fn install(directory: &str) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(Path::new(directory).join("zsh"))?;
+    let directory = std::fs::canonicalize(directory)?;
+    std::fs::write(directory.join("rs_terminal.bash"), BASH_SCRIPT)?;
+    std::fs::write(directory.join("zsh").join(".zshenv"), ZSH_ENV_SCRIPT)?;
+    std::fs::write(directory.join("zsh").join(".zprofile"), ZSH_PROFILE_SCRIPT)?;
+    std::fs::write(directory.join("zsh").join(".zshrc"), ZSH_RC_SCRIPT)?;
+    std::fs::write(directory.join("rs_terminal.fish"), FISH_SCRIPT)?;
+    Ok(directory)
+}
+
+// 按shell程序修改启动命令或环境变量以加载集成脚本，不支持的shell或已指定启动参数时返回false
+pub fn inject(shell_config: &mut ShellConfig, directory: &Path) -> bool {
+    let program = Path::new(&shell_config.command[0])
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // 执行命令或自行指定初始化文件时不注入
+    if shell_config.command[1..].iter().any(|arg| matches!(arg.as_str(), "-c" | "--norc" | "--rcfile" | "--init-file")) {
+        return false;
+    }
+
+    match program.as_str() {
+        "bash" => {
+            // 指定 --init-file 时bash不再读取登录配置，改由集成脚本按登录shell加载
+            let login = shell_config.command.iter().any(|arg| arg == "-l" || arg == "--login");
+            if login {
+                shell_config.command.retain(|arg| arg != "-l" && arg != "--login");
+                shell_config.environment.insert("RS_TERMINAL_SHELL_LOGIN".to_string(), "1".to_string());
+            }
+            let script = directory.join("rs_terminal.bash").to_string_lossy().into_owned();
+            shell_config.command.splice(1..1, ["--init-file".to_string(), script]);
+        },
+        "zsh" => {
+            let original = shell_config.environment.get("ZDOTDIR").cloned().or_else(|| std::env::var("ZDOTDIR").ok());
+            if let Some(original) = original {
+                shell_config.environment.insert("RS_TERMINAL_ZDOTDIR".to_string(), original);
+            }
+            shell_config.environment.insert("ZDOTDIR".to_string(), directory.join("zsh").to_string_lossy().into_owned());
+        },
+        "fish" => {
+            let script = directory.join("rs_terminal.fish").to_string_lossy().replace('\\', "\\\\").replace('\'', "\\'");
+            shell_config.command.splice(1..1, ["--init-command".to_string(), format!("source '{}'", script)]);
+        },
+        _ => return false,
+    }
+    true
+}
+
+// 一条命令的执行情况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandInfo {
+    // 会话内的命令序号，从1开始
+    pub number: u64,
+    // 从屏幕上读取的命令行，提示符结束标记缺失时为None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    // 执行命令时的当前目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    pub started_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+// 会话的shell状态，由shell集成标记更新
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellState {
+    // 是否收到过提示符或命令标记
+    pub integrated: bool,
+    // shell上报的当前目录
+    pub current_directory: Option<String>,
+    // 正在执行的命令
+    pub current_command: Option<CommandInfo>,
+    // 最近执行完的命令
+    pub last_command: Option<CommandInfo>,
+    // 已执行完的命令，最早的在前
+    #[serde(skip)]
+    history: VecDeque<CommandInfo>,
+    #[serde(skip)]
+    next_number: u64,
+}
+
+impl ShellState {
+    // 根据标记更新状态，返回需要通知客户端的事件
+    pub fn apply(&mut self, mark: ShellMark) -> Option<ControlEvent> {
+        match mark {
+            ShellMark::WorkingDirectory(directory) => {
+                if self.current_directory.as_ref() == Some(&directory) {
+                    return None;
+                }
+                self.current_directory = Some(directory.clone());
+                Some(ControlEvent::DirectoryChanged { directory })
+            },
+            // 没有结束标记的shell在下一个提示符时结束当前命令，退出码未知
+            ShellMark::PromptStart => {
+                self.integrated = true;
+                self.finish(None)
+            },
+            ShellMark::CommandStart { command } => {
+                self.integrated = true;
+                self.next_number += 1;
+                let command = CommandInfo {
+                    number: self.next_number,
+                    command,
+                    directory: self.current_directory.clone(),
+                    started_at: now_millis(),
+                    finished_at: None,
+                    duration_ms: None,
+                    exit_code: None,
+                };
+                self.current_command = Some(command.clone());
+                Some(ControlEvent::CommandStarted { command })
+            },
+            ShellMark::CommandFinished { exit_code } => self.finish(exit_code),
+        }
+    }
+
+    fn finish(&mut self, exit_code: Option<i32>) -> Option<ControlEvent> {
+        let mut command = self.current_command.take()?;
+        let finished_at = now_millis();
+        command.finished_at = Some(finished_at);
+        command.duration_ms = Some(finished_at.saturating_sub(command.started_at));
+        command.exit_code = exit_code;
+        self.last_command = Some(command.clone());
+        if self.history.len() == MAX_SESSION_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(command.clone());
+        Some(ControlEvent::CommandFinished { command })
+    }
+
+    // 服务器重启后重新接管的会话，命令序号接着持久化的命令历史编号
+    pub fn continue_numbering(&mut self, last_number: u64) {
+        self.next_number = self.next_number.max(last_number);
+    }
+
+    // 最近执行完的若干条命令，最早的在前
+    pub fn history(&self, limit: usize) -> Vec<CommandInfo> {
+        self.history.iter().skip(self.history.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    // 按序号查找已执行完的命令
+    pub fn command(&self, number: u64) -> Option<CommandInfo> {
+        self.history.iter().find(|command| command.number == number).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(event: Option<ControlEvent>) -> CommandInfo {
+        match event {
+            Some(ControlEvent::CommandStarted { command }) => command,
+            other => panic!("expected CommandStarted, got {:?}", other),
+        }
+    }
+
+    fn finished(event: Option<ControlEvent>) -> CommandInfo {
+        match event {
+            Some(ControlEvent::CommandFinished { command }) => command,
+            other => panic!("expected CommandFinished, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn apply_tracks_commands() {
+        let mut state = ShellState::default();
+        assert!(matches!(
+            state.apply(ShellMark::WorkingDirectory("/tmp".to_string())),
+            Some(ControlEvent::DirectoryChanged { directory }) if directory == "/tmp"
+        ));
+        // 目录未变化时不通知
+        assert!(state.apply(ShellMark::WorkingDirectory("/tmp".to_string())).is_none());
+        assert!(state.apply(ShellMark::PromptStart).is_none());
+        assert!(state.integrated);
+
+        let command = started(state.apply(ShellMark::CommandStart { command: Some("make".to_string()) }));
+        assert_eq!((command.number, command.command.as_deref(), command.directory.as_deref()), (1, Some("make"), Some("/tmp")));
+        assert!(state.current_command.is_some());
+
+        let command = finished(state.apply(ShellMark::CommandFinished { exit_code: Some(2) }));
+        assert_eq!((command.number, command.exit_code), (1, Some(2)));
+        assert!(command.finished_at.is_some() && command.duration_ms.is_some());
+        assert!(state.current_command.is_none());
+        assert_eq!(state.last_command.as_ref().map(|command| command.number), Some(1));
+        // 没有正在执行的命令时结束标记忽略
+        assert!(state.apply(ShellMark::CommandFinished { exit_code: Some(0) }).is_none());
+    }
+
+    #[test]
+    fn prompt_finishes_command_without_end_mark() {
+        let mut state = ShellState::default();
+        started(state.apply(ShellMark::CommandStart { command: None }));
+        let command = finished(state.apply(ShellMark::PromptStart));
+        assert_eq!(command.exit_code, None);
+    }
+
+    #[test]
+    fn history_and_numbering() {
+        let mut state = ShellState::default();
+        state.continue_numbering(41);
+        for name in ["a", "b", "c"] {
+            state.apply(ShellMark::CommandStart { command: Some(name.to_string()) });
+            state.apply(ShellMark::CommandFinished { exit_code: Some(0) });
+        }
+        let numbers: Vec<u64> = state.history(2).iter().map(|command| command.number).collect();
+        assert_eq!(numbers, [43, 44]);
+        assert_eq!(state.command(42).and_then(|command| command.command).as_deref(), Some("a"));
+        assert!(state.command(45).is_none());
+    }
+
+    #[test]
+    fn inject_bash_login_shell() {
+        let mut config = ShellConfig {
+            command: vec!["/bin/bash".to_string(), "-l".to_string()],
+            working_directory: None,
+            environment: Default::default(),
+            terminal_size: None,
+        };
+        assert!(inject(&mut config, Path::new("/opt/integration")));
+        assert_eq!(config.command, ["/bin/bash", "--init-file", "/opt/integration/rs_terminal.bash"]);
+        assert_eq!(config.environment.get("RS_TERMINAL_SHELL_LOGIN").map(String::as_str), Some("1"));
+
+        // 执行命令的shell和不支持的shell不注入
+        let mut command = ShellConfig { command: vec!["bash".to_string(), "-c".to_string(), "ls".to_string()], ..config.clone() };
+        assert!(!inject(&mut command, Path::new("/opt/integration")));
+        let mut powershell = ShellConfig { command: vec!["pwsh".to_string()], ..config };
+        assert!(!inject(&mut powershell, Path::new("/opt/integration")));
+    }
+}
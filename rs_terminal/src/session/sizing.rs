@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::TerminalSize;
+
+// 多个客户端上报不同窗口大小时，决定终端实际大小的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizePolicy {
+    // 取所有客户端中最小的列数和行数，所有客户端都能完整显示
+    Smallest,
+    // 取所有客户端中最大的列数和行数
+    Largest,
+    // 使用最近输入或调整大小的客户端的大小
+    #[default]
+    LatestActive,
+    // 使用会话所有者的大小，所有者未连接时按最近活动的客户端
+    OwnerWins,
+}
+
+// 单个客户端上报的大小
+struct SizeReport {
+    client_id: String,
+    user_id: Option<String>,
+    size: TerminalSize,
+    // 最近一次活动的序号，越大越新
+    active_at: u64,
+}
+
+// 会话的大小协商 - 记录每个客户端的大小，返回需要应用到终端的新大小
+pub struct SizeNegotiation {
+    policy: SizePolicy,
+    // 会话所有者的用户ID
+    owner: Option<String>,
+    // 没有客户端上报大小时使用，创建会话或通过REST接口设置
+    base: TerminalSize,
+    reports: Vec<SizeReport>,
+    // 当前已应用到终端的大小
+    applied: TerminalSize,
+    clock: u64,
+}
+
+impl SizeNegotiation {
+    pub fn new(policy: SizePolicy, owner: Option<String>, size: TerminalSize) -> Self {
+        Self {
+            policy,
+            owner: owner.filter(|owner| !owner.is_empty()),
+            base: size.clone(),
+            reports: Vec::new(),
+            applied: size,
+            clock: 0,
+        }
+    }
+
+    // 当前已应用到终端的大小
+    pub fn effective(&self) -> TerminalSize {
+        self.applied.clone()
+    }
+
+    // 客户端上报窗口大小
+    pub fn report(&mut self, client_id: &str, user_id: Option<&str>, size: TerminalSize) -> Option<TerminalSize> {
+        self.clock += 1;
+        match self.reports.iter_mut().find(|report| report.client_id == client_id) {
+            Some(report) => {
+                report.size = size;
+                report.active_at = self.clock;
+            },
+            None => self.reports.push(SizeReport {
+                client_id: client_id.to_string(),
+                user_id: user_id.map(str::to_string),
+                size,
+                active_at: self.clock,
+            }),
+        }
+        self.update()
+    }
+
+    // 客户端有输入，最近活动策略下可能切换到该客户端的大小
+    pub fn touch(&mut self, client_id: &str) -> Option<TerminalSize> {
+        self.clock += 1;
+        let report = self.reports.iter_mut().find(|report| report.client_id == client_id)?;
+        report.active_at = self.clock;
+        self.update()
+    }
+
+    // 客户端离开，不再参与协商
+    pub fn remove(&mut self, client_id: &str) -> Option<TerminalSize> {
+        self.reports.retain(|report| report.client_id != client_id);
+        self.update()
+    }
+
+    // 设置没有客户端上报时使用的大小
+    pub fn set_base(&mut self, size: TerminalSize) -> Option<TerminalSize> {
+        self.base = size;
+        self.update()
+    }
+
+    // 重新计算大小，与已应用的大小不同时返回新大小
+    fn update(&mut self) -> Option<TerminalSize> {
+        let size = self.negotiate();
+        if size == self.applied {
+            return None;
+        }
+        self.applied = size.clone();
+        Some(size)
+    }
+
+    fn negotiate(&self) -> TerminalSize {
+        let latest = |reports: &mut dyn Iterator<Item = &SizeReport>| {
+            reports.max_by_key(|report| report.active_at).map(|report| report.size.clone())
+        };
+        let size = match self.policy {
+            SizePolicy::Smallest => self.reports.iter().map(|report| &report.size).cloned().reduce(|a, b| TerminalSize {
+                columns: a.columns.min(b.columns),
+                rows: a.rows.min(b.rows),
+            }),
+            SizePolicy::Largest => self.reports.iter().map(|report| &report.size).cloned().reduce(|a, b| TerminalSize {
+                columns: a.columns.max(b.columns),
+                rows: a.rows.max(b.rows),
+            }),
+            SizePolicy::LatestActive => latest(&mut self.reports.iter()),
+            SizePolicy::OwnerWins => latest(&mut self.reports.iter().filter(|report| {
+                self.owner.is_some() && report.user_id == self.owner
+            })).or_else(|| latest(&mut self.reports.iter())),
+        };
+        size.unwrap_or_else(|| self.base.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(columns: u32, rows: u32) -> TerminalSize {
+        TerminalSize { columns, rows }
+    }
+
+    #[test]
+    fn base_size_without_reports() {
+        let mut negotiation = SizeNegotiation::new(SizePolicy::Smallest, None, size(80, 24));
+        assert_eq!(negotiation.effective(), size(80, 24));
+        assert_eq!(negotiation.set_base(size(100, 30)), Some(size(100, 30)));
+        assert_eq!(negotiation.set_base(size(100, 30)), None);
+
+        negotiation.report("a", None, size(90, 20));
+        // 最后一个客户端离开后恢复基础大小
+        assert_eq!(negotiation.remove("a"), Some(size(100, 30)));
+    }
+
+    #[test]
+    fn smallest_and_largest() {
+        let mut smallest = SizeNegotiation::new(SizePolicy::Smallest, None, size(80, 24));
+        assert_eq!(smallest.report("a", None, size(120, 20)), Some(size(120, 20)));
+        assert_eq!(smallest.report("b", None, size(100, 40)), Some(size(100, 20)));
+        assert_eq!(smallest.remove("a"), Some(size(100, 40)));
+
+        let mut largest = SizeNegotiation::new(SizePolicy::Largest, None, size(80, 24));
+        largest.report("a", None, size(120, 20));
+        assert_eq!(largest.report("b", None, size(100, 40)), Some(size(120, 40)));
+        assert_eq!(largest.touch("a"), None);
+    }
+
+    #[test]
+    fn latest_active() {
+        let mut negotiation = SizeNegotiation::new(SizePolicy::LatestActive, None, size(80, 24));
+        negotiation.report("a", None, size(120, 40));
+        assert_eq!(negotiation.report("b", None, size(100, 30)), Some(size(100, 30)));
+        assert_eq!(negotiation.touch("a"), Some(size(120, 40)));
+        // 未上报大小的客户端不影响结果
+        assert_eq!(negotiation.touch("c"), None);
+        assert_eq!(negotiation.remove("a"), Some(size(100, 30)));
+    }
+
+    #[test]
+    fn owner_wins() {
+        let mut negotiation = SizeNegotiation::new(SizePolicy::OwnerWins, Some("owner".to_string()), size(80, 24));
+        assert_eq!(negotiation.report("guest", Some("guest"), size(100, 30)), Some(size(100, 30)));
+        assert_eq!(negotiation.report("owner", Some("owner"), size(120, 40)), Some(size(120, 40)));
+        assert_eq!(negotiation.touch("guest"), None);
+        // 所有者离开后按最近活动的客户端
+        assert_eq!(negotiation.remove("owner"), Some(size(100, 30)));
+
+        // 没有所有者时不把未登录的客户端当作所有者
+        let mut anonymous = SizeNegotiation::new(SizePolicy::OwnerWins, Some(String::new()), size(80, 24));
+        anonymous.report("a", None, size(100, 30));
+        assert_eq!(anonymous.report("b", None, size(90, 20)), Some(size(90, 20)));
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::config::TerminalSize;
+use crate::session::screen::{Cell, Color, Cursor, Modes, Row, Screen, Style};
+
+// 未设置颜色时HTML渲染使用的前景色和背景色
+const DEFAULT_FOREGROUND: &str = "#e5e5e5";
+const DEFAULT_BACKGROUND: &str = "#000000";
+
+// 标准16色（xterm默认调色板）
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+// 屏幕快照格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenFormat {
+    Text,
+    Ansi,
+    Html,
+    Json,
+}
+
+impl ScreenFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "text" => Some(ScreenFormat::Text),
+            "ansi" => Some(ScreenFormat::Ansi),
+            "html" => Some(ScreenFormat::Html),
+            "json" => Some(ScreenFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ScreenFormat::Text => "text/plain; charset=utf-8",
+            ScreenFormat::Ansi => "text/x-ansi; charset=utf-8",
+            ScreenFormat::Html => "text/html; charset=utf-8",
+            ScreenFormat::Json => "application/json",
+        }
+    }
+}
+
+// 颜色的十六进制表示，默认颜色返回None
+fn color_hex(color: Color) -> Option<String> {
+    let (r, g, b) = match color {
+        Color::Default => return None,
+        Color::Indexed(index) if index < 16 => BASE_COLORS[index as usize],
+        // 6x6x6色彩立方
+        Color::Indexed(index) if index < 232 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        },
+        // 24级灰度
+        Color::Indexed(index) => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        },
+        Color::Rgb(r, g, b) => (r, g, b),
+    };
+    Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+// 同一样式的连续字符
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Span {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub bold: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dim: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub italic: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub underline: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub blink: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub inverse: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub strikethrough: bool,
+}
+
+impl Span {
+    fn new(style: &Style) -> Self {
+        Self {
+            text: String::new(),
+            foreground: color_hex(style.foreground),
+            background: color_hex(style.background),
+            bold: style.bold,
+            dim: style.dim,
+            italic: style.italic,
+            underline: style.underline,
+            blink: style.blink,
+            inverse: style.inverse,
+            hidden: style.hidden,
+            strikethrough: style.strikethrough,
+        }
+    }
+}
+
+// 快照中的一行
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Line {
+    pub text: String,
+    pub spans: Vec<Span>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub wrapped: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorSnapshot {
+    pub row: usize,
+    pub column: usize,
+    pub visible: bool,
+}
+
+// JSON格式的屏幕快照
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenSnapshot {
+    pub columns: u32,
+    pub rows: u32,
+    pub cursor: CursorSnapshot,
+    pub alternate_screen: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    pub scrollback: Vec<Line>,
+    pub lines: Vec<Line>,
+}
+
+// 按样式把一行拆分为若干段，去掉行尾空白
+fn row_spans(row: &Row) -> Vec<(Style, String)> {
+    let mut spans: Vec<(Style, String)> = Vec::new();
+    for cell in row.cells[..row.trimmed_len()].iter().filter(|cell: &&Cell| cell.width > 0) {
+        match spans.last_mut() {
+            Some((style, text)) if *style == cell.style => text.push(cell.character),
+            _ => spans.push((cell.style, cell.character.to_string())),
+        }
+    }
+    spans
+}
+
+fn line(row: &Row) -> Line {
+    Line {
+        text: row.text(),
+        spans: row_spans(row).into_iter()
+            .map(|(style, text)| Span { text, ..Span::new(&style) })
+            .collect(),
+        wrapped: row.wrapped,
+    }
+}
+
+fn escape_html(text: &str, output: &mut String) {
+    for character in text.chars() {
+        match character {
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '&' => output.push_str("&amp;"),
+            '"' => output.push_str("&quot;"),
+            other => output.push(other),
+        }
+    }
+}
+
+// 样式对应的内联CSS，反显时交换前景色和背景色
+fn css(style: &Style) -> String {
+    let mut foreground = color_hex(style.foreground);
+    let mut background = color_hex(style.background);
+    if style.inverse {
+        (foreground, background) = (
+            Some(background.unwrap_or_else(|| DEFAULT_BACKGROUND.to_string())),
+            Some(foreground.unwrap_or_else(|| DEFAULT_FOREGROUND.to_string())),
+        );
+    }
+    let mut declarations = Vec::new();
+    if let Some(foreground) = foreground {
+        declarations.push(format!("color:{}", foreground));
+    }
+    if let Some(background) = background {
+        declarations.push(format!("background-color:{}", background));
+    }
+    if style.bold {
+        declarations.push("font-weight:bold".to_string());
+    }
+    if style.dim {
+        declarations.push("opacity:0.5".to_string());
+    }
+    if style.italic {
+        declarations.push("font-style:italic".to_string());
+    }
+    match (style.underline, style.strikethrough) {
+        (true, true) => declarations.push("text-decoration:underline line-through".to_string()),
+        (true, false) => declarations.push("text-decoration:underline".to_string()),
+        (false, true) => declarations.push("text-decoration:line-through".to_string()),
+        (false, false) => {},
+    }
+    if style.hidden {
+        declarations.push("visibility:hidden".to_string());
+    }
+    declarations.join(";")
+}
+
+// 渲染所需的屏幕内容副本，在模拟器锁内复制后即可释放锁
+pub struct ScreenCapture {
+    size: TerminalSize,
+    cursor: Cursor,
+    modes: Modes,
+    title: String,
+    scrollback: Vec<Row>,
+    visible: Vec<Row>,
+}
+
+impl ScreenCapture {
+    // 复制当前显示的各行和最近scrollback行滚动历史
+    pub fn new(screen: &Screen, scrollback: usize) -> Self {
+        Self {
+            size: screen.size(),
+            cursor: *screen.cursor(),
+            modes: *screen.modes(),
+            title: screen.title().to_string(),
+            scrollback: screen.scrollback(scrollback).cloned().collect(),
+            visible: screen.visible_rows().to_vec(),
+        }
+    }
+}
+
+// 渲染屏幕快照
+pub fn render(screen: &ScreenCapture, format: ScreenFormat) -> anyhow::Result<String> {
+    let rows: Vec<&Row> = screen.scrollback.iter().chain(&screen.visible).collect();
+    let output = match format {
+        ScreenFormat::Text => rows.iter().map(|row| row.text()).collect::<Vec<_>>().join("\n"),
+        ScreenFormat::Ansi => {
+            let mut output = String::new();
+            for (index, row) in rows.iter().enumerate() {
+                if index > 0 {
+                    output.push('\n');
+                }
+                row.write_ansi(&mut output);
+            }
+            output
+        },
+        ScreenFormat::Html => {
+            let mut output = format!(
+                "<pre class=\"terminal\" style=\"color:{};background-color:{};font-family:monospace\">",
+                DEFAULT_FOREGROUND, DEFAULT_BACKGROUND,
+            );
+            for (index, row) in rows.iter().enumerate() {
+                if index > 0 {
+                    output.push('\n');
+                }
+                for (style, text) in row_spans(row) {
+                    let css = css(&style);
+                    if css.is_empty() {
+                        escape_html(&text, &mut output);
+                    } else {
+                        let _ = write!(output, "<span style=\"{}\">", css);
+                        escape_html(&text, &mut output);
+                        output.push_str("</span>");
+                    }
+                }
+            }
+            output.push_str("</pre>");
+            output
+        },
+        ScreenFormat::Json => {
+            let snapshot = ScreenSnapshot {
+                columns: screen.size.columns,
+                rows: screen.size.rows,
+                cursor: CursorSnapshot {
+                    row: screen.cursor.row,
+                    column: screen.cursor.column,
+                    visible: screen.modes.cursor_visible,
+                },
+                alternate_screen: screen.modes.alternate_screen,
+                title: screen.title.clone(),
+                scrollback: screen.scrollback.iter().map(line).collect(),
+                lines: screen.visible.iter().map(line).collect(),
+            };
+            serde_json::to_string(&snapshot)?
+        },
+    };
+    Ok(output)
+}
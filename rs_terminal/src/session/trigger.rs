@@ -0,0 +1,411 @@
+use std::time::{Duration, Instant};
+
+use regex::{Regex, RegexBuilder};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{TriggerAction, TriggerEvent, TriggerRule};
+use crate::session::repository::now_millis;
+use crate::session::screen::PlainText;
+
+// 每个会话最多的触发器数量
+const MAX_TRIGGERS: usize = 32;
+// 尚未换行的输出最多保留的长度（字节），超出时按一行处理
+const MAX_PARTIAL_LINE: usize = 4096;
+// 正则表达式编译后的大小上限
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+// 触发原因
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum TriggerCause {
+    // 匹配的输出行（去掉转义序列）
+    Output { line: String },
+    Exit { exit_code: Option<u32> },
+    Idle { seconds: u64 },
+}
+
+// 一次触发，由会话管理器执行动作
+pub struct Firing {
+    pub name: String,
+    pub action: TriggerAction,
+    pub cause: TriggerCause,
+}
+
+// webhook请求体
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookPayload<'a> {
+    trigger: &'a str,
+    session_id: &'a str,
+    timestamp: u64,
+    cause: &'a TriggerCause,
+}
+
+// webhook发送 - 每次触发在独立任务中发送，失败只记录日志；不跟随重定向，避免绕过URL白名单
+#[derive(Clone)]
+pub struct WebhookClient {
+    client: reqwest::Client,
+}
+
+impl WebhookClient {
+    pub fn new(timeout_ms: u64) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(timeout_ms))
+            .redirect(Policy::none())
+            .build()
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to configure webhook timeout, using defaults: {}", e);
+                reqwest::Client::builder().redirect(Policy::none()).build().unwrap_or_default()
+            });
+        Self { client }
+    }
+
+    pub fn send(&self, url: &str, session_id: &str, firing: &Firing) {
+        let payload = WebhookPayload {
+            trigger: &firing.name,
+            session_id,
+            timestamp: now_millis(),
+            cause: &firing.cause,
+        };
+        let request = self.client.post(url).json(&payload);
+        let url = url.to_string();
+        let name = firing.name.clone();
+        tokio::spawn(async move {
+            match request.send().await.and_then(|response| response.error_for_status()) {
+                Ok(_) => log::debug!("Webhook for trigger {} delivered to {}", name, url),
+                Err(e) => log::warn!("Webhook for trigger {} to {} failed: {}", name, url, e),
+            }
+        });
+    }
+}
+
+// 检查配置中的触发器，任一无效时返回错误
+pub fn validate(rules: &[TriggerRule]) -> anyhow::Result<()> {
+    rules.iter().try_for_each(|rule| ActiveTrigger::new(rule.clone()).map(|_| ()))
+}
+
+// 通过REST接口添加的webhook触发器只能指向允许的URL - 解析后协议、主机和端口必须与某个前缀完全一致，路径以前缀的路径开头
+pub fn check_webhook_allowed(rule: &TriggerRule, prefixes: &[String]) -> anyhow::Result<()> {
+    let TriggerAction::Webhook { url } = &rule.action else {
+        return Ok(());
+    };
+    let parsed = Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid webhook URL {}: {}", url, e))?;
+    let allowed = prefixes.iter()
+        .filter_map(|prefix| Url::parse(prefix).ok())
+        .any(|prefix| {
+            parsed.scheme() == prefix.scheme()
+                && parsed.host_str().is_some_and(|host| prefix.host_str() == Some(host))
+                && parsed.port_or_known_default() == prefix.port_or_known_default()
+                && parsed.path().starts_with(prefix.path())
+        });
+    if !allowed {
+        anyhow::bail!("Webhook URL is not allowed: {}", url);
+    }
+    Ok(())
+}
+
+struct ActiveTrigger {
+    rule: TriggerRule,
+    pattern: Option<Regex>,
+    last_fired: Option<Instant>,
+    // 当前未换行的输出已经触发过，该行结束时不再重复触发
+    partial_fired: bool,
+    // 本次空闲已经触发过，有活动后重新计时
+    idle_fired: bool,
+}
+
+impl ActiveTrigger {
+    fn new(rule: TriggerRule) -> anyhow::Result<Self> {
+        if rule.name.is_empty() {
+            anyhow::bail!("Trigger name is empty");
+        }
+        let pattern = match &rule.on {
+            TriggerEvent::Output { pattern } => Some(
+                RegexBuilder::new(pattern)
+                    .size_limit(REGEX_SIZE_LIMIT)
+                    .build()
+                    .map_err(|e| anyhow::anyhow!("Invalid pattern in trigger '{}': {}", rule.name, e))?,
+            ),
+            TriggerEvent::Idle { seconds: 0 } => anyhow::bail!("Idle time of trigger '{}' must be positive", rule.name),
+            _ => None,
+        };
+        // 自动输入可能再次匹配自身的输出，必须设置冷却时间
+        if matches!(rule.action, TriggerAction::Input { .. }) && rule.cooldown == 0 {
+            anyhow::bail!("Input trigger '{}' requires a positive cooldown", rule.name);
+        }
+        Ok(Self {
+            rule,
+            pattern,
+            last_fired: None,
+            partial_fired: false,
+            idle_fired: false,
+        })
+    }
+
+    // 冷却时间已过时记录触发并返回触发事件
+    fn fire(&mut self, now: Instant, cause: TriggerCause) -> Option<Firing> {
+        let cooldown = Duration::from_secs(self.rule.cooldown);
+        if self.last_fired.is_some_and(|last| now.duration_since(last) < cooldown) {
+            return None;
+        }
+        self.last_fired = Some(now);
+        Some(Firing {
+            name: self.rule.name.clone(),
+            action: self.rule.action.clone(),
+            cause,
+        })
+    }
+}
+
+// 会话的触发器和判断触发所需的状态
+pub struct TriggerSet {
+    triggers: Vec<ActiveTrigger>,
+    plain: PlainText,
+    // 尚未换行的输出
+    partial: String,
+    last_activity: Instant,
+}
+
+impl TriggerSet {
+    // 按配置创建，配置在启动时已检查，无效的触发器只记录日志
+    pub fn new(rules: Vec<TriggerRule>) -> Self {
+        let mut set = Self {
+            triggers: Vec::new(),
+            plain: PlainText::default(),
+            partial: String::new(),
+            last_activity: Instant::now(),
+        };
+        for rule in rules {
+            if let Err(e) = set.add(rule) {
+                log::error!("Ignoring trigger: {}", e);
+            }
+        }
+        set
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    pub fn rules(&self) -> Vec<TriggerRule> {
+        self.triggers.iter().map(|trigger| trigger.rule.clone()).collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.triggers.iter().any(|trigger| trigger.rule.name == name)
+    }
+
+    pub fn add(&mut self, rule: TriggerRule) -> anyhow::Result<()> {
+        if self.contains(&rule.name) {
+            anyhow::bail!("Trigger '{}' already exists", rule.name);
+        }
+        if self.triggers.len() >= MAX_TRIGGERS {
+            anyhow::bail!("Session already has {} triggers", MAX_TRIGGERS);
+        }
+        self.triggers.push(ActiveTrigger::new(rule)?);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.triggers.len();
+        self.triggers.retain(|trigger| trigger.rule.name != name);
+        self.triggers.len() != count
+    }
+
+    // 有输入或输出，重新开始空闲计时
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+        for trigger in &mut self.triggers {
+            trigger.idle_fired = false;
+        }
+    }
+
+    // 按行匹配输出，未换行的输出（如提示符）也参与匹配
+    pub fn on_output(&mut self, data: &str) -> Vec<Firing> {
+        self.touch();
+        if !self.triggers.iter().any(|trigger| trigger.pattern.is_some()) {
+            return Vec::new();
+        }
+
+        // 回车只移动光标，不计入行内容
+        self.partial.extend(self.plain.feed(data).chars().filter(|character| *character != '\r'));
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.find('\n') {
+            lines.push(self.partial[..end].to_string());
+            self.partial.drain(..=end);
+        }
+        if self.partial.len() > MAX_PARTIAL_LINE {
+            lines.push(std::mem::take(&mut self.partial));
+        }
+
+        let now = Instant::now();
+        let mut firings = Vec::new();
+        for trigger in &mut self.triggers {
+            let Some(pattern) = trigger.pattern.clone() else { continue };
+            for (index, line) in lines.iter().enumerate() {
+                // 该行在未换行时已经触发过
+                if index == 0 && trigger.partial_fired {
+                    continue;
+                }
+                if pattern.is_match(line) {
+                    firings.extend(trigger.fire(now, TriggerCause::Output { line: line.clone() }));
+                }
+            }
+            if !lines.is_empty() {
+                trigger.partial_fired = false;
+            }
+            if !trigger.partial_fired && !self.partial.is_empty() && pattern.is_match(&self.partial) {
+                trigger.partial_fired = true;
+                firings.extend(trigger.fire(now, TriggerCause::Output { line: self.partial.clone() }));
+            }
+        }
+        firings
+    }
+
+    // 检查空闲触发器，每次空闲只触发一次
+    pub fn on_idle_check(&mut self) -> Vec<Firing> {
+        let now = Instant::now();
+        let idle = now.duration_since(self.last_activity);
+        let mut firings = Vec::new();
+        for trigger in &mut self.triggers {
+            if let TriggerEvent::Idle { seconds } = trigger.rule.on
+                && !trigger.idle_fired && idle >= Duration::from_secs(seconds) {
+                trigger.idle_fired = true;
+                firings.extend(trigger.fire(now, TriggerCause::Idle { seconds }));
+            }
+        }
+        firings
+    }
+
+    pub fn on_exit(&mut self, exit_code: Option<u32>) -> Vec<Firing> {
+        let now = Instant::now();
+        self.triggers.iter_mut()
+            .filter(|trigger| matches!(trigger.rule.on, TriggerEvent::Exit))
+            .filter_map(|trigger| trigger.fire(now, TriggerCause::Exit { exit_code }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, on: TriggerEvent, action: TriggerAction, cooldown: u64) -> TriggerRule {
+        TriggerRule { name: name.to_string(), shells: Vec::new(), on, action, cooldown }
+    }
+
+    fn output_rule(name: &str, pattern: &str, cooldown: u64) -> TriggerRule {
+        rule(name, TriggerEvent::Output { pattern: pattern.to_string() }, TriggerAction::Control, cooldown)
+    }
+
+    fn webhook(url: &str) -> TriggerRule {
+        rule("hook", TriggerEvent::Exit, TriggerAction::Webhook { url: url.to_string() }, 0)
+    }
+
+    fn names(firings: &[Firing]) -> Vec<&str> {
+        firings.iter().map(|firing| firing.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn webhook_client_supports_https() {
+        let webhooks = WebhookClient::new(1000);
+        let error = webhooks.client.post("https://127.0.0.1:9/hook").send().await.unwrap_err();
+        // 没有TLS后端时请求在连接前就因协议不受支持而失败
+        assert!(error.is_connect(), "{:?}", error);
+    }
+
+    #[test]
+    fn webhook_allow_list() {
+        let prefixes = vec!["https://hooks.example.com/notify".to_string(), "http://localhost:9000/".to_string()];
+        for allowed in [
+            "https://hooks.example.com/notify",
+            "https://hooks.example.com:443/notify/build?id=1",
+            "http://localhost:9000/any",
+        ] {
+            assert!(check_webhook_allowed(&webhook(allowed), &prefixes).is_ok(), "{}", allowed);
+        }
+        for rejected in [
+            "https://hooks.example.com.evil.net/notify",
+            "https://hooks.example.com@evil.net/notify",
+            "https://hooks.example.com:8443/notify",
+            "http://hooks.example.com/notify",
+            "https://hooks.example.com/other",
+            "http://localhost:9001/",
+            "not a url",
+        ] {
+            assert!(check_webhook_allowed(&webhook(rejected), &prefixes).is_err(), "{}", rejected);
+        }
+        // 其他动作不受限制
+        assert!(check_webhook_allowed(&output_rule("control", "x", 0), &[]).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!(validate(&[output_rule("", "x", 0)]).is_err());
+        assert!(validate(&[output_rule("bad", "(", 0)]).is_err());
+        assert!(validate(&[rule("idle", TriggerEvent::Idle { seconds: 0 }, TriggerAction::Control, 0)]).is_err());
+
+        // 自动输入必须设置冷却时间
+        let input = TriggerAction::Input { data: "y\n".to_string() };
+        assert!(validate(&[rule("confirm", TriggerEvent::Exit, input.clone(), 0)]).is_err());
+        assert!(validate(&[rule("confirm", TriggerEvent::Exit, input, 1)]).is_ok());
+
+        let mut set = TriggerSet::new(vec![output_rule("a", "x", 0)]);
+        assert!(set.add(output_rule("a", "y", 0)).is_err());
+        for index in 1..MAX_TRIGGERS {
+            set.add(output_rule(&index.to_string(), "x", 0)).unwrap();
+        }
+        assert!(set.add(output_rule("full", "x", 0)).is_err());
+        assert!(set.remove("a"));
+        assert!(!set.remove("a"));
+    }
+
+    #[test]
+    fn cooldown() {
+        let mut trigger = ActiveTrigger::new(output_rule("a", "x", 10)).unwrap();
+        let now = Instant::now();
+        let cause = || TriggerCause::Output { line: "x".to_string() };
+        assert!(trigger.fire(now, cause()).is_some());
+        assert!(trigger.fire(now + Duration::from_secs(9), cause()).is_none());
+        assert!(trigger.fire(now + Duration::from_secs(10), cause()).is_some());
+
+        let mut set = TriggerSet::new(vec![output_rule("a", "error", 60), output_rule("b", "error", 0)]);
+        assert_eq!(names(&set.on_output("error 1\r\nerror 2\r\n")), ["a", "b", "b"]);
+    }
+
+    #[test]
+    fn output_lines_and_partial_line() {
+        let mut set = TriggerSet::new(vec![output_rule("prompt", r"\[y/N\]", 0)]);
+        // 跨输出块的转义序列和未换行的提示符
+        assert!(set.on_output("Continue? \x1b[1").is_empty());
+        let firings = set.on_output("m[y/N]\x1b[0m ");
+        assert_eq!(names(&firings), ["prompt"]);
+        assert!(matches!(&firings[0].cause, TriggerCause::Output { line } if line == "Continue? [y/N] "));
+        // 已触发过的未换行行结束时不重复触发
+        assert!(set.on_output("y\r\n").is_empty());
+        assert_eq!(names(&set.on_output("again [y/N]\n")), ["prompt"]);
+    }
+
+    #[test]
+    fn exit_and_idle() {
+        let mut set = TriggerSet::new(vec![
+            rule("exit", TriggerEvent::Exit, TriggerAction::Control, 0),
+            rule("idle", TriggerEvent::Idle { seconds: 30 }, TriggerAction::Control, 0),
+        ]);
+        assert!(set.on_idle_check().is_empty());
+
+        set.last_activity = Instant::now() - Duration::from_secs(31);
+        assert_eq!(names(&set.on_idle_check()), ["idle"]);
+        // 每次空闲只触发一次
+        assert!(set.on_idle_check().is_empty());
+        set.touch();
+        set.last_activity = Instant::now() - Duration::from_secs(31);
+        assert_eq!(names(&set.on_idle_check()), ["idle"]);
+
+        let firings = set.on_exit(Some(1));
+        assert_eq!(names(&firings), ["exit"]);
+        assert!(matches!(firings[0].cause, TriggerCause::Exit { exit_code: Some(1) }));
+    }
+}
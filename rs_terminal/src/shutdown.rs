@@ -0,0 +1,53 @@
+use tokio::sync::watch;
+
+// 关闭信号 - 在各服务器之间广播关闭通知
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    sender: watch::Sender<bool>,
+}
+
+impl ShutdownSignal {
+    // 创建新的关闭信号
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self { sender }
+    }
+
+    // 触发关闭
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    // 等待关闭信号被触发
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // 发送者由self持有，wait_for不会因通道关闭而返回错误
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 等待进程终止信号 - Unix上同时处理SIGINT和SIGTERM，其他平台处理Ctrl-C
+pub async fn wait_for_termination_signal() -> anyhow::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigint.recv() => Ok("SIGINT"),
+            _ = sigterm.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("Ctrl-C")
+    }
+}
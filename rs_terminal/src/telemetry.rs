@@ -0,0 +1,127 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use opentelemetry::trace::{SpanContext, TraceContextExt, TracerProvider as _};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::config::{TracingConfig, TracingExporter};
+
+// 链路追踪，持有导出器直到服务器关闭
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Telemetry {
+    // 导出剩余的span并关闭导出器
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.shutdown() {
+            log::warn!("Failed to shut down tracing exporter: {}", e);
+        }
+    }
+}
+
+// 按配置初始化链路追踪，未开启时tracing的span不会被记录
+pub fn init(config: &TracingConfig) -> anyhow::Result<Option<Telemetry>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    let resource = Resource::builder().with_service_name(config.service_name.clone()).build();
+    let provider = match config.exporter {
+        TracingExporter::Otlp => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(config.otlp_endpoint.clone())
+                .build()?;
+            log::info!("Exporting traces to {}", config.otlp_endpoint);
+            SdkTracerProvider::builder().with_batch_exporter(exporter).with_resource(resource).build()
+        },
+        TracingExporter::File => {
+            let exporter = FileSpanExporter::open(&config.file_path)?;
+            log::info!("Writing traces to {}", config.file_path);
+            SdkTracerProvider::builder().with_batch_exporter(exporter).with_resource(resource).build()
+        },
+    };
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+
+    Ok(Some(Telemetry { provider }))
+}
+
+// 当前span的上下文，未开启链路追踪时返回None
+pub fn current_span_context() -> Option<SpanContext> {
+    let context = tracing::Span::current().context();
+    let span_context = context.span().span_context().clone();
+    span_context.is_valid().then_some(span_context)
+}
+
+// 将span关联到另一条链路上的span，例如终端输出关联到触发它的输入
+pub fn link(span: &tracing::Span, span_context: SpanContext) {
+    span.add_link(span_context);
+}
+
+// 本地文件导出器 - 每个span写一行JSON，用于没有采集器时离线查看
+#[derive(Debug)]
+struct FileSpanExporter {
+    file: Mutex<File>,
+}
+
+impl FileSpanExporter {
+    fn open(path: &str) -> anyhow::Result<Self> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_nanos()).unwrap_or_default()
+}
+
+impl SpanExporter for FileSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let mut lines = String::new();
+        for span in batch {
+            let attributes = span.attributes.iter()
+                .map(|attribute| (attribute.key.to_string(), serde_json::Value::String(attribute.value.to_string())))
+                .collect::<serde_json::Map<_, _>>();
+            let links = span.links.iter()
+                .map(|link| serde_json::json!({
+                    "traceId": link.span_context.trace_id().to_string(),
+                    "spanId": link.span_context.span_id().to_string(),
+                }))
+                .collect::<Vec<_>>();
+            let line = serde_json::json!({
+                "traceId": span.span_context.trace_id().to_string(),
+                "spanId": span.span_context.span_id().to_string(),
+                "parentSpanId": span.parent_span_id.to_string(),
+                "name": span.name,
+                "startTimeUnixNano": unix_nanos(span.start_time),
+                "endTimeUnixNano": unix_nanos(span.end_time),
+                "durationMicros": span.end_time.duration_since(span.start_time).unwrap_or_default().as_micros(),
+                "attributes": attributes,
+                "links": links,
+            });
+            lines.push_str(&line.to_string());
+            lines.push('\n');
+        }
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(lines.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))
+    }
+}
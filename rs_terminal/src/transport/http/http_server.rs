@@ -1,10 +1,27 @@
-use axum::{extract::{Path, Query, State}, http::StatusCode, routing::{get, post, delete}, Json, Router};
+use axum::{extract::{connect_info::Connected, ConnectInfo, FromRequestParts, MatchedPath, Path, Query, RawPathParams, RawQuery, State, WebSocketUpgrade}, http::{header, request::Parts, StatusCode}, response::{IntoResponse, Response}, routing::{get, post, delete}, serve::IncomingStream, Json, Router};
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::trace::TraceLayer;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
-use crate::session::session::{SessionManager, SessionStatus};
+use crate::audit::{AuditAction, ClientContext, TransportKind};
+use crate::config::{Config, TriggerRule};
+use crate::health::{HealthRegistry, HealthState};
+use crate::protocol::message::TextProtocol;
+use crate::pty::process::{Signal, SignalTarget};
+use crate::pty::terminal_service::{ConnectionTarget, TerminalService};
+use crate::session::error::SessionError;
+use crate::session::expect::{self, ExpectScript};
+use crate::session::recording::{self, Recording, RecordingInfo, RecordingSummary};
+use crate::session::repository::{SessionFilter, SessionRecord, StatusTransition};
+use crate::session::session::{check_reader, SessionManager, SessionOptions, SessionStatus};
+use crate::session::search::SearchQuery;
+use crate::session::share::{Share, ShareRole};
+use crate::session::snapshot::ScreenFormat;
+use crate::shutdown::ShutdownSignal;
+use crate::transport::listener;
+use crate::transport::tls::{self, CertificateDigest, ReloadableTlsConfig, TlsListener};
 
 // 响应数据结构
 #[derive(Serialize)]
@@ -22,6 +39,16 @@ pub struct TerminalInterruptResponse {
     pub status: String,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalSignalResponse {
+    pub session_id: String,
+    pub signal: String,
+    pub target: String,
+    // 接收信号的进程组（foreground）或进程（shell）ID
+    pub pid: u32,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TerminalTerminateResponse {
@@ -35,6 +62,14 @@ pub struct TerminalStatusResponse {
     pub status: String,
 }
 
+// WebTransport证书摘要响应，可直接用于浏览器的serverCertificateHashes
+#[derive(Serialize)]
+pub struct CertificateHashResponse {
+    pub algorithm: String,
+    pub value: Vec<u8>,
+    pub hex: String,
+}
+
 // 终端尺寸
 #[derive(Serialize, Deserialize, Clone)]
 pub struct TerminalSize {
@@ -57,6 +92,17 @@ pub struct TerminalSession {
     pub updated_at: u64,
     pub last_active_time: u64,
     pub expired_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminated_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status_history: Vec<StatusTransition>,
+    // shell集成上报的当前目录，未开启shell集成时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_directory: Option<String>,
 }
 
 // 请求参数
@@ -71,6 +117,23 @@ struct CreateSessionParams {
     shell_type: Option<String>,
     columns: Option<u32>,
     rows: Option<u32>,
+    record: Option<bool>,
+    #[serde(rename = "singleDriver")]
+    single_driver: Option<bool>,
+    #[serde(rename = "shellIntegration")]
+    shell_integration: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ListSessionsParams {
+    status: Option<String>,
+    #[serde(rename = "userId")]
+    user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReplayParams {
+    speed: Option<f64>,
 }
 
 #[derive(Deserialize)]
@@ -79,6 +142,50 @@ struct ResizeParams {
     rows: Option<u32>,
 }
 
+#[derive(Deserialize)]
+struct ScreenParams {
+    format: Option<String>,
+    scrollback: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: Option<String>,
+    regex: Option<bool>,
+    #[serde(rename = "ignoreCase")]
+    ignore_case: Option<bool>,
+    context: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SignalParams {
+    signal: Option<String>,
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HistoryParams {
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct HistorySearchParams {
+    q: Option<String>,
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CreateShareParams {
+    role: Option<String>,
+    #[serde(rename = "expiresIn")]
+    expires_in: Option<u64>,
+    #[serde(rename = "maxUses")]
+    max_uses: Option<u32>,
+}
+
 #[derive(Deserialize)]
 struct ExecuteParams {
     command: Option<String>,
@@ -86,7 +193,78 @@ struct ExecuteParams {
     timeout_ms: Option<u64>,
 }
 
+// 客户端连接信息，通过Unix域套接字接入时没有对端地址
+#[derive(Clone, Copy, Debug)]
+struct RemoteAddr(Option<SocketAddr>);
+
+impl Connected<IncomingStream<'_, tokio::net::TcpListener>> for RemoteAddr {
+    fn connect_info(stream: IncomingStream<'_, tokio::net::TcpListener>) -> Self {
+        RemoteAddr(Some(*stream.remote_addr()))
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for RemoteAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        RemoteAddr(Some(*stream.remote_addr()))
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for RemoteAddr {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        RemoteAddr(None)
+    }
+}
+
+// 能确定客户端在会话中角色的路由状态
+trait ClientAuthorizer {
+    fn authorize_client(&self, session_id: &str, client: &mut ClientContext) -> anyhow::Result<()>;
+}
+
+impl ClientAuthorizer for (Arc<SessionManager>, Arc<Config>) {
+    fn authorize_client(&self, session_id: &str, client: &mut ClientContext) -> anyhow::Result<()> {
+        self.0.authorize_client(session_id, client)
+    }
+}
+
+impl ClientAuthorizer for Arc<TerminalService> {
+    fn authorize_client(&self, session_id: &str, client: &mut ClientContext) -> anyhow::Result<()> {
+        TerminalService::authorize_client(self, session_id, client)
+    }
+}
+
+// 以会话ID为路径参数的路由，录制ID即会话ID
+const SESSION_ROUTES: [&str; 4] = ["/api/sessions/{id}", "/api/recordings/{id}", "/ws/{id}", "/ws/replay/{id}"];
+
+// 路由是否以会话ID为路径参数
+fn is_session_route(path: &str) -> bool {
+    SESSION_ROUTES.iter().any(|route| path.starts_with(route))
+}
+
+// 发起请求的客户端，用于审计日志和权限检查；会话路由在此按共享令牌或会话所有者确定角色，
+// 令牌无效时返回403；此处只校验令牌，WebSocket连接建立时才计一次使用
+struct HttpClient(ClientContext);
+
+impl<S: ClientAuthorizer + Send + Sync> FromRequestParts<S> for HttpClient {
+    type Rejection = Response;
+    
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts.extensions.get::<ConnectInfo<RemoteAddr>>().and_then(|ConnectInfo(remote_addr)| remote_addr.0);
+        let mut client = ClientContext::from_request(TransportKind::Http, peer, &parts.headers, parts.uri.query());
+        let session_route = parts.extensions.get::<MatchedPath>().is_some_and(|path| is_session_route(path.as_str()));
+        if session_route
+            && let Ok(params) = RawPathParams::from_request_parts(parts, state).await
+            && let Some((_, session_id)) = params.iter().find(|(name, _)| *name == "id")
+            && let Err(e) = state.authorize_client(session_id, &mut client) {
+            log::warn!("Rejected request to session {}: {}", session_id, e);
+            return Err((StatusCode::FORBIDDEN, "Invalid share token").into_response());
+        }
+        Ok(HttpClient(client))
+    }
+}
+
 // 辅助函数：创建默认的TerminalSession对象
+#[allow(clippy::too_many_arguments)]
 fn create_default_terminal_session(
     id: String,
     user_id: String,
@@ -115,13 +293,87 @@ fn create_default_terminal_session(
         updated_at: now,
         last_active_time: now,
         expired_at: now + config.terminal.session_timeout,
+        terminated_at: None,
+        termination_reason: None,
+        exit_code: None,
+        status_history: Vec::new(),
+        current_directory: None,
+    }
+}
+
+// 辅助函数：将会话记录转换为TerminalSession对象
+fn terminal_session_from_record(record: SessionRecord) -> TerminalSession {
+    TerminalSession {
+        id: record.id,
+        user_id: record.user_id,
+        title: record.title,
+        working_directory: record.working_directory,
+        shell_type: record.shell_type,
+        status: record.status.as_str().to_string(),
+        terminal_size: TerminalSize {
+            columns: record.terminal_size.columns,
+            rows: record.terminal_size.rows,
+        },
+        created_at: record.created_at,
+        updated_at: record.updated_at,
+        last_active_time: record.last_active_time,
+        expired_at: record.expired_at,
+        terminated_at: record.terminated_at,
+        termination_reason: record.termination_reason,
+        exit_code: record.exit_code,
+        status_history: record.transitions,
+        current_directory: record.current_directory,
+    }
+}
+
+// 健康检查中的适配器名称
+pub const ADAPTER_NAME: &str = "http";
+
+// 搜索默认和最多附带的前后文行数
+const DEFAULT_SEARCH_CONTEXT: usize = 2;
+const MAX_SEARCH_CONTEXT: usize = 20;
+// 搜索默认和最多返回的匹配行数
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+const MAX_SEARCH_LIMIT: usize = 1000;
+// 命令历史默认和最多返回的条数
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+// 会话错误对应的状态码
+fn error_status(e: &SessionError) -> StatusCode {
+    match e {
+        SessionError::SessionNotFound(_) | SessionError::NotFound(_) => StatusCode::NOT_FOUND,
+        SessionError::Forbidden(_) => StatusCode::FORBIDDEN,
+        SessionError::Conflict(_) => StatusCode::CONFLICT,
+        SessionError::Invalid(_) => StatusCode::BAD_REQUEST,
+        SessionError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        SessionError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// 会话错误转换为响应，其他错误只记录日志，不向调用方返回内部细节
+fn error_response(e: SessionError, action: &str) -> Response {
+    match e {
+        SessionError::Other(e) => {
+            log::error!("Failed to {}: {}", action, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to {}", action)).into_response()
+        },
+        e => (error_status(&e), e.to_string()).into_response(),
     }
 }
 
 // 启动HTTP服务器
-pub async fn start_server(session_manager: Arc<SessionManager>, config: Arc<Config>) -> anyhow::Result<()> {
+pub async fn start_server(
+    session_manager: Arc<SessionManager>,
+    terminal_service: Arc<TerminalService>,
+    config: Arc<Config>,
+    certificate_digest: CertificateDigest,
+    health: HealthRegistry,
+    shutdown: ShutdownSignal,
+) -> anyhow::Result<()> {
     // 保存端口值，因为config会被移动到app状态中
     let port = config.http.port;
+    let http_config = config.http.clone();
     
     // 创建CORS配置
     let cors = CorsLayer::new()
@@ -136,28 +388,99 @@ pub async fn start_server(session_manager: Arc<SessionManager>, config: Arc<Conf
         .route("/api/sessions/{id}", get(get_session_by_id))
         .route("/api/sessions/{id}/resize", post(resize_terminal))
         .route("/api/sessions/{id}/interrupt", post(interrupt_terminal))
+        .route("/api/sessions/{id}/signal", post(signal_terminal))
+        .route("/api/sessions/{id}/foreground", get(get_foreground_process))
         .route("/api/sessions/{id}", delete(terminate_session))
         .route("/api/sessions/{id}/status", get(get_session_status))
         .route("/api/sessions/{id}/execute", post(execute_command))
         .route("/api/sessions/{id}/execute-check", post(execute_command_check))
+        .route("/api/sessions/{id}/expect", post(run_expect_script))
+        .route("/api/sessions/{id}/screen", get(get_screen))
+        .route("/api/sessions/{id}/search", get(search_session))
+        .route("/api/sessions/{id}/shell", get(get_shell_state))
+        .route("/api/sessions/{id}/history", get(get_session_history))
+        .route("/api/sessions/{id}/history/{number}/rerun", post(rerun_command))
+        .route("/api/history", get(search_history))
+        .route("/api/sessions/{id}/recording", get(download_recording))
+        .route("/api/sessions/{id}/shares", post(create_share))
+        .route("/api/sessions/{id}/shares", get(list_shares))
+        .route("/api/sessions/{id}/shares/{share_id}", delete(revoke_share))
+        .route("/api/sessions/{id}/triggers", post(add_trigger))
+        .route("/api/sessions/{id}/triggers", get(list_triggers))
+        .route("/api/sessions/{id}/triggers/{name}", delete(remove_trigger))
+        .route("/api/recordings", get(list_recordings))
+        .route("/api/recordings/{id}", get(get_recording))
+        .route("/metrics", get(get_metrics))
+        .with_state((session_manager, config))
+        .merge(
+            Router::new()
+                .route("/api/webtransport/certificate-hash", get(get_certificate_hash))
+                .with_state(certificate_digest)
+        )
+        .merge(
+            Router::new()
+                .route("/healthz", get(get_health))
+                .route("/readyz", get(get_readiness))
+                .with_state(health.clone())
+        )
+        .merge(
+            Router::new()
+                .route("/ws/{id}", get(websocket_upgrade))
+                .route("/ws/replay/{id}", get(websocket_replay))
+                .with_state(terminal_service)
+        )
         .layer(cors)
-        .with_state((session_manager, config));
+        .layer(TraceLayer::new_for_http());
     
-    // 绑定地址并启动服务器
-    let addr: std::net::SocketAddr = format!("127.0.0.1:{}", port).parse()?;
+    // 配置了Unix域套接字时只监听该套接字，由本地反向代理负责TLS
+    if let Some(socket_path) = &http_config.unix_socket_path {
+        #[cfg(unix)]
+        {
+            if http_config.use_https {
+                log::warn!("HTTPS is ignored when listening on unix socket {}", socket_path);
+            }
+            let listener = listener::bind_unix_socket(socket_path, http_config.unix_socket_mode.as_deref())?;
+            log::info!("HTTP server started on unix:{}", socket_path);
+            health.set_ready(ADAPTER_NAME);
+            axum::serve(listener, app.into_make_service_with_connect_info::<RemoteAddr>())
+                .with_graceful_shutdown(async move { shutdown.wait().await })
+                .await?;
+            return Ok(());
+        }
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix domain sockets are not supported on this platform: {}", socket_path);
+    }
     
-    // 暂时只支持HTTP，HTTPS支持需要安装cmake和nasm依赖
-    log::info!("HTTP server started on http://{}", addr);
+    // 绑定地址并启动服务器
+    let addr = listener::parse_bind_address(&http_config.bind_address, port)?;
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app)
-        .await?;
     
+    if http_config.use_https {
+        let (cert_path, key_path) = tls::configured_paths(&http_config.cert_path, &http_config.key_path)
+            .ok_or_else(|| anyhow::anyhow!("HTTPS is enabled but cert_path or key_path is not configured"))?;
+        let tls_config = ReloadableTlsConfig::load(&cert_path, &key_path, vec![b"http/1.1".to_vec()])?;
+        
+        log::info!("HTTP server started on https://{}", addr);
+        health.set_ready(ADAPTER_NAME);
+        axum::serve(TlsListener::new(listener, tls_config)?, app.into_make_service_with_connect_info::<RemoteAddr>())
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await?;
+    } else {
+        log::info!("HTTP server started on http://{}", addr);
+        health.set_ready(ADAPTER_NAME);
+        axum::serve(listener, app.into_make_service_with_connect_info::<RemoteAddr>())
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await?;
+    }
+    
+    log::info!("HTTP server stopped");
     Ok(())
 }
 
 // 创建新会话
 async fn create_session(
     Query(params): Query<CreateSessionParams>,
+    HttpClient(client): HttpClient,
     State((session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<TerminalSession>) {
     // 检查userId是否提供
@@ -183,31 +506,45 @@ async fn create_session(
     // 获取shell类型
     let shell_type = params.shell_type.unwrap_or(config.terminal.default_shell_type.clone());
     
-    // 验证shell类型是否存在
-    if config.get_shell_config(&shell_type).is_none() {
-        log::warn!("Shell type '{}' not found, using default shell type '{}'", shell_type, config.terminal.default_shell_type);
-    }
+    // 创建会话，shell类型不存在时使用默认shell
+    let options = SessionOptions {
+        user_id: Some(user_id.clone()),
+        title: params.title.clone(),
+        shell_type: Some(shell_type.clone()),
+        working_directory: params.working_directory.clone(),
+        terminal_size: Some(crate::config::TerminalSize {
+            columns: params.columns.unwrap_or(config.terminal.default_terminal_size.columns),
+            rows: params.rows.unwrap_or(config.terminal.default_terminal_size.rows),
+        }),
+        record: params.record,
+        single_driver: params.single_driver,
+        shell_integration: params.shell_integration,
+    };
+    let created = match session_manager.create_session(options, &client).await {
+        Ok(session_id) => session_manager.get_session_record(&session_id),
+        Err(e) => Err(e),
+    };
     
-    // 生成会话ID
-    match session_manager.create_session().await {
-        Ok(session_id) => {
-            // 创建会话响应
+    match created {
+        Ok(Some(record)) => (StatusCode::CREATED, Json(terminal_session_from_record(record))),
+        Ok(None) => {
+            log::error!("Created session has no record");
             let session = create_default_terminal_session(
-                session_id.clone(),
-                user_id.clone(),
+                "".to_string(),
+                user_id,
                 params.title,
                 params.working_directory.unwrap_or(config.terminal.default_working_directory.clone()),
                 shell_type,
-                "ACTIVE".to_string(),
+                "ERROR".to_string(),
                 params.columns.unwrap_or(config.terminal.default_terminal_size.columns),
                 params.rows.unwrap_or(config.terminal.default_terminal_size.rows),
                 &config,
             );
-            
-            (StatusCode::CREATED, Json(session))
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(session))
         },
         Err(e) => {
             log::error!("Failed to create session: {}", e);
+            let status = e.downcast_ref::<SessionError>().map_or(StatusCode::INTERNAL_SERVER_ERROR, error_status);
             
             // 创建错误响应
             let session = create_default_terminal_session(
@@ -222,51 +559,53 @@ async fn create_session(
                 &config,
             );
             
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(session))
+            (status, Json(session))
         }
     }
 }
 
-// 获取所有会话
+// 获取所有会话，支持按状态（?status=ACTIVE|TERMINATED）和用户（?userId=）筛选
 async fn get_all_sessions(
-    State((session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
+    Query(params): Query<ListSessionsParams>,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<Vec<TerminalSession>>) {
-    // 获取所有会话及其状态
-    let sessions_with_status = session_manager.get_all_sessions_with_status().await;
-    
-    // 创建会话响应列表
-    let sessions: Vec<TerminalSession> = sessions_with_status.into_iter().map(|(session_id, status)| {
-        // 转换状态为字符串
-            let status_str = match status {
-                SessionStatus::Active => "ACTIVE".to_string(),
-                SessionStatus::Terminated => "TERMINATED".to_string(),
-            };
-        
-        create_default_terminal_session(
-            session_id.clone(),
-            "default_user".to_string(),
-            None,
-            config.terminal.default_working_directory.clone(),
-            config.terminal.default_shell_type.clone(),
-            status_str,
-            config.terminal.default_terminal_size.columns,
-            config.terminal.default_terminal_size.rows,
-            &config,
-        )
-    }).collect();
+    // 解析状态筛选条件
+    let status = match params.status.as_deref() {
+        Some(status) => match SessionStatus::parse(status) {
+            Some(status) => Some(status),
+            None => return (StatusCode::BAD_REQUEST, Json(Vec::new())),
+        },
+        None => None,
+    };
+    
+    let filter = SessionFilter {
+        status,
+        user_id: params.user_id,
+    };
     
-    (StatusCode::OK, Json(sessions))
+    match session_manager.list_session_records(&filter) {
+        Ok(records) => {
+            let sessions = records.into_iter().map(terminal_session_from_record).collect();
+            (StatusCode::OK, Json(sessions))
+        },
+        Err(e) => {
+            log::error!("Failed to list sessions: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new()))
+        }
+    }
 }
 
-// 获取会话详情
+// 获取会话详情，包括已终止的会话
 async fn get_session_by_id(
     Path(id): Path<String>,
     State((session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<TerminalSession>) {
-    // 获取会话状态
-    let status = match session_manager.get_session_status(&id).await {
-        Ok(status) => status,
-        Err(_) => {
+    match session_manager.get_session_record(&id) {
+        Ok(Some(record)) => (StatusCode::OK, Json(terminal_session_from_record(record))),
+        result => {
+            if let Err(e) = result {
+                log::error!("Failed to load session {}: {}", id, e);
+            }
             // 返回404 Not Found
             let session = create_default_terminal_session(
                 id,
@@ -279,36 +618,16 @@ async fn get_session_by_id(
                 config.terminal.default_terminal_size.rows,
                 &config,
             );
-            return (StatusCode::NOT_FOUND, Json(session));
+            (StatusCode::NOT_FOUND, Json(session))
         },
-    };
-    
-    // 转换状态为字符串
-    let status_str = match status {
-        SessionStatus::Active => "ACTIVE".to_string(),
-        SessionStatus::Terminated => "TERMINATED".to_string(),
-    };
-    
-    // 返回会话详情
-    let session = create_default_terminal_session(
-        id,
-        "default_user".to_string(),
-        None,
-        config.terminal.default_working_directory.clone(),
-        config.terminal.default_shell_type.clone(),
-        status_str,
-        config.terminal.default_terminal_size.columns,
-        config.terminal.default_terminal_size.rows,
-        &config,
-    );
-    
-    (StatusCode::OK, Json(session))
+    }
 }
 
 // 调整终端大小
 async fn resize_terminal(
     Path(id): Path<String>,
     Query(params): Query<ResizeParams>,
+    HttpClient(client): HttpClient,
     State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<TerminalResizeResponse>) {
     // 检查cols和rows参数是否提供
@@ -337,32 +656,21 @@ async fn resize_terminal(
     };
     
     // 调整终端大小
-    match session_manager.resize_session(&id, cols, rows).await {
-        Ok(_) => {
+    match session_manager.resize_session(&id, cols, rows, &client).await {
+        Ok(size) => {
             (StatusCode::OK, Json(TerminalResizeResponse {
                 session_id: id,
-                terminal_size: TerminalSize { columns: cols, rows: rows },
+                terminal_size: TerminalSize { columns: size.columns, rows: size.rows },
                 status: "ACTIVE".to_string(),
             }))
         },
         Err(e) => {
             log::error!("Failed to resize session {}: {}", id, e);
-            // 检查是否是因为会话不存在
-            if e.to_string().contains("Session not found") {
-                // 返回404 Not Found
-                (StatusCode::NOT_FOUND, Json(TerminalResizeResponse {
-                    session_id: id,
-                    terminal_size: TerminalSize { columns: cols, rows: rows },
-                    status: "ERROR".to_string(),
-                }))
-            } else {
-                // 返回500 Internal Server Error
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(TerminalResizeResponse {
-                    session_id: id,
-                    terminal_size: TerminalSize { columns: cols, rows: rows },
-                    status: "ERROR".to_string(),
-                }))
-            }
+            (error_status(&e), Json(TerminalResizeResponse {
+                session_id: id,
+                terminal_size: TerminalSize { columns: cols, rows },
+                status: "ERROR".to_string(),
+            }))
         }
     }
 }
@@ -370,6 +678,7 @@ async fn resize_terminal(
 // 中断终端
 async fn interrupt_terminal(
     Path(id): Path<String>,
+    HttpClient(client): HttpClient,
     State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<TerminalInterruptResponse>) {
     // 检查会话是否存在
@@ -382,7 +691,7 @@ async fn interrupt_terminal(
     }
     
     // 发送中断信号（Ctrl+C）到终端
-    match session_manager.write_to_session(&id, "\x03").await {
+    match session_manager.write_to_session(&id, "\x03", &client).await {
         Ok(_) => {
             (StatusCode::OK, Json(TerminalInterruptResponse {
                 session_id: id,
@@ -391,8 +700,7 @@ async fn interrupt_terminal(
         },
         Err(e) => {
             log::error!("Failed to interrupt session {}: {}", id, e);
-            // 返回500 Internal Server Error
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(TerminalInterruptResponse {
+            (error_status(&e), Json(TerminalInterruptResponse {
                 session_id: id,
                 status: "ERROR".to_string(),
             }))
@@ -400,13 +708,53 @@ async fn interrupt_terminal(
     }
 }
 
+// 向终端的前台进程组或shell发送信号，不受应用程序的终端模式影响
+async fn signal_terminal(
+    Path(id): Path<String>,
+    Query(params): Query<SignalParams>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    let Some(signal) = params.signal.as_deref().and_then(Signal::parse) else {
+        return (StatusCode::BAD_REQUEST, "Invalid or missing signal, expected one of SIGINT, SIGTERM, SIGKILL, SIGHUP, SIGTSTP, SIGCONT, SIGWINCH").into_response();
+    };
+    let target = match params.target.as_deref().map(SignalTarget::parse) {
+        None => SignalTarget::Foreground,
+        Some(Some(target)) => target,
+        Some(None) => return (StatusCode::BAD_REQUEST, "Invalid target, expected foreground or shell").into_response(),
+    };
+    
+    match session_manager.signal_session(&id, signal, target, &client).await {
+        Ok(pid) => Json(TerminalSignalResponse {
+            session_id: id,
+            signal: signal.name().to_string(),
+            target: target.as_str().to_string(),
+            pid,
+        }).into_response(),
+        Err(e) => error_response(e, "signal session"),
+    }
+}
+
+// 获取终端的前台进程
+async fn get_foreground_process(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    match session_manager.foreground_process(&id, &client).await {
+        Ok(process) => Json(process).into_response(),
+        Err(e) => error_response(e, "query foreground process"),
+    }
+}
+
 // 终止会话
 async fn terminate_session(
     Path(id): Path<String>,
+    HttpClient(client): HttpClient,
     State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<TerminalTerminateResponse>) {
     // 关闭会话
-    match session_manager.close_session(&id).await {
+    match session_manager.close_session(&id, "User terminated", &client).await {
         Ok(_) => {
             (StatusCode::OK, Json(TerminalTerminateResponse {
                 session_id: id,
@@ -416,26 +764,112 @@ async fn terminate_session(
         },
         Err(e) => {
             log::error!("Failed to terminate session {}: {}", id, e);
-            // 检查是否是因为会话不存在
-            if e.to_string().contains("Session not found") {
-                // 返回404 Not Found
-                (StatusCode::NOT_FOUND, Json(TerminalTerminateResponse {
-                    session_id: id,
-                    reason: e.to_string(),
-                    status: "ERROR".to_string(),
-                }))
-            } else {
-                // 返回500 Internal Server Error
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(TerminalTerminateResponse {
-                    session_id: id,
-                    reason: e.to_string(),
-                    status: "ERROR".to_string(),
-                }))
-            }
+            (error_status(&e), Json(TerminalTerminateResponse {
+                session_id: id,
+                reason: e.to_string(),
+                status: "ERROR".to_string(),
+            }))
+        }
+    }
+}
+
+// 创建会话共享 - role为viewer（只读）或collaborator（可输入），默认viewer
+async fn create_share(
+    Path(id): Path<String>,
+    Query(params): Query<CreateShareParams>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<Option<Share>>) {
+    let role = match params.role.as_deref().map(ShareRole::parse) {
+        None => ShareRole::Viewer,
+        Some(Some(role)) => role,
+        // 返回400 Bad Request
+        Some(None) => return (StatusCode::BAD_REQUEST, Json(None)),
+    };
+    if params.max_uses == Some(0) {
+        return (StatusCode::BAD_REQUEST, Json(None));
+    }
+    
+    match session_manager.create_share(&id, role, params.expires_in, params.max_uses, &client).await {
+        Ok(share) => (StatusCode::CREATED, Json(Some(share))),
+        Err(e) => {
+            log::error!("Failed to share session {}: {}", id, e);
+            (error_status(&e), Json(None))
         }
     }
 }
 
+// 列出会话共享
+async fn list_shares(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<Vec<Share>>) {
+    match session_manager.list_shares(&id, &client).await {
+        Ok(shares) => (StatusCode::OK, Json(shares)),
+        Err(e) => (error_status(&e), Json(Vec::new())),
+    }
+}
+
+// 撤销会话共享
+async fn revoke_share(
+    Path((id, share_id)): Path<(String, String)>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<TerminalStatusResponse>) {
+    match session_manager.revoke_share(&id, &share_id, &client) {
+        Ok(_) => (StatusCode::OK, Json(TerminalStatusResponse {
+            status: "REVOKED".to_string(),
+        })),
+        Err(e) => (error_status(&e), Json(TerminalStatusResponse {
+            status: "ERROR".to_string(),
+        })),
+    }
+}
+
+// 为会话添加触发器
+async fn add_trigger(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+    Json(rule): Json<TriggerRule>,
+) -> Response {
+    match session_manager.add_trigger(&id, rule, &client).await {
+        Ok(()) => (StatusCode::CREATED, Json(TerminalStatusResponse {
+            status: "CREATED".to_string(),
+        })).into_response(),
+        Err(e) => error_response(e, "add trigger"),
+    }
+}
+
+// 列出会话的触发器
+async fn list_triggers(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<Vec<TriggerRule>>) {
+    match session_manager.list_triggers(&id, &client) {
+        Ok(rules) => (StatusCode::OK, Json(rules)),
+        Err(e) => (error_status(&e), Json(Vec::new())),
+    }
+}
+
+// 删除会话的触发器
+async fn remove_trigger(
+    Path((id, name)): Path<(String, String)>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<TerminalStatusResponse>) {
+    match session_manager.remove_trigger(&id, &name, &client) {
+        Ok(()) => (StatusCode::OK, Json(TerminalStatusResponse {
+            status: "REMOVED".to_string(),
+        })),
+        Err(e) => (error_status(&e), Json(TerminalStatusResponse {
+            status: "ERROR".to_string(),
+        })),
+    }
+}
+
 // 获取会话状态
 async fn get_session_status(
     Path(id): Path<String>,
@@ -444,19 +878,12 @@ async fn get_session_status(
     // 获取会话状态
     match session_manager.get_session_status(&id).await {
         Ok(status) => {
-            // 转换状态为字符串
-            let status_str = match status {
-                SessionStatus::Active => "ACTIVE".to_string(),
-                SessionStatus::Terminated => "TERMINATED".to_string(),
-            };
-            
             (StatusCode::OK, Json(TerminalStatusResponse {
-                status: status_str,
+                status: status.as_str().to_string(),
             }))
         },
-        Err(_) => {
-            // 返回404 Not Found
-            (StatusCode::NOT_FOUND, Json(TerminalStatusResponse {
+        Err(e) => {
+            (error_status(&e), Json(TerminalStatusResponse {
                 status: "ERROR".to_string(),
             }))
         },
@@ -467,6 +894,7 @@ async fn get_session_status(
 async fn execute_command(
     Path(id): Path<String>,
     Query(params): Query<ExecuteParams>,
+    HttpClient(client): HttpClient,
     State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, String) {
     // 检查command参数是否提供
@@ -490,7 +918,7 @@ async fn execute_command(
     }
     
     // 执行命令
-    let result = session_manager.write_to_session(&id, &format!("{}\n", command)).await;
+    let result = session_manager.execute_command(&id, &command, &client).await;
     
     // 如果提供了超时时间，记录超时信息
     if let Some(timeout_ms) = params.timeout_ms {
@@ -501,10 +929,11 @@ async fn execute_command(
         Ok(_) => {
             (StatusCode::OK, format!("Command executed: {}", command))
         },
-        Err(e) => {
+        Err(SessionError::Other(e)) => {
             log::error!("Failed to execute command on session {}: {}", id, e);
             (StatusCode::INTERNAL_SERVER_ERROR, "Failed to execute command".to_string())
-        }
+        },
+        Err(e) => (error_status(&e), e.to_string()),
     }
 }
 
@@ -512,6 +941,7 @@ async fn execute_command(
 async fn execute_command_check(
     Path(id): Path<String>,
     Query(params): Query<ExecuteParams>,
+    HttpClient(client): HttpClient,
     State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
 ) -> (StatusCode, Json<bool>) {
     // 检查command参数是否提供
@@ -536,6 +966,343 @@ async fn execute_command_check(
     }
     
     // 执行命令
-    let success = session_manager.write_to_session(&id, &format!("{}\n", command)).await.is_ok();
-    (StatusCode::OK, Json(success))
+    match session_manager.execute_command(&id, &command, &client).await {
+        Ok(()) => (StatusCode::OK, Json(true)),
+        Err(e @ SessionError::Forbidden(_)) => (error_status(&e), Json(false)),
+        Err(_) => (StatusCode::OK, Json(false)),
+    }
+}
+
+// 执行send/expect脚本，等待输出匹配后返回各步骤的捕获值或失败的步骤
+async fn run_expect_script(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+    Json(script): Json<ExpectScript>,
+) -> Response {
+    if !session_manager.session_exists(&id).await {
+        return (StatusCode::NOT_FOUND, "Session not found").into_response();
+    }
+    
+    session_manager.audit().record(AuditAction::Execute, &id, &client, Some(&format!("expect script with {} steps", script.steps.len())));
+    match expect::run(&session_manager, &id, &script, &client).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => error_response(e, "run expect script"),
+    }
+}
+
+// 获取会话的shell集成状态
+async fn get_shell_state(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    match session_manager.shell_state(&id, &client) {
+        Ok(state) => Json(state).into_response(),
+        Err(e) => error_response(e, "query shell state"),
+    }
+}
+
+// 获取会话最近执行的命令
+async fn get_session_history(
+    Path(id): Path<String>,
+    Query(params): Query<HistoryParams>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    let limit = params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).min(MAX_HISTORY_LIMIT);
+    match session_manager.session_history(&id, limit, &client) {
+        Ok(commands) => Json(commands).into_response(),
+        Err(e) => error_response(e, "query command history"),
+    }
+}
+
+// 在会话中重新执行历史命令
+async fn rerun_command(
+    Path((id, number)): Path<(String, u64)>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    match session_manager.rerun_command(&id, number, &client).await {
+        Ok(command) => Json(command).into_response(),
+        Err(e) => error_response(e, &format!("rerun command {} in session {}", number, id)),
+    }
+}
+
+// 跨会话查询命令历史，指定sessionId时按共享令牌或会话所有者授权，否则查询userId参数或X-User-Id请求头指定用户的命令
+async fn search_history(
+    Query(params): Query<HistorySearchParams>,
+    HttpClient(mut client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    if let Some(session_id) = &params.session_id
+        && let Err(e) = session_manager.authorize_client(session_id, &mut client) {
+        log::warn!("Rejected history query for session {}: {}", session_id, e);
+        return (StatusCode::FORBIDDEN, "Invalid share token").into_response();
+    }
+    let text = params.q.filter(|text| !text.is_empty());
+    let limit = params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).min(MAX_HISTORY_LIMIT);
+    match session_manager.search_history(params.session_id, text, limit, &client) {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => error_response(e, "search command history"),
+    }
+}
+
+// 列出所有会话录制
+async fn list_recordings(
+    State((_session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<Vec<RecordingInfo>>) {
+    match recording::list_recordings(&config.recording.directory) {
+        Ok(recordings) => (StatusCode::OK, Json(recordings)),
+        Err(e) => {
+            log::error!("Failed to list recordings: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::new()))
+        }
+    }
+}
+
+// 获取录制摘要（尺寸、时长、事件数量），回放前供客户端展示
+async fn get_recording(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((_session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> (StatusCode, Json<Option<RecordingSummary>>) {
+    if let Err(e) = check_reader(&id, &client) {
+        return (error_status(&e), Json(None));
+    }
+    let path = match recording::recording_path(&config.recording.directory, &id) {
+        Ok(path) => path,
+        Err(_) => return (StatusCode::BAD_REQUEST, Json(None)),
+    };
+    if !path.exists() {
+        return (StatusCode::NOT_FOUND, Json(None));
+    }
+    
+    match Recording::load(&path) {
+        Ok(recording) => (StatusCode::OK, Json(Some(recording.summary(&id)))),
+        Err(e) => {
+            log::error!("Failed to load recording {:?}: {}", path, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+        }
+    }
+}
+
+// 下载会话录制文件（asciicast v2），可直接用asciinema播放
+async fn download_recording(
+    Path(id): Path<String>,
+    HttpClient(client): HttpClient,
+    State((_session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    if let Err(e) = check_reader(&id, &client) {
+        return error_response(e, &format!("download recording {}", id));
+    }
+    let path = match recording::recording_path(&config.recording.directory, &id) {
+        Ok(path) => path,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid session id").into_response(),
+    };
+    
+    match tokio::fs::read(&path).await {
+        Ok(content) => (
+            [
+                (header::CONTENT_TYPE, "application/x-asciicast".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.cast\"", id)),
+            ],
+            content,
+        ).into_response(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            (StatusCode::NOT_FOUND, "Recording not found").into_response()
+        },
+        Err(e) => {
+            log::error!("Failed to read recording {:?}: {}", path, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read recording").into_response()
+        }
+    }
+}
+
+// 获取会话当前屏幕的快照，可附带滚动历史
+async fn get_screen(
+    Path(id): Path<String>,
+    Query(params): Query<ScreenParams>,
+    HttpClient(client): HttpClient,
+    State((session_manager, config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    let format = match params.format.as_deref().map(ScreenFormat::parse) {
+        None => ScreenFormat::Text,
+        Some(Some(format)) => format,
+        Some(None) => return (StatusCode::BAD_REQUEST, "Invalid format, expected text, ansi, html or json").into_response(),
+    };
+    let scrollback = params.scrollback.unwrap_or(0).min(config.terminal.scrollback_lines);
+    
+    match session_manager.render_screen(&id, format, scrollback, &client) {
+        Ok(screen) => (
+            [(header::CONTENT_TYPE, format.content_type())],
+            screen,
+        ).into_response(),
+        Err(e) => error_response(e, &format!("render screen of session {}", id)),
+    }
+}
+
+// 在会话保留的输出中搜索，返回匹配行及前后文
+async fn search_session(
+    Path(id): Path<String>,
+    Query(params): Query<SearchParams>,
+    HttpClient(client): HttpClient,
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    let Some(text) = params.q else {
+        return (StatusCode::BAD_REQUEST, "Missing query parameter q").into_response();
+    };
+    let query = match SearchQuery::new(
+        &text,
+        params.regex.unwrap_or(false),
+        params.ignore_case.unwrap_or(false),
+        params.context.unwrap_or(DEFAULT_SEARCH_CONTEXT).min(MAX_SEARCH_CONTEXT),
+        params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT),
+    ) {
+        Ok(query) => query,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    
+    match session_manager.search_session(&id, &query, &client) {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => error_response(e, &format!("search session {}", id)),
+    }
+}
+
+// 导出Prometheus指标
+async fn get_metrics(
+    State((session_manager, _config)): State<(Arc<SessionManager>, Arc<Config>)>,
+) -> Response {
+    match session_manager.render_metrics() {
+        Ok(metrics) => (
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            metrics,
+        ).into_response(),
+        Err(e) => {
+            log::error!("Failed to render metrics: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render metrics").into_response()
+        }
+    }
+}
+
+// 存活检查 - 必需的适配器均未失败时返回200
+async fn get_health(
+    State(health): State<HealthRegistry>,
+) -> (StatusCode, Json<HealthState>) {
+    let state = health.snapshot();
+    let status = if state.is_healthy() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(state))
+}
+
+// 就绪检查 - 必需的适配器均已开始监听且未在关闭时返回200
+async fn get_readiness(
+    State(health): State<HealthRegistry>,
+) -> (StatusCode, Json<HealthState>) {
+    let state = health.snapshot();
+    let status = if state.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(state))
+}
+
+// 获取WebTransport证书的SHA-256摘要
+async fn get_certificate_hash(
+    State(certificate_digest): State<CertificateDigest>,
+) -> (StatusCode, Json<Option<CertificateHashResponse>>) {
+    match certificate_digest.get() {
+        Some(digest) => {
+            let hex = digest.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":");
+            (StatusCode::OK, Json(Some(CertificateHashResponse {
+                algorithm: "sha-256".to_string(),
+                value: digest.to_vec(),
+                hex,
+            })))
+        },
+        // WebTransport服务器尚未加载证书
+        None => (StatusCode::SERVICE_UNAVAILABLE, Json(None)),
+    }
+}
+
+// WebSocket升级 - 与REST API共用端口，附加到已存在的会话
+async fn websocket_upgrade(
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+    HttpClient(client): HttpClient,
+    ws: WebSocketUpgrade,
+    State(terminal_service): State<Arc<TerminalService>>,
+) -> Response {
+    // 检查会话是否存在
+    if !terminal_service.session_exists(&id).await {
+        return (StatusCode::NOT_FOUND, "Session not found").into_response();
+    }
+    
+    // 没有角色的客户端不能观看，在升级前拒绝
+    if let Err(e) = check_reader(&id, &client) {
+        return error_response(e, &format!("connect to session {}", id));
+    }
+    let client = ClientContext { transport: TransportKind::WebSocket, ..client };
+    let protocol = TextProtocol::from_query(query.as_deref());
+    
+    ws.on_upgrade(move |socket| async move {
+        log::info!("New WebSocket connection established on HTTP server for session {}", id);
+        if let Err(e) = crate::transport::websocket::websocket::handle_axum_connection(socket, terminal_service, ConnectionTarget::Session(id), client, protocol).await {
+            log::error!("WebSocket connection error: {}", e);
+        }
+    })
+}
+
+// WebSocket回放 - 按原始节奏播放录制，支持 ?speed= 倍速
+async fn websocket_replay(
+    Path(id): Path<String>,
+    Query(params): Query<ReplayParams>,
+    RawQuery(query): RawQuery,
+    HttpClient(client): HttpClient,
+    ws: WebSocketUpgrade,
+    State(terminal_service): State<Arc<TerminalService>>,
+) -> Response {
+    // 检查录制是否存在
+    if !terminal_service.recording_exists(&id) {
+        return (StatusCode::NOT_FOUND, "Recording not found").into_response();
+    }
+    if let Err(e) = check_reader(&id, &client) {
+        return error_response(e, &format!("replay recording {}", id));
+    }
+    
+    let target = ConnectionTarget::Replay {
+        recording_id: id,
+        speed: params.speed.unwrap_or(1.0),
+    };
+    let protocol = TextProtocol::from_query(query.as_deref());
+    ws.on_upgrade(move |socket| async move {
+        log::info!("New WebSocket replay connection established on HTTP server: {:?}", target);
+        let client = ClientContext { transport: TransportKind::WebSocket, ..client };
+        if let Err(e) = crate::transport::websocket::websocket::handle_axum_connection(socket, terminal_service, target, client, protocol).await {
+            log::error!("WebSocket replay error: {}", e);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_routes_resolve_roles() {
+        // 读取会话内容的路由都按会话确定角色
+        for path in [
+            "/api/sessions/{id}/screen",
+            "/api/sessions/{id}/search",
+            "/api/sessions/{id}/shell",
+            "/api/sessions/{id}/foreground",
+            "/api/sessions/{id}/history",
+            "/api/sessions/{id}/recording",
+            "/api/sessions/{id}/triggers",
+            "/api/recordings/{id}",
+            "/ws/{id}",
+            "/ws/replay/{id}",
+        ] {
+            assert!(is_session_route(path), "{}", path);
+        }
+        for path in ["/api/sessions", "/api/recordings", "/api/history", "/metrics"] {
+            assert!(!is_session_route(path), "{}", path);
+        }
+    }
 }
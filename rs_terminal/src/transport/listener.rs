@@ -0,0 +1,64 @@
+use std::net::{IpAddr, SocketAddr};
+
+// 解析监听地址，支持IPv4和IPv6（IPv6地址可带方括号，如 "[::1]"）
+pub fn parse_bind_address(bind_address: &str, port: u16) -> anyhow::Result<SocketAddr> {
+    let host = bind_address.trim().trim_start_matches('[').trim_end_matches(']');
+    let ip: IpAddr = host
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid bind address '{}': {}", bind_address, e))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+// 绑定Unix域套接字，并按配置设置文件权限（八进制字符串，如 "660"）
+// 先在权限为0700的临时目录中绑定并设置权限，再重命名到目标路径，
+// 避免套接字在设置权限前以默认权限暴露给其他用户
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &str, mode: Option<&str>) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt};
+    use std::path::Path;
+
+    let mode = mode
+        .map(|mode| u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+            .map_err(|e| anyhow::anyhow!("Invalid unix socket mode '{}': {}", mode, e)))
+        .transpose()?;
+
+    // 清理上次运行遗留的套接字文件，其他类型的文件不做处理
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        } else {
+            anyhow::bail!("Unix socket path {} exists and is not a socket", path);
+        }
+    }
+
+    let target = Path::new(path);
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid unix socket path {}", path))?
+        .to_string_lossy();
+    // 临时目录与目标在同一目录下，保证重命名不跨文件系统
+    let staging = target
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let result = bind_staged(&staging.join(file_name.as_ref()), target, mode);
+    let _ = std::fs::remove_dir_all(&staging);
+
+    result
+}
+
+// 在临时路径绑定并设置权限后移动到目标路径
+#[cfg(unix)]
+fn bind_staged(staged_path: &std::path::Path, target: &std::path::Path, mode: Option<u32>) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let listener = tokio::net::UnixListener::bind(staged_path)?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(staged_path, std::fs::Permissions::from_mode(mode))?;
+    }
+    std::fs::rename(staged_path, target)?;
+    Ok(listener)
+}
@@ -1,4 +1,6 @@
 // Transport模块 - 处理不同传输协议的实现
 pub mod http;
+pub mod listener;
+pub mod tls;
 pub mod websocket;
 pub mod webtransport;
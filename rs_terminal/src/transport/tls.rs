@@ -0,0 +1,195 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+// 证书文件变更检查间隔
+const CERT_RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// TLS握手超时时间
+pub const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 读取配置中的证书和私钥路径，空字符串视为未配置
+pub fn configured_paths(cert_path: &Option<String>, key_path: &Option<String>) -> Option<(String, String)> {
+    match (cert_path.as_deref(), key_path.as_deref()) {
+        (Some(cert), Some(key)) if !cert.is_empty() && !key.is_empty() => Some((cert.to_string(), key.to_string())),
+        _ => None,
+    }
+}
+
+// 可热加载的TLS配置 - 证书文件在磁盘上变化时自动重新加载
+#[derive(Clone)]
+pub struct ReloadableTlsConfig {
+    current: Arc<RwLock<Arc<rustls::ServerConfig>>>,
+}
+
+impl ReloadableTlsConfig {
+    // 从PEM证书和私钥文件加载TLS配置，并启动文件变更监听任务
+    pub fn load(cert_path: &str, key_path: &str, alpn_protocols: Vec<Vec<u8>>) -> anyhow::Result<Self> {
+        let cert_path = PathBuf::from(cert_path);
+        let key_path = PathBuf::from(key_path);
+
+        let server_config = load_server_config(&cert_path, &key_path, &alpn_protocols)?;
+        let tls_config = Self {
+            current: Arc::new(RwLock::new(Arc::new(server_config))),
+        };
+
+        // 证书变化后重新加载，加载失败时继续使用旧证书
+        let current = tls_config.current.clone();
+        let (watched_cert, watched_key) = (cert_path.clone(), key_path.clone());
+        watch_files(vec![cert_path, key_path], move || {
+            match load_server_config(&watched_cert, &watched_key, &alpn_protocols) {
+                Ok(server_config) => {
+                    *current.write().unwrap() = Arc::new(server_config);
+                    log::info!("Reloaded TLS certificate from {:?}", watched_cert);
+                },
+                Err(e) => {
+                    log::error!("Failed to reload TLS certificate from {:?}, keeping previous one: {}", watched_cert, e);
+                }
+            }
+        });
+
+        Ok(tls_config)
+    }
+
+    // 获取基于当前证书的TLS接收器
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().clone())
+    }
+}
+
+// 加载PEM证书链和私钥，构建rustls服务端配置
+fn load_server_config(cert_path: &Path, key_path: &Path, alpn_protocols: &[Vec<u8>]) -> anyhow::Result<rustls::ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read certificate {:?}: {}", cert_path, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid certificate {:?}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificate found in {:?}", cert_path);
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read private key {:?}: {}", key_path, e))?;
+
+    let mut server_config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    server_config.alpn_protocols = alpn_protocols.to_vec();
+
+    Ok(server_config)
+}
+
+// 获取文件修改时间
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// 监听文件变化 - 定期比较修改时间，任一文件变化时调用回调
+pub fn watch_files<F>(paths: Vec<PathBuf>, on_change: F)
+where
+    F: Fn() + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut last_modified: Vec<Option<SystemTime>> = paths.iter().map(|path| modified_time(path)).collect();
+        let mut interval = tokio::time::interval(CERT_RELOAD_CHECK_INTERVAL);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let modified: Vec<Option<SystemTime>> = paths.iter().map(|path| modified_time(path)).collect();
+            if modified != last_modified {
+                log::debug!("Detected change in certificate files: {:?}", paths);
+                last_modified = modified;
+                on_change();
+            }
+        }
+    });
+}
+
+// TLS监听器 - 后台接受TCP连接并完成握手，供axum::serve使用
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    // 在已绑定的TCP监听器上启动TLS握手任务
+    pub fn new(listener: TcpListener, tls_config: ReloadableTlsConfig) -> anyhow::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (incoming_tx, incoming) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            // 监听器被丢弃后停止接受连接
+            while !incoming_tx.is_closed() {
+                let (stream, remote_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        log::error!("Failed to accept TLS connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                // 每个握手独立进行，避免慢客户端阻塞其他连接
+                let acceptor = tls_config.acceptor();
+                let handshake_tx = incoming_tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(tls_stream)) => {
+                            let _ = handshake_tx.send((tls_stream, remote_addr)).await;
+                        },
+                        Ok(Err(e)) => log::debug!("TLS handshake with {} failed: {}", remote_addr, e),
+                        Err(_) => log::debug!("TLS handshake with {} timed out", remote_addr),
+                    }
+                });
+            }
+        });
+
+        Ok(Self { local_addr, incoming })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(accepted) => accepted,
+            // 握手任务已退出，不再产生新连接
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+// WebTransport证书摘要 - 在WebTransport适配器与HTTP服务器之间共享，
+// 供浏览器通过serverCertificateHashes信任自签名证书
+#[derive(Clone, Default)]
+pub struct CertificateDigest {
+    current: Arc<RwLock<Option<[u8; 32]>>>,
+}
+
+impl CertificateDigest {
+    // 更新当前证书摘要
+    pub fn set(&self, digest: [u8; 32]) {
+        *self.current.write().unwrap() = Some(digest);
+    }
+
+    // 获取当前证书摘要
+    pub fn get(&self) -> Option<[u8; 32]> {
+        *self.current.read().unwrap()
+    }
+}
@@ -1,2 +1,3 @@
 // WebSocket传输模块 - 处理WebSocket连接和通信
+#[allow(clippy::module_inception)]
 pub mod websocket;
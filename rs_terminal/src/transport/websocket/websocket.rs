@@ -1,29 +1,46 @@
 use std::sync::Arc;
-use std::net::SocketAddr;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::pin::Pin;
 
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
-use futures_util::{stream::StreamExt, sink::SinkExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use futures_util::{future, Sink, Stream, stream::StreamExt, sink::SinkExt};
 use tokio::sync::mpsc;
 
+use crate::audit::{ClientContext, TransportKind};
 use crate::config::Config;
+use crate::content_log;
+use crate::health::HealthRegistry;
 use crate::protocol::adapter::ProtocolAdapter;
-use crate::pty::terminal_service::TerminalService;
+use crate::protocol::message::{ServerMessage, TextProtocol};
+use crate::pty::terminal_service::{ConnectionTarget, TerminalService};
+use crate::shutdown::ShutdownSignal;
+use crate::transport::listener;
+use crate::transport::tls::{self, ReloadableTlsConfig};
 
 // WebSocket适配器 - 实现ProtocolAdapter接口
 pub struct WebSocketAdapter {
     terminal_service: Arc<TerminalService>,
     config: Arc<Config>,
+    health: HealthRegistry,
+    shutdown: ShutdownSignal,
 }
 
 impl WebSocketAdapter {
+    // 健康检查中的适配器名称
+    pub const NAME: &'static str = "websocket";
+    
     // 创建新的WebSocket适配器
-    pub fn new(terminal_service: Arc<TerminalService>, config: Arc<Config>) -> Self {
+    pub fn new(terminal_service: Arc<TerminalService>, config: Arc<Config>, health: HealthRegistry, shutdown: ShutdownSignal) -> Self {
         Self {
             terminal_service,
             config,
+            health,
+            shutdown,
         }
     }
 }
@@ -33,37 +50,134 @@ impl ProtocolAdapter for WebSocketAdapter {
     fn start(&self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
         let terminal_service = self.terminal_service.clone();
         let port = self.config.websocket.port;
+        let websocket_config = self.config.websocket.clone();
+        let shutdown = self.shutdown.clone();
+        let health = self.health.clone();
         
         Box::pin(async move {
-            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            // 启用WSS时加载证书，证书文件变化时自动重新加载（Unix域套接字不使用TLS）
+            let tls_config = if websocket_config.use_tls && websocket_config.unix_socket_path.is_none() {
+                let (cert_path, key_path) = tls::configured_paths(&websocket_config.cert_path, &websocket_config.key_path)
+                    .ok_or_else(|| anyhow::anyhow!("WSS is enabled but cert_path or key_path is not configured"))?;
+                Some(ReloadableTlsConfig::load(&cert_path, &key_path, vec![b"http/1.1".to_vec()])?)
+            } else {
+                None
+            };
+            
+            // 配置了Unix域套接字时只监听该套接字，由本地反向代理负责TLS
+            if let Some(socket_path) = &websocket_config.unix_socket_path {
+                #[cfg(unix)]
+                {
+                    let listener = listener::bind_unix_socket(socket_path, websocket_config.unix_socket_mode.as_deref())?;
+                    log::info!("WebSocket server started on unix:{}", socket_path);
+                    health.set_ready(Self::NAME);
+                    
+                    // 收到关闭信号后停止接受新连接
+                    while let Some((stream, _)) = next_connection(|| listener.accept(), &shutdown).await? {
+                        let terminal_service = terminal_service.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, terminal_service, None).await {
+                                log::error!("WebSocket connection error: {}", e);
+                            }
+                        });
+                    }
+                    log::info!("WebSocket server stopped");
+                    return Ok(());
+                }
+                #[cfg(not(unix))]
+                anyhow::bail!("Unix domain sockets are not supported on this platform: {}", socket_path);
+            }
+            
+            let addr = listener::parse_bind_address(&websocket_config.bind_address, port)?;
             let listener = TcpListener::bind(addr).await?;
             
-            log::info!("WebSocket server started on ws://localhost:{}", port);
+            let scheme = if tls_config.is_some() { "wss" } else { "ws" };
+            log::info!("WebSocket server started on {}://{}", scheme, addr);
+            health.set_ready(Self::NAME);
 
-            // 处理传入的连接 - 每个连接独立处理
-            while let Ok((stream, _)) = listener.accept().await {
+            // 处理传入的连接 - 每个连接独立处理，收到关闭信号后停止接受新连接
+            while let Some((stream, peer)) = next_connection(|| listener.accept(), &shutdown).await? {
                 let terminal_service = terminal_service.clone();
+                let tls_config = tls_config.clone();
                 
                 // 为每个连接启动独立任务
                 tokio::spawn(async move {
-                    if let Err(e) = handle_connection(stream, terminal_service).await {
+                    let result = match tls_config {
+                        // 握手超时后关闭连接，避免不完成握手的客户端长期占用连接
+                        Some(tls_config) => match tokio::time::timeout(tls::TLS_HANDSHAKE_TIMEOUT, tls_config.acceptor().accept(stream)).await {
+                            Ok(Ok(tls_stream)) => handle_connection(tls_stream, terminal_service, Some(peer)).await,
+                            Ok(Err(e)) => {
+                                log::warn!("WebSocket TLS handshake failed: {}", e);
+                                return;
+                            },
+                            Err(_) => {
+                                log::warn!("WebSocket TLS handshake with {} timed out", peer);
+                                return;
+                            }
+                        },
+                        None => handle_connection(stream, terminal_service, Some(peer)).await,
+                    };
+                    
+                    if let Err(e) = result {
                         log::error!("WebSocket connection error: {}", e);
                     }
                 });
             }
             
+            log::info!("WebSocket server stopped");
             Ok(())
         })
     }
 }
 
-// 处理WebSocket连接 - 简洁的中转设计
-async fn handle_connection(
-    stream: TcpStream,
+// 等待新连接，关闭信号触发时返回None；单个连接在握手前被对端中止时继续等待，
+// 监听器本身出错时返回错误，适配器随之标记为失败
+async fn next_connection<A, F, T>(mut accept: A, shutdown: &ShutdownSignal) -> anyhow::Result<Option<T>>
+where
+    A: FnMut() -> F,
+    F: Future<Output = std::io::Result<T>>,
+{
+    loop {
+        let accepted = tokio::select! {
+            accepted = accept() => accepted,
+            _ = shutdown.wait() => return Ok(None),
+        };
+        match accepted {
+            Ok(connection) => return Ok(Some(connection)),
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::Interrupted) => {
+                log::warn!("Failed to accept WebSocket connection: {}", e);
+            },
+            Err(e) => return Err(anyhow::anyhow!("Failed to accept WebSocket connection: {}", e)),
+        }
+    }
+}
+
+// WebSocket帧 - 屏蔽tokio-tungstenite与axum消息类型的差异
+pub enum WebSocketFrame {
+    Text(String),
+    Close,
+    Other,
+}
+
+// 处理独立监听器上的WebSocket连接 - 路径为 /ws/{sessionId} 且会话存在时附加到该会话，否则创建新会话
+// 握手回调的错误类型由tungstenite决定
+#[allow(clippy::result_large_err)]
+async fn handle_connection<S>(
+    stream: S,
     terminal_service: Arc<TerminalService>,
-) -> anyhow::Result<()> {
-    // 1. 接受WebSocket连接
-    let ws_stream = match accept_async(stream).await {
+    peer: Option<SocketAddr>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // 1. 接受WebSocket连接，同时记录请求路径和发起连接的客户端
+    let mut request_path = String::new();
+    let mut client = ClientContext::new(TransportKind::WebSocket, None, None);
+    let ws_stream = match accept_hdr_async(stream, |request: &Request, response: Response| {
+        request_path = request.uri().path_and_query().map(|path| path.to_string()).unwrap_or_default();
+        client = ClientContext::from_request(TransportKind::WebSocket, peer, request.headers(), request.uri().query());
+        Ok(response)
+    }).await {
         Ok(stream) => stream,
         Err(e) => {
             log::warn!("Failed to accept WebSocket connection: {}", e);
@@ -71,20 +185,77 @@ async fn handle_connection(
         }
     };
     
-    log::info!("New WebSocket connection established");
+    log::info!("New WebSocket connection established on path {}", request_path);
     
-    // 2. 创建终端会话
-    let session_id = terminal_service.create_terminal_session().await?;
-    log::info!("Created new session {} for WebSocket connection", session_id);
+    // 2. 确定连接目标：/ws/replay/{recordingId} 回放录制，/ws/{sessionId} 附加到已存在的会话，否则创建新会话
+    let target = match ConnectionTarget::from_path(&request_path) {
+        Some(ConnectionTarget::Session(id)) if request_path.starts_with("/ws/") && terminal_service.session_exists(&id).await => {
+            ConnectionTarget::Session(id)
+        },
+        Some(replay @ ConnectionTarget::Replay { .. }) if request_path.starts_with("/ws/") => replay,
+        // 持有共享令牌的客户端只能连接已存在的会话
+        _ if client.share_token.is_some() => anyhow::bail!("Shared session not found: {}", request_path),
+        _ => {
+            let session_id = terminal_service.create_terminal_session(&client).await?;
+            log::info!("Created new session {} for WebSocket connection", session_id);
+            ConnectionTarget::Session(session_id)
+        }
+    };
+    
+    // 3. 转换消息类型并转发
+    let protocol = TextProtocol::from_query(request_path.split_once('?').map(|(_, query)| query));
+    let (ws_write, ws_read) = ws_stream.split();
+    let incoming = ws_read.map(|msg_result| {
+        msg_result
+            .map(|msg| match msg {
+                Message::Text(text) => WebSocketFrame::Text(text.to_string()),
+                Message::Close(_) => WebSocketFrame::Close,
+                _ => WebSocketFrame::Other,
+            })
+            .map_err(anyhow::Error::from)
+    });
+    let outgoing = ws_write
+        .sink_map_err(anyhow::Error::from)
+        .with(|text: String| future::ready(Ok::<_, anyhow::Error>(Message::Text(text.into()))));
+    
+    bridge_session(incoming, outgoing, terminal_service, target, client, protocol).await
+}
+
+// 在WebSocket连接与终端会话（或录制回放）之间转发消息 - 独立监听器和axum路由共用
+pub async fn bridge_session<R, W>(
+    mut ws_read: R,
+    ws_write: W,
+    terminal_service: Arc<TerminalService>,
+    target: ConnectionTarget,
+    mut client: ClientContext,
+    protocol: TextProtocol,
+) -> anyhow::Result<()>
+where
+    R: Stream<Item = anyhow::Result<WebSocketFrame>> + Unpin + Send + 'static,
+    W: Sink<String, Error = anyhow::Error> + Unpin + Send + 'static,
+{
+    let mut ws_write = ws_write;
     
     // 创建终端输出通道
-    let (terminal_output_tx, mut terminal_output_rx) = mpsc::channel::<String>(100);
+    let (terminal_output_tx, mut terminal_output_rx) = mpsc::channel::<ServerMessage>(100);
     
-    // 3. 处理终端连接
-    terminal_service.handle_terminal_connection(&session_id, terminal_output_tx.clone()).await?;
+    // 回复消息使用弱引用发送者，会话关闭后输出通道随之关闭，写任务据此结束连接
+    let reply_tx = terminal_output_tx.downgrade();
     
-    // 拆分WebSocket流为读写通道
-    let (mut ws_write, mut ws_read) = ws_stream.split();
+    // 添加客户端发送者到会话，或启动回放；失败时（会话不存在、已终止或无权观看）先告知客户端再关闭
+    let session_id = match terminal_service.connect(target, terminal_output_tx, &mut client).await {
+        Ok(session_id) => session_id,
+        Err(e) => {
+            if let Some(text) = protocol.encode(&ServerMessage::Error { message: e.to_string() }) {
+                let _ = ws_write.send(text).await;
+            }
+            let _ = ws_write.close().await;
+            return Err(e);
+        }
+    };
+    let transport = client.transport;
+    let client_id = client.client_id.clone();
+    let _attached = terminal_service.metrics().client_attached(transport);
     
     // 任务1: WebSocket读 → PTY写
     let terminal_service_clone = terminal_service.clone();
@@ -94,31 +265,44 @@ async fn handle_connection(
         log::debug!("Started WebSocket read task (WebSocket → PTY)");
         
         // 监听WebSocket消息
-        while let Some(msg_result) = ws_read.next().await {
-            match msg_result {
-                Ok(msg) => {
-                    if msg.is_text() {
-                        // 直接将WebSocket消息转发到终端
-                        let text = match msg.to_text() {
-                            Ok(text) => text,
-                            Err(e) => {
-                                log::error!("Failed to parse WebSocket text: {}", e);
-                                continue;
+        while let Some(frame_result) = ws_read.next().await {
+            match frame_result {
+                Ok(WebSocketFrame::Text(text)) => {
+                    // 无法解析的消息回复错误后继续处理后续消息
+                    let message = match protocol.parse(&text) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            log::warn!("Invalid WebSocket message for session {}: {}", session_id_clone, e);
+                            let Some(reply_tx) = reply_tx.upgrade() else { break };
+                            if reply_tx.send(ServerMessage::Error { message: format!("Invalid message: {}", e) }).await.is_err() {
+                                break;
                             }
-                        };
-                        
-                        log::debug!("WebSocket → PTY: {:?} (session: {})", text, session_id_clone);
-                        
-                        // 写入到终端 - 异步操作，不阻塞
-                        if let Err(e) = terminal_service_clone.handle_terminal_input(&session_id_clone, text.to_string()).await {
-                            log::error!("Failed to write to terminal: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Some(payload) = content_log::client_message(&message) {
+                        log::debug!("WebSocket → PTY: {} (session: {})", payload, session_id_clone);
+                    }
+                    // 处理失败只回复该客户端，连接在读写出错或会话关闭时才结束
+                    let reply = match terminal_service_clone.handle_client_message(&session_id_clone, message, &client).await {
+                        Ok(reply) => reply,
+                        Err(e) => {
+                            log::warn!("Failed to handle WebSocket message for session {}: {}", session_id_clone, e);
+                            Some(ServerMessage::Error { message: e.to_string() })
+                        }
+                    };
+                    if let Some(reply) = reply {
+                        let Some(reply_tx) = reply_tx.upgrade() else { break };
+                        if reply_tx.send(reply).await.is_err() {
                             break;
                         }
-                    } else if msg.is_close() {
-                        log::info!("WebSocket connection closing (read task)");
-                        break;
                     }
                 },
+                Ok(WebSocketFrame::Close) => {
+                    log::info!("WebSocket connection closing (read task)");
+                    break;
+                },
+                Ok(WebSocketFrame::Other) => {},
                 Err(e) => {
                     log::error!("WebSocket read error: {}", e);
                     break;
@@ -131,6 +315,7 @@ async fn handle_connection(
     
     // 任务2: PTY读 → WebSocket写
     let session_id_clone = session_id.clone();
+    let terminal_service_clone = terminal_service.clone();
     
     let ws_write_task = tokio::spawn(async move {
         log::debug!("Started WebSocket write task (PTY → WebSocket)");
@@ -138,18 +323,26 @@ async fn handle_connection(
         loop {
             tokio::select! {
                 // 监听终端输出
-                Some(output) = terminal_output_rx.recv() => {
-                    log::debug!("PTY → WebSocket: {:?} (session: {})", output, session_id_clone);
+                Some(message) = terminal_output_rx.recv() => {
+                    if let Some(payload) = content_log::server_message(&message) {
+                        log::debug!("PTY → WebSocket: {} (session: {})", payload, session_id_clone);
+                    }
                     
-                    // 发送到WebSocket - 异步操作，不阻塞
-                    if let Err(e) = ws_write.send(Message::Text(output.into())).await {
+                    if let ServerMessage::Output { data } = &message {
+                        terminal_service_clone.metrics().bytes_sent(transport, data.len());
+                    }
+                    
+                    // 发送到WebSocket - 异步操作，不阻塞；原始模式下只发送终端输出
+                    let Some(text) = protocol.encode(&message) else { continue };
+                    if let Err(e) = ws_write.send(text).await {
                         log::error!("Failed to send terminal output to WebSocket: {}", e);
                         break;
                     }
                 },
-                // 所有通道关闭，退出
+                // 所有通道关闭（会话已关闭），发送关闭帧后退出
                 else => {
                     log::debug!("All channels closed, exiting write task");
+                    let _ = ws_write.close().await;
                     break;
                 }
             }
@@ -168,5 +361,33 @@ async fn handle_connection(
         }
     }
     
+    terminal_service.disconnect(&session_id, &client_id);
     Ok(())
 }
+
+// 处理axum路由升级后的WebSocket连接
+pub async fn handle_axum_connection(
+    socket: axum::extract::ws::WebSocket,
+    terminal_service: Arc<TerminalService>,
+    target: ConnectionTarget,
+    client: ClientContext,
+    protocol: TextProtocol,
+) -> anyhow::Result<()> {
+    use axum::extract::ws::Message as AxumMessage;
+    
+    let (ws_write, ws_read) = socket.split();
+    let incoming = ws_read.map(|msg_result| {
+        msg_result
+            .map(|msg| match msg {
+                AxumMessage::Text(text) => WebSocketFrame::Text(text.to_string()),
+                AxumMessage::Close(_) => WebSocketFrame::Close,
+                _ => WebSocketFrame::Other,
+            })
+            .map_err(anyhow::Error::from)
+    });
+    let outgoing = ws_write
+        .sink_map_err(anyhow::Error::from)
+        .with(|text: String| future::ready(Ok::<_, anyhow::Error>(AxumMessage::Text(text.into()))));
+    
+    bridge_session(incoming, outgoing, terminal_service, target, client, protocol).await
+}
@@ -1,2 +1,3 @@
 // WebTransport传输模块 - 处理WebTransport连接和通信
+#[allow(clippy::module_inception)]
 pub mod webtransport;
@@ -2,7 +2,8 @@ use std::sync::Arc;
 use std::future::Future;
 use std::pin::Pin;
 use std::net::SocketAddr;
-use std::str::FromStr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use tokio::sync::mpsc;
 
@@ -12,22 +13,48 @@ use wtransport::Endpoint;
 use wtransport::ServerConfig;
 use wtransport::Identity;
 
-use crate::config::Config;
+use crate::audit::{query_value, ClientContext, TransportKind};
+use crate::config::{Config, WebTransportConfig};
+use crate::content_log;
+use crate::health::HealthRegistry;
+use crate::metrics::Metrics;
 use crate::protocol::adapter::ProtocolAdapter;
-use crate::pty::terminal_service::TerminalService;
+use crate::protocol::message::{self, ClientMessage, ServerMessage};
+use crate::pty::terminal_service::{ConnectionTarget, TerminalService};
+use crate::shutdown::ShutdownSignal;
+use crate::transport::listener;
+use crate::transport::tls::{self, CertificateDigest};
+
+// 自签名证书的重新生成间隔 - 浏览器要求serverCertificateHashes对应的证书有效期不超过14天
+const SELF_SIGNED_RENEW_INTERVAL: Duration = Duration::from_secs(13 * 24 * 60 * 60);
 
 // WebTransport适配器 - 实现ProtocolAdapter接口
 pub struct WebTransportAdapter {
     terminal_service: Arc<TerminalService>,
     config: Arc<Config>,
+    certificate_digest: CertificateDigest,
+    health: HealthRegistry,
+    shutdown: ShutdownSignal,
 }
 
 impl WebTransportAdapter {
+    // 健康检查中的适配器名称
+    pub const NAME: &'static str = "webtransport";
+    
     // 创建新的WebTransport适配器
-    pub fn new(terminal_service: Arc<TerminalService>, config: Arc<Config>) -> Self {
+    pub fn new(
+        terminal_service: Arc<TerminalService>,
+        config: Arc<Config>,
+        certificate_digest: CertificateDigest,
+        health: HealthRegistry,
+        shutdown: ShutdownSignal,
+    ) -> Self {
         Self {
             terminal_service,
             config,
+            certificate_digest,
+            health,
+            shutdown,
         }
     }
 }
@@ -37,35 +64,47 @@ impl ProtocolAdapter for WebTransportAdapter {
     fn start(&self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
         let terminal_service = self.terminal_service.clone();
         let port = self.config.webtransport.port;
+        let enable_datagrams = self.config.webtransport.enable_datagrams;
+        let webtransport_config = self.config.webtransport.clone();
+        let certificate_digest = self.certificate_digest.clone();
+        let shutdown = self.shutdown.clone();
+        let health = self.health.clone();
         
         Box::pin(async move {
-            log::info!("WebTransport server starting on https://localhost:{}", port);
+            let bind_address = listener::parse_bind_address(&webtransport_config.bind_address, port)?;
+            log::info!("WebTransport server starting on https://{}", bind_address);
             
-            // 参考wtransport 0.6.1官方示例创建服务器配置
-            let config = ServerConfig::builder()
-                .with_bind_address(SocketAddr::from_str(&format!("0.0.0.0:{}", port))?)
-                // 使用自签名证书，仅用于开发测试
-                .with_identity(Identity::self_signed(["localhost"]).unwrap())
-                // 添加更多的调试信息
-                .build();
+            let identity = load_identity(&webtransport_config).await?;
+            certificate_digest.set(*identity.certificate_chain().as_slice()[0].hash().as_ref());
             
-            let server = Endpoint::server(config)?;
+            let server = Arc::new(Endpoint::server(build_server_config(bind_address, identity))?);
             
-            log::info!("WebTransport server started successfully on https://localhost:{}", port);
+            // 证书热加载：PEM证书在文件变化时重新加载，自签名证书在过期前重新生成
+            spawn_certificate_reloader(server.clone(), bind_address, webtransport_config, certificate_digest);
+            
+            log::info!("WebTransport server started successfully on https://{}", bind_address);
+            health.set_ready(Self::NAME);
             log::info!("WebTransport server is ready to accept connections");
             log::info!("WebTransport server will handle requests to /webtransport/* paths");
             
             // 处理服务器连接
             loop {
                 log::debug!("WebTransport server waiting for incoming connections...");
-                let incoming_session = server.accept().await;
+                // 收到关闭信号后停止接受新连接
+                let incoming_session = tokio::select! {
+                    incoming_session = server.accept() => incoming_session,
+                    _ = shutdown.wait() => {
+                        log::info!("WebTransport server stopped");
+                        return Ok(());
+                    }
+                };
                 log::debug!("WebTransport server accepted incoming session");
                 let terminal_service = terminal_service.clone();
                 
                 // 为每个连接启动独立任务
                 tokio::spawn(async move {
                     log::debug!("Starting new task to handle WebTransport connection");
-                    if let Err(e) = handle_incoming_session(incoming_session, terminal_service).await {
+                    if let Err(e) = handle_incoming_session(incoming_session, terminal_service, enable_datagrams).await {
                         log::error!("WebTransport connection error: {}", e);
                     } else {
                         log::debug!("WebTransport connection handled successfully");
@@ -76,100 +115,289 @@ impl ProtocolAdapter for WebTransportAdapter {
     }
 }
 
+// 加载WebTransport证书 - 配置了PEM文件时从文件加载，否则生成自签名证书
+async fn load_identity(webtransport_config: &WebTransportConfig) -> anyhow::Result<Identity> {
+    match tls::configured_paths(&webtransport_config.cert_path, &webtransport_config.key_path) {
+        Some((cert_path, key_path)) => {
+            log::info!("Loading WebTransport certificate from {}", cert_path);
+            Ok(Identity::load_pemfiles(cert_path, key_path).await?)
+        },
+        None => {
+            // 自签名证书仅用于开发测试，浏览器需通过证书摘要信任
+            log::info!("Using self-signed WebTransport certificate");
+            Ok(Identity::self_signed(["localhost", "127.0.0.1", "::1"])?)
+        }
+    }
+}
+
+// 构建WebTransport服务器配置
+fn build_server_config(bind_address: SocketAddr, identity: Identity) -> ServerConfig {
+    ServerConfig::builder()
+        .with_bind_address(bind_address)
+        .with_identity(identity)
+        .build()
+}
+
+// 启动证书重新加载任务
+fn spawn_certificate_reloader(
+    server: Arc<Endpoint<wtransport::endpoint::endpoint_side::Server>>,
+    bind_address: SocketAddr,
+    webtransport_config: WebTransportConfig,
+    certificate_digest: CertificateDigest,
+) {
+    let (reload_tx, mut reload_rx) = mpsc::unbounded_channel::<()>();
+    
+    match tls::configured_paths(&webtransport_config.cert_path, &webtransport_config.key_path) {
+        Some((cert_path, key_path)) => {
+            tls::watch_files(vec![PathBuf::from(cert_path), PathBuf::from(key_path)], move || {
+                let _ = reload_tx.send(());
+            });
+        },
+        None => {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(SELF_SIGNED_RENEW_INTERVAL);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if reload_tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+    
+    tokio::spawn(async move {
+        while reload_rx.recv().await.is_some() {
+            let identity = match load_identity(&webtransport_config).await {
+                Ok(identity) => identity,
+                Err(e) => {
+                    log::error!("Failed to reload WebTransport certificate, keeping previous one: {}", e);
+                    continue;
+                }
+            };
+            
+            let digest = *identity.certificate_chain().as_slice()[0].hash().as_ref();
+            match server.reload_config(build_server_config(bind_address, identity), false) {
+                Ok(_) => {
+                    certificate_digest.set(digest);
+                    log::info!("Reloaded WebTransport certificate");
+                },
+                Err(e) => log::error!("Failed to apply reloaded WebTransport certificate: {}", e),
+            }
+        }
+    });
+}
+
 // 处理WebTransport传入会话
 async fn handle_incoming_session(
     incoming_session: IncomingSession,
     terminal_service: Arc<TerminalService>,
+    enable_datagrams: bool,
 ) -> anyhow::Result<()> {
     log::info!("Waiting for WebTransport session request...");
     
     // 等待会话请求
     let session_request = incoming_session.await?;
     
-    // 从URL路径获取session_id
+    // 从URL路径获取连接目标：/webtransport/{sessionId} 或 /webtransport/replay/{recordingId}
     let path = session_request.path();
-    let session_id = path
-        .split('/')
-        .last()
-        .ok_or(anyhow::anyhow!("Invalid WebTransport URL path"))?
-        .to_string();
+    let target = ConnectionTarget::from_path(path)
+        .ok_or(anyhow::anyhow!("Invalid WebTransport URL path"))?;
+    
+    log::info!("New WebTransport session: Authority: '{}', Path: '{}', Target: {:?}", 
+              session_request.authority(), path, target);
     
-    log::info!("New WebTransport session: Authority: '{}', Path: '{}', Session ID: {}", 
-              session_request.authority(), path, session_id);
+    // 用户取自 ?userId= 或 x-user-id 头，共享令牌取自 ?token= 或 x-share-token 头
+    let query = path.split_once('?').map(|(_, query)| query);
+    let user_id = query
+        .and_then(|query| query_value(query, "userId"))
+        .or_else(|| session_request.headers().get("x-user-id").cloned());
+    let client = ClientContext {
+        share_token: query
+            .and_then(|query| query_value(query, "token"))
+            .or_else(|| session_request.headers().get("x-share-token").cloned()),
+        ..ClientContext::new(TransportKind::WebTransport, Some(session_request.remote_address().ip().to_string()), user_id)
+    };
     
     // 接受连接
     let connection = session_request.accept().await?;
-    log::info!("WebTransport connection established for session {}", session_id);
+    log::info!("WebTransport connection established for {:?}", target);
     
     // 处理连接
-    handle_connection(connection, terminal_service, session_id).await
+    handle_connection(connection, terminal_service, target, client, enable_datagrams).await
 }
 
-// 处理WebTransport连接
+// 处理WebTransport连接 - 每个会话使用一条由客户端打开的长连接双向流
 async fn handle_connection(
     connection: wtransport::Connection,
     terminal_service: Arc<TerminalService>,
-    session_id: String,
+    target: ConnectionTarget,
+    mut client: ClientContext,
+    enable_datagrams: bool,
 ) -> anyhow::Result<()> {
+    // 等待客户端打开唯一的双向流，之后所有帧都在这条流上按序传输
+    let (mut send_stream, recv_stream) = connection.accept_bi().await?;
+    log::debug!("Accepted WebTransport bidirectional stream for {:?}", target);
+    
     // 创建终端输出通道
-    let (terminal_output_tx, terminal_output_rx) = mpsc::channel::<String>(100);
+    let (terminal_output_tx, terminal_output_rx) = mpsc::channel::<ServerMessage>(100);
+    
+    // 回复消息使用弱引用发送者，会话关闭后输出通道随之关闭，写任务据此结束连接
+    let reply_tx = terminal_output_tx.downgrade();
     
-    // 添加客户端发送者到会话
-    terminal_service.handle_terminal_connection(&session_id, terminal_output_tx.clone()).await?;
+    // 添加客户端发送者到会话，或启动回放；失败时（会话不存在、已终止或无权观看）先告知客户端再关闭
+    let session_id = match terminal_service.connect(target, terminal_output_tx, &mut client).await {
+        Ok(session_id) => session_id,
+        Err(e) => {
+            let frame = message::encode_frame(&ServerMessage::Error { message: e.to_string() })?;
+            let _ = send_stream.write_all(&frame).await;
+            let _ = send_stream.finish().await;
+            connection.close(0u32.into(), b"connection refused");
+            return Err(e);
+        }
+    };
+    let _attached = terminal_service.metrics().client_attached(client.transport);
     
-    // 启动两个任务：一个读取终端输出并发送到客户端，一个处理客户端输入
+    // 启动写入、读取和数据报三个任务，任一结束即关闭连接
     tokio::select! {
-        _ = handle_terminal_output(connection.clone(), terminal_output_rx) => {
-            log::debug!("WebTransport terminal output task completed");
+        result = write_frames(connection.clone(), send_stream, terminal_output_rx, terminal_service.metrics(), enable_datagrams) => {
+            log::debug!("WebTransport write task completed: {:?}", result);
+        }
+        result = read_frames(recv_stream, terminal_service.clone(), session_id.clone(), &client, reply_tx) => {
+            log::debug!("WebTransport read task completed: {:?}", result);
         }
-        _ = handle_client_input(connection, terminal_service, session_id.clone()) => {
-            log::debug!("WebTransport client input task completed");
+        result = receive_datagrams(connection.clone(), terminal_service.clone(), session_id.clone(), &client), if enable_datagrams => {
+            log::debug!("WebTransport datagram task completed: {:?}", result);
         }
     }
     
+    terminal_service.disconnect(&session_id, &client.client_id);
+    connection.close(0u32.into(), b"session closed");
     Ok(())
 }
 
-// 处理终端输出，发送到WebTransport客户端
-async fn handle_terminal_output(
+// 发送服务端消息 - 延迟敏感的消息优先走数据报，失败时回退到流
+async fn write_frames(
     connection: wtransport::Connection,
-    mut terminal_output_rx: mpsc::Receiver<String>,
+    mut send_stream: wtransport::SendStream,
+    mut terminal_output_rx: mpsc::Receiver<ServerMessage>,
+    metrics: &Metrics,
+    enable_datagrams: bool,
 ) -> anyhow::Result<()> {
-    while let Some(output) = terminal_output_rx.recv().await {
-        log::debug!("PTY -> WebTransport: {:?}", output);
+    while let Some(message) = terminal_output_rx.recv().await {
+        if let Some(payload) = content_log::server_message(&message) {
+            log::debug!("PTY -> WebTransport: {}", payload);
+        }
+        if let ServerMessage::Output { data } = &message {
+            metrics.bytes_sent(TransportKind::WebTransport, data.len());
+        }
+        
+        if enable_datagrams && message.is_latency_sensitive() && send_datagram(&connection, &message).is_ok() {
+            continue;
+        }
         
-        // 打开双向流发送数据
-        let mut stream = connection.open_bi().await?.await?;
-        stream.0.write_all(output.as_bytes()).await?;
+        let frame = message::encode_frame(&message)?;
+        send_stream.write_all(&frame).await?;
     }
     
     Ok(())
 }
 
-// 处理客户端输入，写入到终端
-async fn handle_client_input(
-    connection: wtransport::Connection,
+// 通过不可靠数据报发送消息
+fn send_datagram(connection: &wtransport::Connection, message: &ServerMessage) -> anyhow::Result<()> {
+    let payload = message.to_json_bytes()?;
+    match connection.max_datagram_size() {
+        Some(max_size) if payload.len() <= max_size => {
+            connection.send_datagram(payload)?;
+            Ok(())
+        },
+        _ => anyhow::bail!("Datagram not supported or payload too large"),
+    }
+}
+
+// 读取客户端帧，写入到终端
+async fn read_frames(
+    mut recv_stream: wtransport::RecvStream,
     terminal_service: Arc<TerminalService>,
     session_id: String,
+    client: &ClientContext,
+    reply_tx: mpsc::WeakSender<ServerMessage>,
 ) -> anyhow::Result<()> {
-    let mut buffer = vec![0; 4096].into_boxed_slice();
+    let mut header = [0u8; 4];
     
     loop {
-        // 接受双向流
-        let mut stream = connection.accept_bi().await?;
-        log::debug!("Accepted WebTransport bidirectional stream");
+        // 读取4字节长度前缀，流结束表示客户端关闭
+        if recv_stream.read_exact(&mut header).await.is_err() {
+            log::debug!("WebTransport stream closed for session {}", session_id);
+            return Ok(());
+        }
+        
+        let length = message::decode_frame_length(header)?;
+        let mut payload = vec![0u8; length];
+        recv_stream.read_exact(&mut payload).await?;
         
-        // 读取客户端数据
-        while let Some(bytes_read) = stream.1.read(&mut buffer).await? {
-            if bytes_read == 0 {
-                break;
+        // 无效的消息和处理失败只回复该客户端，帧格式或读取出错时才关闭连接
+        let reply = match ClientMessage::from_json_bytes(&payload) {
+            Ok(message) => {
+                if let Some(payload) = content_log::client_message(&message) {
+                    log::debug!("WebTransport -> PTY: {} (session: {})", payload, session_id);
+                }
+                match terminal_service.handle_client_message(&session_id, message, client).await {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        log::warn!("Failed to handle WebTransport message for session {}: {}", session_id, e);
+                        Some(ServerMessage::Error { message: e.to_string() })
+                    }
+                }
+            },
+            Err(e) => {
+                log::warn!("Invalid WebTransport message for session {}: {}", session_id, e);
+                Some(ServerMessage::Error { message: format!("Invalid message: {}", e) })
             }
-            
-            let input = String::from_utf8_lossy(&buffer[..bytes_read]).to_string();
-            log::debug!("WebTransport -> PTY: {:?} (session: {})", input, session_id);
-            
-            // 写入到终端
-            terminal_service.handle_terminal_input(&session_id, input).await?;
+        };
+        
+        // 回复消息通过输出通道发送，保证与终端输出的顺序一致
+        if let Some(reply) = reply {
+            let Some(reply_tx) = reply_tx.upgrade() else { return Ok(()) };
+            reply_tx.send(reply).await?;
         }
     }
-}
\ No newline at end of file
+}
+
+// 处理客户端数据报 - 仅接受延迟敏感的控制消息
+async fn receive_datagrams(
+    connection: wtransport::Connection,
+    terminal_service: Arc<TerminalService>,
+    session_id: String,
+    client: &ClientContext,
+) -> anyhow::Result<()> {
+    loop {
+        let datagram = connection.receive_datagram().await?;
+        
+        let message = match ClientMessage::from_json_bytes(&datagram.payload()) {
+            Ok(message) if message.is_latency_sensitive() => message,
+            Ok(_) => {
+                log::warn!("Ignoring non-control WebTransport datagram for session {}", session_id);
+                continue;
+            },
+            Err(e) => {
+                log::warn!("Invalid WebTransport datagram for session {}: {}", session_id, e);
+                continue;
+            }
+        };
+        
+        // 数据报请求的回复同样走数据报，处理失败只记录日志
+        let reply = match terminal_service.handle_client_message(&session_id, message, client).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                log::warn!("Failed to handle WebTransport datagram for session {}: {}", session_id, e);
+                continue;
+            }
+        };
+        if let Some(reply) = reply
+            && let Err(e) = send_datagram(&connection, &reply) {
+            log::debug!("Failed to send WebTransport datagram reply: {}", e);
+        }
+    }
+}